@@ -0,0 +1,23 @@
+-- Timings for the functions that cache per-statement state (SQLite
+-- auxiliary data): regexp() keeps the compiled pattern and hmac() the
+-- keyed state while that argument is constant.
+--
+--   cargo build --release
+--   sqlite3 -cmd '.load target/release/libgpkg_lib' :memory: < benches/functions.sql
+--
+-- Each pair runs the same work twice. The second query appends
+-- `substr(s, 1, 0)`, an empty string that SQLite cannot treat as a
+-- constant, so the argument is rebuilt for every row and no auxiliary data
+-- is kept: regexp() falls back to its LRU cache of compiled patterns and
+-- hmac() re-keys for every row.
+CREATE TEMP TABLE rows AS
+  WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 200000)
+  SELECT i, printf('item-%d-%x', i, i * 2654435761) AS s FROM n;
+
+.timer on
+SELECT count(*) FROM rows WHERE s REGEXP '^item-[0-9]*7-[0-9a-f]+$';
+SELECT count(*) FROM rows WHERE s REGEXP '^item-[0-9]*7-[0-9a-f]+$' || substr(s, 1, 0);
+
+SELECT sum(length(hmac('sha256', 'secret', s))) FROM rows;
+SELECT sum(length(hmac('sha256', 'secret' || substr(s, 1, 0), s))) FROM rows;
+.timer off
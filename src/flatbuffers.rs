@@ -0,0 +1,242 @@
+//! Minimal FlatBuffers writer for the export functions.
+//!
+//! Objects are laid out front to back: each table is followed by the
+//! strings, vectors and tables it refers to, so every `uoffset` points
+//! forward as the format requires. Alignment is relative to the start of
+//! the buffer.
+
+/// A field value; slots that are not set are absent and read as defaults.
+pub enum Field {
+    U8(u8),
+    Bool(bool),
+    U16(u16),
+    I32(i32),
+    U64(u64),
+    String(String),
+    Bytes(Vec<u8>),
+    U32s(Vec<u32>),
+    F64s(Vec<f64>),
+    Table(Table),
+    Tables(Vec<Table>),
+}
+
+#[derive(Default)]
+pub struct Table {
+    fields: Vec<(u16, Field)>,
+}
+
+impl Table {
+    pub fn new() -> Table {
+        Table::default()
+    }
+
+    /// Sets field number `slot` (its position in the schema).
+    pub fn set(mut self, slot: u16, field: Field) -> Table {
+        self.fields.push((slot, field));
+        self
+    }
+
+    /// Serializes the table as the root of a buffer.
+    pub fn finish(&self) -> Vec<u8> {
+        let mut buf = vec![0; 4];
+        let root = write_table(&mut buf, self);
+        patch(&mut buf, 0, root);
+        buf
+    }
+}
+
+fn align(buf: &mut Vec<u8>, alignment: usize) {
+    buf.resize(buf.len().next_multiple_of(alignment), 0);
+}
+
+/// Points the `uoffset` at `at` to `target`.
+fn patch(buf: &mut [u8], at: usize, target: usize) {
+    buf[at..at + 4].copy_from_slice(&((target - at) as u32).to_le_bytes());
+}
+
+/// Starts a vector of `len` elements of `size` bytes and returns the
+/// position of its first element.
+fn vector_start(buf: &mut Vec<u8>, len: usize, size: usize) -> usize {
+    align(buf, 4);
+    while !(buf.len() + 4).is_multiple_of(size.max(4)) {
+        buf.push(0);
+    }
+    buf.extend_from_slice(&(len as u32).to_le_bytes());
+    buf.len()
+}
+
+fn write_table(buf: &mut Vec<u8>, table: &Table) -> usize {
+    let slots = table.fields.iter().map(|(slot, _)| *slot as usize + 1).max().unwrap_or(0);
+
+    // Inline layout: the soffset to the vtable, then each field aligned to
+    // its size; references are 4-byte placeholders patched below.
+    let mut offsets = vec![0u16; slots];
+    let mut inline = Vec::with_capacity(table.fields.len());
+    let mut size: usize = 4;
+    for (slot, field) in &table.fields {
+        let width = match field {
+            Field::U8(_) | Field::Bool(_) => 1,
+            Field::U16(_) => 2,
+            Field::I32(_) => 4,
+            Field::U64(_) => 8,
+            _ => 4,
+        };
+        size = size.next_multiple_of(width);
+        offsets[*slot as usize] = size as u16;
+        inline.push((size, width));
+        size += width;
+    }
+
+    align(buf, 2);
+    let vtable = buf.len();
+    buf.extend_from_slice(&(4 + 2 * slots as u16).to_le_bytes());
+    buf.extend_from_slice(&(size as u16).to_le_bytes());
+    for offset in &offsets {
+        buf.extend_from_slice(&offset.to_le_bytes());
+    }
+    align(buf, 8);
+    let start = buf.len();
+    buf.resize(start + size, 0);
+    buf[start..start + 4].copy_from_slice(&((start - vtable) as i32).to_le_bytes());
+
+    for ((_, field), (offset, _)) in table.fields.iter().zip(&inline) {
+        let at = start + offset;
+        match field {
+            Field::U8(v) => buf[at] = *v,
+            Field::Bool(v) => buf[at] = *v as u8,
+            Field::U16(v) => buf[at..at + 2].copy_from_slice(&v.to_le_bytes()),
+            Field::I32(v) => buf[at..at + 4].copy_from_slice(&v.to_le_bytes()),
+            Field::U64(v) => buf[at..at + 8].copy_from_slice(&v.to_le_bytes()),
+            Field::String(s) => {
+                let target = vector_start(buf, s.len(), 1) - 4;
+                buf.extend_from_slice(s.as_bytes());
+                buf.push(0);
+                patch(buf, at, target);
+            }
+            Field::Bytes(bytes) => {
+                let target = vector_start(buf, bytes.len(), 1) - 4;
+                buf.extend_from_slice(bytes);
+                patch(buf, at, target);
+            }
+            Field::U32s(values) => {
+                let target = vector_start(buf, values.len(), 4) - 4;
+                values.iter().for_each(|v| buf.extend_from_slice(&v.to_le_bytes()));
+                patch(buf, at, target);
+            }
+            Field::F64s(values) => {
+                let target = vector_start(buf, values.len(), 8) - 4;
+                values.iter().for_each(|v| buf.extend_from_slice(&v.to_le_bytes()));
+                patch(buf, at, target);
+            }
+            Field::Table(child) => {
+                let target = write_table(buf, child);
+                patch(buf, at, target);
+            }
+            Field::Tables(children) => {
+                let elements = vector_start(buf, children.len(), 4);
+                patch(buf, at, elements - 4);
+                buf.resize(elements + 4 * children.len(), 0);
+                for (i, child) in children.iter().enumerate() {
+                    let target = write_table(buf, child);
+                    patch(buf, elements + 4 * i, target);
+                }
+            }
+        }
+    }
+    start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(buf: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(buf[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(buf: &[u8], at: usize) -> usize {
+        u32::from_le_bytes(buf[at..at + 4].try_into().unwrap()) as usize
+    }
+
+    /// Follows the `uoffset` at `at`.
+    fn deref(buf: &[u8], at: usize) -> usize {
+        at + u32_at(buf, at)
+    }
+
+    /// The position of field `slot` of the table at `table`, as readers
+    /// find it through the vtable.
+    fn field(buf: &[u8], table: usize, slot: usize) -> Option<usize> {
+        let vtable = table - i32::from_le_bytes(buf[table..table + 4].try_into().unwrap()) as usize;
+        let entry = 4 + 2 * slot;
+        if entry >= u16_at(buf, vtable) as usize {
+            return None;
+        }
+        Some(u16_at(buf, vtable + entry) as usize).filter(|&o| o != 0).map(|o| table + o)
+    }
+
+    /// The elements of the vector the field at `at` refers to.
+    fn vector(buf: &[u8], at: usize) -> (usize, usize) {
+        let target = deref(buf, at);
+        (target + 4, u32_at(buf, target))
+    }
+
+    #[test]
+    fn scalars_and_vectors() {
+        let child = Table::new().set(0, Field::I32(-7));
+        let buf = Table::new()
+            .set(0, Field::U8(200))
+            .set(1, Field::Bool(true))
+            .set(2, Field::U16(0xBEEF))
+            .set(4, Field::U64(u64::MAX - 1))
+            .set(5, Field::String("héllo".into()))
+            .set(6, Field::Bytes(vec![1, 2, 3]))
+            .set(7, Field::U32s(vec![10, 20]))
+            .set(8, Field::F64s(vec![1.5, -2.5]))
+            .set(9, Field::Table(child))
+            .set(10, Field::Tables(vec![Table::new().set(1, Field::U8(1)), Table::new().set(1, Field::U8(2))]))
+            .finish();
+        let root = deref(&buf, 0);
+
+        assert_eq!(buf[field(&buf, root, 0).unwrap()], 200);
+        assert_eq!(buf[field(&buf, root, 1).unwrap()], 1);
+        assert_eq!(u16_at(&buf, field(&buf, root, 2).unwrap()), 0xBEEF);
+        assert_eq!(field(&buf, root, 3), None);
+        assert_eq!(field(&buf, root, 11), None);
+        let at = field(&buf, root, 4).unwrap();
+        assert_eq!(at % 8, 0);
+        assert_eq!(u64::from_le_bytes(buf[at..at + 8].try_into().unwrap()), u64::MAX - 1);
+
+        let (start, len) = vector(&buf, field(&buf, root, 5).unwrap());
+        assert_eq!(&buf[start..start + len], "héllo".as_bytes());
+        assert_eq!(buf[start + len], 0);
+        let (start, len) = vector(&buf, field(&buf, root, 6).unwrap());
+        assert_eq!(&buf[start..start + len], [1, 2, 3]);
+        let (start, len) = vector(&buf, field(&buf, root, 7).unwrap());
+        assert_eq!((start % 4, len, u32_at(&buf, start), u32_at(&buf, start + 4)), (0, 2, 10, 20));
+        let (start, len) = vector(&buf, field(&buf, root, 8).unwrap());
+        assert_eq!((start % 8, len), (0, 2));
+        assert_eq!(f64::from_le_bytes(buf[start + 8..start + 16].try_into().unwrap()), -2.5);
+
+        let child = deref(&buf, field(&buf, root, 9).unwrap());
+        let at = field(&buf, child, 0).unwrap();
+        assert_eq!(i32::from_le_bytes(buf[at..at + 4].try_into().unwrap()), -7);
+        let (start, len) = vector(&buf, field(&buf, root, 10).unwrap());
+        assert_eq!(len, 2);
+        for i in 0..len {
+            let element = deref(&buf, start + 4 * i);
+            assert_eq!(field(&buf, element, 0), None);
+            assert_eq!(buf[field(&buf, element, 1).unwrap()], i as u8 + 1);
+        }
+    }
+
+    #[test]
+    fn empty_table() {
+        let buf = Table::new().finish();
+        let root = deref(&buf, 0);
+        assert_eq!(field(&buf, root, 0), None);
+        let buf = Table::new().set(0, Field::Tables(Vec::new())).set(1, Field::String(String::new())).finish();
+        let root = deref(&buf, 0);
+        assert_eq!(vector(&buf, field(&buf, root, 0).unwrap()).1, 0);
+        assert_eq!(vector(&buf, field(&buf, root, 1).unwrap()).1, 0);
+    }
+}
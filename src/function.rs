@@ -0,0 +1,252 @@
+//! Scalar SQL functions implemented as plain Rust `fn`s.
+//!
+//! Every function is registered with the same C callback; the Rust
+//! implementation travels as the function's user data.
+use crate::error::{Error, Result};
+use libsqlite3_sys as ffi;
+use rusqlite::Connection;
+use std::any::Any;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::slice;
+
+pub type ScalarFn = fn(&Context, &Args) -> Result<Value>;
+
+/// A function result.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Integer(value as i64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Real(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Text(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Text(value.to_string())
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(value: Vec<u8>) -> Self {
+        Value::Blob(value)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+/// The invocation context of a function call.
+pub struct Context(*mut ffi::sqlite3_context);
+
+impl Context {
+    /// The connection the function was invoked on.
+    pub fn connection(&self) -> Result<Connection> {
+        // from_handle does not take ownership; dropping it leaves the db open.
+        unsafe { Ok(Connection::from_handle(ffi::sqlite3_context_db_handle(self.0))?) }
+    }
+
+    /// A value derived from argument `arg`, such as a compiled pattern,
+    /// built by `make` on the first call and reused by later calls of the
+    /// same statement while the argument stays constant (SQLite auxiliary
+    /// data). SQLite drops it when the argument changes or the statement
+    /// ends.
+    pub fn aux<T: 'static>(&self, arg: usize, make: impl FnOnce() -> Result<T>) -> Result<Rc<T>> {
+        let arg = arg as c_int;
+        unsafe {
+            let cached = ffi::sqlite3_get_auxdata(self.0, arg) as *const Box<dyn Any>;
+            if let Some(value) = cached.as_ref().and_then(|b| b.downcast_ref::<Rc<T>>()) {
+                return Ok(value.clone());
+            }
+            let value = Rc::new(make()?);
+            let boxed: Box<Box<dyn Any>> = Box::new(Box::new(value.clone()));
+            ffi::sqlite3_set_auxdata(self.0, arg, Box::into_raw(boxed) as *mut c_void, Some(free_aux));
+            Ok(value)
+        }
+    }
+}
+
+unsafe extern "C" fn free_aux(data: *mut c_void) {
+    drop(unsafe { Box::from_raw(data as *mut Box<dyn Any>) });
+}
+
+/// The arguments of a function call.
+pub struct Args<'a>(&'a [*mut ffi::sqlite3_value]);
+
+impl Args<'_> {
+    fn value(&self, i: usize) -> Option<*mut ffi::sqlite3_value> {
+        let value = *self.0.get(i)?;
+        if unsafe { ffi::sqlite3_value_type(value) } == ffi::SQLITE_NULL {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    /// Text argument `i`, which must not be NULL.
+    pub fn text(&self, i: usize) -> Result<String> {
+        self.opt_text(i).ok_or_else(|| missing(i))
+    }
+
+    pub fn opt_text(&self, i: usize) -> Option<String> {
+        let value = self.value(i)?;
+        unsafe {
+            let text = ffi::sqlite3_value_text(value);
+            let len = ffi::sqlite3_value_bytes(value) as usize;
+            let bytes = if text.is_null() { &[][..] } else { slice::from_raw_parts(text, len) };
+            Some(String::from_utf8_lossy(bytes).into_owned())
+        }
+    }
+
+    /// Integer argument `i`, which must not be NULL.
+    pub fn int(&self, i: usize) -> Result<i64> {
+        self.opt_int(i).ok_or_else(|| missing(i))
+    }
+
+    pub fn opt_int(&self, i: usize) -> Option<i64> {
+        let value = self.value(i)?;
+        Some(unsafe { ffi::sqlite3_value_int64(value) })
+    }
+
+    pub fn double(&self, i: usize) -> Result<f64> {
+        self.opt_double(i).ok_or_else(|| missing(i))
+    }
+
+    pub fn opt_double(&self, i: usize) -> Option<f64> {
+        let value = self.value(i)?;
+        Some(unsafe { ffi::sqlite3_value_double(value) })
+    }
+
+    pub fn opt_blob(&self, i: usize) -> Option<&[u8]> {
+        let value = self.value(i)?;
+        unsafe {
+            let blob = ffi::sqlite3_value_blob(value) as *const u8;
+            let len = ffi::sqlite3_value_bytes(value) as usize;
+            Some(if blob.is_null() { &[][..] } else { slice::from_raw_parts(blob, len) })
+        }
+    }
+}
+
+fn missing(i: usize) -> Error {
+    Error::new(format!("argument {} must not be NULL", i + 1))
+}
+
+/// An entry of a function table: SQL name, number of arguments, flags such
+/// as `SQLITE_DETERMINISTIC`, and implementation. A name may appear once
+/// per argument count.
+pub type Definition = (&'static str, c_int, c_int, ScalarFn);
+
+/// Registers every function of `functions` on `conn`.
+pub fn register(conn: &Connection, functions: &[Definition]) -> Result<()> {
+    for &(name, n_arg, flags, f) in functions {
+        let rc = unsafe { create_scalar(conn.handle(), name, n_arg, flags, f) };
+        if rc != ffi::SQLITE_OK {
+            return Err(Error::new(format!("cannot register {name}/{n_arg}: {}", ffi::Error::new(rc))));
+        }
+    }
+    Ok(())
+}
+
+/// Registers `f` as the scalar SQL function `name`.
+///
+/// # Safety
+///
+/// `db` must be a valid, open database handle.
+unsafe fn create_scalar(
+    db: *mut ffi::sqlite3,
+    name: &str,
+    n_arg: c_int,
+    flags: c_int,
+    f: ScalarFn,
+) -> c_int {
+    let name = CString::new(name).unwrap();
+    unsafe {
+        ffi::sqlite3_create_function_v2(
+            db,
+            name.as_ptr(),
+            n_arg,
+            ffi::SQLITE_UTF8 | flags,
+            f as *mut c_void,
+            Some(dispatch),
+            None,
+            None,
+            None,
+        )
+    }
+}
+
+unsafe extern "C" fn dispatch(
+    context: *mut ffi::sqlite3_context,
+    argc: c_int,
+    argv: *mut *mut ffi::sqlite3_value,
+) {
+    let (f, args) = unsafe {
+        let f: ScalarFn = std::mem::transmute(ffi::sqlite3_user_data(context));
+        let args = if argc == 0 { &[][..] } else { slice::from_raw_parts(argv, argc as usize) };
+        (f, args)
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(&Context(context), &Args(args))))
+        .unwrap_or_else(|_| Err(Error::new("internal error: function panicked")));
+    unsafe {
+        match result {
+            Ok(value) => set_result(context, value),
+            Err(err) => {
+                let msg = CString::new(err.to_string().replace('\0', "")).unwrap();
+                ffi::sqlite3_result_error(context, msg.as_ptr(), -1);
+            }
+        }
+    }
+}
+
+unsafe fn set_result(context: *mut ffi::sqlite3_context, value: Value) {
+    unsafe {
+        match value {
+            Value::Null => ffi::sqlite3_result_null(context),
+            Value::Integer(i) => ffi::sqlite3_result_int64(context, i),
+            Value::Real(r) => ffi::sqlite3_result_double(context, r),
+            Value::Text(s) => ffi::sqlite3_result_text64(
+                context,
+                s.as_ptr() as *const c_char,
+                s.len() as u64,
+                ffi::SQLITE_TRANSIENT(),
+                ffi::SQLITE_UTF8 as u8,
+            ),
+            Value::Blob(b) => ffi::sqlite3_result_blob64(
+                context,
+                b.as_ptr() as *const c_void,
+                b.len() as u64,
+                ffi::SQLITE_TRANSIENT(),
+            ),
+        }
+    }
+}
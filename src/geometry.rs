@@ -0,0 +1,200 @@
+//! Geometry model and the encodings GeoPackages store it in.
+//!
+//! A GeoPackage geometry BLOB is a GPB header (magic, flags, srs_id and an
+//! optional envelope, see [`gpb`]) followed by ISO WKB (see [`wkb`]).
+pub mod geohash;
+pub mod geojson;
+pub mod gpb;
+pub mod kml;
+pub mod wkb;
+pub mod wkt;
+
+/// Which optional ordinates the coordinates of a geometry carry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Dims {
+    pub z: bool,
+    pub m: bool,
+}
+
+impl Dims {
+    pub const XY: Dims = Dims { z: false, m: false };
+
+    /// The WKT dimension qualifier, e.g. ` Z` or ` ZM`.
+    pub fn suffix(self) -> &'static str {
+        match (self.z, self.m) {
+            (false, false) => "",
+            (true, false) => " Z",
+            (false, true) => " M",
+            (true, true) => " ZM",
+        }
+    }
+}
+
+/// A position; `z` and `m` are only meaningful when the geometry's [`Dims`] say so.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Coord {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub m: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)] // GeometryCollection is the OGC name
+pub enum Geometry {
+    /// `None` is the empty point.
+    Point(Option<Coord>),
+    LineString(Vec<Coord>),
+    Polygon(Vec<Vec<Coord>>),
+    MultiPoint(Vec<Coord>),
+    MultiLineString(Vec<Vec<Coord>>),
+    MultiPolygon(Vec<Vec<Vec<Coord>>>),
+    GeometryCollection(Vec<Geometry>),
+}
+
+/// Bounding box of a geometry; `z` and `m` hold `(min, max)` ranges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Envelope {
+    pub min_x: f64,
+    pub max_x: f64,
+    pub min_y: f64,
+    pub max_y: f64,
+    pub z: Option<(f64, f64)>,
+    pub m: Option<(f64, f64)>,
+}
+
+impl Geometry {
+    /// The WKB geometry type code (1-7).
+    pub fn type_code(&self) -> u32 {
+        match self {
+            Geometry::Point(_) => 1,
+            Geometry::LineString(_) => 2,
+            Geometry::Polygon(_) => 3,
+            Geometry::MultiPoint(_) => 4,
+            Geometry::MultiLineString(_) => 5,
+            Geometry::MultiPolygon(_) => 6,
+            Geometry::GeometryCollection(_) => 7,
+        }
+    }
+
+    /// The upper-case type name used by WKT and `gpkg_geometry_columns`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Geometry::Point(_) => "POINT",
+            Geometry::LineString(_) => "LINESTRING",
+            Geometry::Polygon(_) => "POLYGON",
+            Geometry::MultiPoint(_) => "MULTIPOINT",
+            Geometry::MultiLineString(_) => "MULTILINESTRING",
+            Geometry::MultiPolygon(_) => "MULTIPOLYGON",
+            Geometry::GeometryCollection(_) => "GEOMETRYCOLLECTION",
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_points() == 0
+    }
+
+    /// Calls `f` for every coordinate of the geometry.
+    pub fn for_each_coord(&self, f: &mut impl FnMut(&Coord)) {
+        match self {
+            Geometry::Point(point) => point.iter().for_each(f),
+            Geometry::LineString(line) | Geometry::MultiPoint(line) => line.iter().for_each(f),
+            Geometry::Polygon(rings) | Geometry::MultiLineString(rings) => {
+                rings.iter().flatten().for_each(f)
+            }
+            Geometry::MultiPolygon(polygons) => polygons.iter().flatten().flatten().for_each(f),
+            Geometry::GeometryCollection(geometries) => {
+                geometries.iter().for_each(|g| g.for_each_coord(f))
+            }
+        }
+    }
+
+    /// Calls `f` for every coordinate of the geometry, allowing it to move them.
+    pub fn for_each_coord_mut(&mut self, f: &mut impl FnMut(&mut Coord)) {
+        match self {
+            Geometry::Point(point) => point.iter_mut().for_each(f),
+            Geometry::LineString(line) | Geometry::MultiPoint(line) => line.iter_mut().for_each(f),
+            Geometry::Polygon(rings) | Geometry::MultiLineString(rings) => {
+                rings.iter_mut().flatten().for_each(f)
+            }
+            Geometry::MultiPolygon(polygons) => polygons.iter_mut().flatten().flatten().for_each(f),
+            Geometry::GeometryCollection(geometries) => {
+                geometries.iter_mut().for_each(|g| g.for_each_coord_mut(f))
+            }
+        }
+    }
+
+    pub fn num_points(&self) -> usize {
+        let mut count = 0;
+        self.for_each_coord(&mut |_| count += 1);
+        count
+    }
+
+    /// The envelope of all coordinates, or `None` for an empty geometry.
+    pub fn envelope(&self, dims: Dims) -> Option<Envelope> {
+        let mut envelope: Option<Envelope> = None;
+        self.for_each_coord(&mut |c| match &mut envelope {
+            None => {
+                envelope = Some(Envelope {
+                    min_x: c.x,
+                    max_x: c.x,
+                    min_y: c.y,
+                    max_y: c.y,
+                    z: dims.z.then_some((c.z, c.z)),
+                    m: dims.m.then_some((c.m, c.m)),
+                })
+            }
+            Some(e) => {
+                e.min_x = e.min_x.min(c.x);
+                e.max_x = e.max_x.max(c.x);
+                e.min_y = e.min_y.min(c.y);
+                e.max_y = e.max_y.max(c.y);
+                if let Some((min, max)) = &mut e.z {
+                    *min = min.min(c.z);
+                    *max = max.max(c.z);
+                }
+                if let Some((min, max)) = &mut e.m {
+                    *min = min.min(c.m);
+                    *max = max.max(c.m);
+                }
+            }
+        });
+        envelope
+    }
+}
+
+/// Formats an ordinate for WKT and GeoJSON: `1` rather than `1.0`, and
+/// exponent notation instead of hundreds of digits for extreme values.
+pub fn number(value: f64) -> String {
+    let abs = value.abs();
+    if abs == 0.0 || (1e-5..1e16).contains(&abs) {
+        format!("{value}")
+    } else {
+        format!("{value:?}")
+    }
+}
+
+/// Renders a GeoPackage geometry BLOB as `wkt`, `geojson`, `hex` or `summary`.
+pub fn format(blob: &[u8], format: &str) -> crate::error::Result<String> {
+    if format.eq_ignore_ascii_case("hex") {
+        return Ok(blob.iter().map(|b| format!("{b:02X}")).collect());
+    }
+    let geometry = gpb::decode(blob)?;
+    match format.to_ascii_lowercase().as_str() {
+        "wkt" => Ok(wkt::write(&geometry.geometry, geometry.dims)),
+        "geojson" => Ok(geojson::write(&geometry.geometry, geometry.dims)),
+        "summary" => Ok(summary(&geometry.geometry, geometry.dims)),
+        other => Err(crate::error::Error::new(format!(
+            "unknown geometry format '{other}', expected wkt, geojson, hex or summary"
+        ))),
+    }
+}
+
+/// A short description such as `POLYGON(… 124 pts)`; points are shown in full.
+pub fn summary(geometry: &Geometry, dims: Dims) -> String {
+    match geometry {
+        Geometry::Point(_) => wkt::write(geometry, dims),
+        _ if geometry.is_empty() => format!("{}{} EMPTY", geometry.type_name(), dims.suffix()),
+        _ => format!("{}{}(… {} pts)", geometry.type_name(), dims.suffix(), geometry.num_points()),
+    }
+}
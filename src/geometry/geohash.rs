@@ -0,0 +1,99 @@
+//! Geohash cells: base-32 strings that interleave longitude and latitude
+//! bits, so nearby points share prefixes.
+use crate::error::{Error, Result};
+
+const ALPHABET: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+/// Longest hash produced; 12 characters resolve to a few centimetres.
+pub const MAX_PRECISION: usize = 12;
+
+/// The `precision`-character geohash of the cell containing (lon, lat).
+pub fn encode(lon: f64, lat: f64, precision: usize) -> String {
+    let (mut lon_range, mut lat_range) = ((-180.0, 180.0), (-90.0, 90.0));
+    let mut hash = String::with_capacity(precision);
+    let mut even = true;
+    for _ in 0..precision {
+        let mut index = 0;
+        for _ in 0..5 {
+            let (range, value) = if even { (&mut lon_range, lon) } else { (&mut lat_range, lat) };
+            let mid = (range.0 + range.1) / 2.0;
+            index <<= 1;
+            if value >= mid {
+                index |= 1;
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+        }
+        hash.push(ALPHABET[index] as char);
+    }
+    hash
+}
+
+/// The cell of `hash` as `[min_lon, min_lat, max_lon, max_lat]`.
+pub fn decode(hash: &str) -> Result<[f64; 4]> {
+    if hash.is_empty() {
+        return Err(Error::new("empty geohash"));
+    }
+    let (mut lon_range, mut lat_range) = ((-180.0, 180.0), (-90.0, 90.0));
+    let mut even = true;
+    for c in hash.chars() {
+        let index = ALPHABET
+            .iter()
+            .position(|&a| a as char == c.to_ascii_lowercase())
+            .ok_or_else(|| Error::new(format!("invalid geohash character {c:?} in {hash}")))?;
+        for bit in (0..5).rev() {
+            let range = if even { &mut lon_range } else { &mut lat_range };
+            let mid = (range.0 + range.1) / 2.0;
+            if index >> bit & 1 == 1 {
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+        }
+    }
+    Ok([lon_range.0, lat_range.0, lon_range.1, lat_range.1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_hashes() {
+        assert_eq!(encode(-5.6, 42.6, 5), "ezs42");
+        assert_eq!(encode(10.40744, 57.64911, 11), "u4pruydqqvj");
+        assert_eq!(encode(0.0, 0.0, 4), "s000");
+        assert_eq!(encode(-180.0, -90.0, 3), "000");
+        assert_eq!(encode(180.0, 90.0, 3), "zzz");
+        assert_eq!(encode(1.0, 2.0, 0), "");
+    }
+
+    #[test]
+    fn decode_contains_point() {
+        for (lon, lat) in [(-5.6, 42.6), (10.40744, 57.64911), (-122.4194, 37.7749), (151.2, -33.9)] {
+            for precision in 1..=MAX_PRECISION {
+                let hash = encode(lon, lat, precision);
+                let [min_lon, min_lat, max_lon, max_lat] = decode(&hash).unwrap();
+                assert!((min_lon..=max_lon).contains(&lon) && (min_lat..=max_lat).contains(&lat), "{hash}");
+                assert_eq!(encode((min_lon + max_lon) / 2.0, (min_lat + max_lat) / 2.0, precision), hash);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_cells() {
+        assert_eq!(decode("ezs42").unwrap(), [-5.625, 42.5830078125, -5.5810546875, 42.626953125]);
+        assert_eq!(decode("EZS42").unwrap(), decode("ezs42").unwrap());
+        assert_eq!(decode("s").unwrap(), [0.0, 0.0, 45.0, 45.0]);
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        assert!(decode("").is_err());
+        for hash in ["a", "ezs4i", "ezl", "ezo", "ez 42", "ezs42é"] {
+            assert!(decode(hash).is_err(), "{hash}");
+        }
+    }
+}
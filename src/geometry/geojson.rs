@@ -0,0 +1,197 @@
+//! GeoJSON geometry objects (RFC 7946).
+use super::{Coord, Dims, Geometry, number};
+use crate::error::{Error, Result};
+use crate::json::Json;
+use std::fmt::Write;
+
+/// Writes `geometry` as a GeoJSON geometry object. M values are dropped,
+/// since GeoJSON positions only carry x, y and an optional z.
+pub fn write(geometry: &Geometry, dims: Dims) -> String {
+    let mut out = String::new();
+    write_geometry(&mut out, geometry, dims);
+    out
+}
+
+fn write_geometry(out: &mut String, geometry: &Geometry, dims: Dims) {
+    let name = match geometry {
+        Geometry::Point(_) => "Point",
+        Geometry::LineString(_) => "LineString",
+        Geometry::Polygon(_) => "Polygon",
+        Geometry::MultiPoint(_) => "MultiPoint",
+        Geometry::MultiLineString(_) => "MultiLineString",
+        Geometry::MultiPolygon(_) => "MultiPolygon",
+        Geometry::GeometryCollection(_) => "GeometryCollection",
+    };
+    write!(out, "{{\"type\":\"{name}\",").unwrap();
+    match geometry {
+        Geometry::GeometryCollection(geometries) => {
+            out.push_str("\"geometries\":");
+            list(out, geometries, |out, geometry| write_geometry(out, geometry, dims));
+        }
+        _ => {
+            out.push_str("\"coordinates\":");
+            match geometry {
+                Geometry::Point(Some(point)) => position(out, point, dims),
+                Geometry::Point(None) => out.push_str("[]"),
+                Geometry::LineString(line) | Geometry::MultiPoint(line) => positions(out, line, dims),
+                Geometry::Polygon(rings) | Geometry::MultiLineString(rings) => {
+                    list(out, rings, |out, ring| positions(out, ring, dims))
+                }
+                Geometry::MultiPolygon(polygons) => list(out, polygons, |out, rings| {
+                    list(out, rings, |out, ring| positions(out, ring, dims))
+                }),
+                Geometry::GeometryCollection(_) => unreachable!(),
+            }
+        }
+    }
+    out.push('}');
+}
+
+fn list<T>(out: &mut String, items: &[T], mut item: impl FnMut(&mut String, &T)) {
+    out.push('[');
+    for (i, value) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        item(out, value);
+    }
+    out.push(']');
+}
+
+fn positions(out: &mut String, coords: &[Coord], dims: Dims) {
+    list(out, coords, |out, c| position(out, c, dims));
+}
+
+fn position(out: &mut String, c: &Coord, dims: Dims) {
+    write!(out, "[{},{}", number(c.x), number(c.y)).unwrap();
+    if dims.z {
+        write!(out, ",{}", number(c.z)).unwrap();
+    }
+    out.push(']');
+}
+
+/// Reads a GeoJSON geometry object. Positions with a third element make the
+/// whole geometry `Z`.
+pub fn read(json: &Json) -> Result<(Geometry, Dims)> {
+    let mut dims = Dims::XY;
+    let geometry = read_geometry(json, &mut dims, 0)?;
+    Ok((geometry, dims))
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(format!("invalid GeoJSON geometry: {msg}"))
+}
+
+fn array(json: &Json) -> Result<&[Json]> {
+    json.as_array().ok_or_else(|| invalid("coordinates are not an array"))
+}
+
+fn read_geometry(json: &Json, dims: &mut Dims, depth: usize) -> Result<Geometry> {
+    let kind = json.get("type").and_then(Json::as_str).ok_or_else(|| invalid("missing type"))?;
+    if kind == "GeometryCollection" {
+        if depth > 32 {
+            return Err(invalid("collections nested too deep"));
+        }
+        let members = json
+            .get("geometries")
+            .and_then(Json::as_array)
+            .ok_or_else(|| invalid("missing geometries"))?;
+        let geometries = members
+            .iter()
+            .map(|g| read_geometry(g, dims, depth + 1))
+            .collect::<Result<Vec<_>>>()?;
+        return Ok(Geometry::GeometryCollection(geometries));
+    }
+
+    let coordinates = json.get("coordinates").ok_or_else(|| invalid("missing coordinates"))?;
+    let position = |json: &Json, dims: &mut Dims| -> Result<Coord> {
+        let values = json.as_array().ok_or_else(|| invalid("position is not an array"))?;
+        let mut ordinates = values.iter().map(|v| v.as_f64().ok_or_else(|| invalid("non-numeric ordinate")));
+        let x = ordinates.next().ok_or_else(|| invalid("position needs x and y"))??;
+        let y = ordinates.next().ok_or_else(|| invalid("position needs x and y"))??;
+        let z = ordinates.next().transpose()?;
+        dims.z |= z.is_some();
+        Ok(Coord { x, y, z: z.unwrap_or(0.0), m: 0.0 })
+    };
+    let positions = |json: &Json, dims: &mut Dims| -> Result<Vec<Coord>> {
+        array(json)?.iter().map(|p| position(p, dims)).collect()
+    };
+    let rings = |json: &Json, dims: &mut Dims| -> Result<Vec<Vec<Coord>>> {
+        array(json)?.iter().map(|r| positions(r, dims)).collect()
+    };
+
+    Ok(match kind {
+        "Point" if array(coordinates)?.is_empty() => Geometry::Point(None),
+        "Point" => Geometry::Point(Some(position(coordinates, dims)?)),
+        "LineString" => Geometry::LineString(positions(coordinates, dims)?),
+        "Polygon" => Geometry::Polygon(rings(coordinates, dims)?),
+        "MultiPoint" => Geometry::MultiPoint(positions(coordinates, dims)?),
+        "MultiLineString" => Geometry::MultiLineString(rings(coordinates, dims)?),
+        "MultiPolygon" => Geometry::MultiPolygon(
+            array(coordinates)?.iter().map(|p| rings(p, dims)).collect::<Result<_>>()?,
+        ),
+        other => return Err(invalid(&format!("unknown type {other}"))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+
+    fn round_trip(text: &str) {
+        let (geometry, dims) = read(&json::parse(text).unwrap()).unwrap();
+        assert_eq!(write(&geometry, dims), text);
+    }
+
+    #[test]
+    fn round_trips() {
+        for text in [
+            r#"{"type":"Point","coordinates":[1,2]}"#,
+            r#"{"type":"Point","coordinates":[1,2,3]}"#,
+            r#"{"type":"Point","coordinates":[]}"#,
+            r#"{"type":"LineString","coordinates":[[0,0],[1.5,-2.25]]}"#,
+            r#"{"type":"Polygon","coordinates":[[[0,0],[4,0],[4,4],[0,0]]]}"#,
+            r#"{"type":"MultiPoint","coordinates":[[1,2],[3,4]]}"#,
+            r#"{"type":"MultiLineString","coordinates":[[[0,0],[1,1]],[[2,2],[3,3]]]}"#,
+            r#"{"type":"MultiPolygon","coordinates":[[[[0,0],[1,0],[1,1],[0,0]]]]}"#,
+            r#"{"type":"GeometryCollection","geometries":[{"type":"Point","coordinates":[1,2]}]}"#,
+            r#"{"type":"GeometryCollection","geometries":[]}"#,
+        ] {
+            round_trip(text);
+        }
+    }
+
+    #[test]
+    fn m_is_dropped() {
+        let point = Geometry::Point(Some(Coord { x: 1.0, y: 2.0, z: 3.0, m: 4.0 }));
+        assert_eq!(write(&point, Dims { z: false, m: true }), r#"{"type":"Point","coordinates":[1,2]}"#);
+        assert_eq!(write(&point, Dims { z: true, m: true }), r#"{"type":"Point","coordinates":[1,2,3]}"#);
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        for text in [
+            r#"{}"#,
+            r#"{"type":"Circle","coordinates":[1,2]}"#,
+            r#"{"type":"Point"}"#,
+            r#"{"type":"Point","coordinates":[1]}"#,
+            r#"{"type":"Point","coordinates":[1,"2"]}"#,
+            r#"{"type":"Point","coordinates":1}"#,
+            r#"{"type":"LineString","coordinates":[1,2]}"#,
+            r#"{"type":"GeometryCollection"}"#,
+        ] {
+            assert!(read(&json::parse(text).unwrap()).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |levels: usize| {
+            let open = r#"{"type":"GeometryCollection","geometries":["#.repeat(levels);
+            format!(r#"{open}{{"type":"Point","coordinates":[1,2]}}{}"#, "]}".repeat(levels))
+        };
+        assert!(read(&json::parse(&nested(33)).unwrap()).is_ok());
+        assert!(read(&json::parse(&nested(34)).unwrap()).is_err());
+    }
+}
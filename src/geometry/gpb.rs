@@ -0,0 +1,235 @@
+//! GeoPackage binary: the header stored in front of the WKB in geometry columns.
+use super::{Dims, Envelope, Geometry, wkb};
+use crate::error::{Error, Result};
+
+const MAGIC: [u8; 2] = *b"GP";
+
+/// A decoded geometry BLOB.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoPackageGeometry {
+    pub srs_id: i32,
+    /// The envelope stored in the header, if any.
+    pub envelope: Option<Envelope>,
+    pub geometry: Geometry,
+    pub dims: Dims,
+}
+
+/// The fixed part of the header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+    pub srs_id: i32,
+    pub envelope: Option<Envelope>,
+    pub empty: bool,
+    /// Length of the header including the envelope, i.e. the WKB offset.
+    pub len: usize,
+}
+
+/// Parses the header of a geometry BLOB without touching the WKB.
+pub fn header(blob: &[u8]) -> Result<Header> {
+    if blob.len() < 8 || blob[..2] != MAGIC {
+        return Err(Error::new("not a GeoPackage geometry"));
+    }
+    if blob[2] != 0 {
+        return Err(Error::new(format!("unsupported GeoPackage binary version {}", blob[2])));
+    }
+    let flags = blob[3];
+    if flags & 0b0010_0000 != 0 {
+        return Err(Error::new("extended GeoPackage geometries are not supported"));
+    }
+    let little_endian = flags & 1 == 1;
+    let read_f64 = |offset: usize| -> f64 {
+        let bytes: [u8; 8] = blob[offset..offset + 8].try_into().unwrap();
+        if little_endian { f64::from_le_bytes(bytes) } else { f64::from_be_bytes(bytes) }
+    };
+    let srs_bytes: [u8; 4] = blob[4..8].try_into().unwrap();
+    let srs_id = if little_endian { i32::from_le_bytes(srs_bytes) } else { i32::from_be_bytes(srs_bytes) };
+
+    let (doubles, has_z, has_m) = match (flags >> 1) & 0b111 {
+        0 => (0, false, false),
+        1 => (4, false, false),
+        2 => (6, true, false),
+        3 => (6, false, true),
+        4 => (8, true, true),
+        other => return Err(Error::new(format!("invalid envelope indicator {other}"))),
+    };
+    let len = 8 + doubles * 8;
+    if blob.len() < len {
+        return Err(Error::new("truncated GeoPackage geometry header"));
+    }
+    let envelope = (doubles > 0).then(|| {
+        // Stored as minx, maxx, miny, maxy, then the z and m ranges if present.
+        let v: Vec<f64> = (0..doubles).map(|i| read_f64(8 + i * 8)).collect();
+        Envelope {
+            min_x: v[0],
+            max_x: v[1],
+            min_y: v[2],
+            max_y: v[3],
+            z: has_z.then(|| (v[4], v[5])),
+            m: has_m.then(|| if has_z { (v[6], v[7]) } else { (v[4], v[5]) }),
+        }
+    });
+    Ok(Header { srs_id, envelope, empty: flags & 0b0001_0000 != 0, len })
+}
+
+/// Decodes a geometry BLOB.
+pub fn decode(blob: &[u8]) -> Result<GeoPackageGeometry> {
+    let header = header(blob)?;
+    let (geometry, dims) = wkb::read(&blob[header.len..])?;
+    Ok(GeoPackageGeometry { srs_id: header.srs_id, envelope: header.envelope, geometry, dims })
+}
+
+/// Encodes `geometry` as a little-endian GeoPackage BLOB with an envelope.
+pub fn encode(geometry: &Geometry, dims: Dims, srs_id: i32) -> Vec<u8> {
+    let envelope = geometry.envelope(dims);
+    // Envelope indicator: 0 none, 1 xy, 2 xyz (m ranges are not stored).
+    let indicator: u8 = match envelope {
+        None => 0,
+        Some(Envelope { z: Some(_), .. }) => 2,
+        Some(_) => 1,
+    };
+    let empty = if envelope.is_none() { 0b0001_0000 } else { 0 };
+    let mut out = Vec::with_capacity(64);
+    out.extend_from_slice(&MAGIC);
+    out.push(0);
+    out.push(1 | (indicator << 1) | empty);
+    out.extend_from_slice(&srs_id.to_le_bytes());
+    if let Some(e) = envelope {
+        for v in [e.min_x, e.max_x, e.min_y, e.max_y] {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        if let Some((min, max)) = e.z {
+            out.extend_from_slice(&min.to_le_bytes());
+            out.extend_from_slice(&max.to_le_bytes());
+        }
+    }
+    out.extend_from_slice(&wkb::write(geometry, dims));
+    out
+}
+
+/// The envelope of a geometry BLOB: taken from the header when present,
+/// otherwise computed from the WKB. `None` for empty geometries.
+pub fn envelope(blob: &[u8]) -> Result<Option<Envelope>> {
+    let header = header(blob)?;
+    if header.empty {
+        return Ok(None);
+    }
+    if header.envelope.is_some() {
+        return Ok(header.envelope);
+    }
+    let (geometry, dims) = wkb::read(&blob[header.len..])?;
+    Ok(geometry.envelope(dims))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Coord;
+
+    fn point() -> Geometry {
+        Geometry::Point(Some(Coord { x: 1.0, y: 2.0, z: 3.0, m: 4.0 }))
+    }
+
+    /// A BLOB with envelope indicator `indicator` holding `doubles`, in the
+    /// given byte order, followed by the WKB of `point()`.
+    fn blob(indicator: u8, doubles: &[f64], little_endian: bool, empty: bool) -> Vec<u8> {
+        let mut out = b"GP\0".to_vec();
+        out.push(u8::from(little_endian) | indicator << 1 | if empty { 0b0001_0000 } else { 0 });
+        let srs_id = 4326i32;
+        out.extend_from_slice(&if little_endian { srs_id.to_le_bytes() } else { srs_id.to_be_bytes() });
+        for v in doubles {
+            out.extend_from_slice(&if little_endian { v.to_le_bytes() } else { v.to_be_bytes() });
+        }
+        out.extend_from_slice(&wkb::write(&point(), Dims::XY));
+        out
+    }
+
+    #[test]
+    fn envelope_variants() {
+        let xy = Envelope { min_x: 1.0, max_x: 2.0, min_y: 3.0, max_y: 4.0, z: None, m: None };
+        let cases = [
+            (0, vec![], None),
+            (1, vec![1.0, 2.0, 3.0, 4.0], Some(xy)),
+            (2, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], Some(Envelope { z: Some((5.0, 6.0)), ..xy })),
+            (3, vec![1.0, 2.0, 3.0, 4.0, 7.0, 8.0], Some(Envelope { m: Some((7.0, 8.0)), ..xy })),
+            (
+                4,
+                vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0],
+                Some(Envelope { z: Some((5.0, 6.0)), m: Some((7.0, 8.0)), ..xy }),
+            ),
+        ];
+        for (indicator, doubles, envelope) in cases {
+            for little_endian in [true, false] {
+                let blob = blob(indicator, &doubles, little_endian, false);
+                let header = header(&blob).unwrap();
+                assert_eq!(header.srs_id, 4326);
+                assert_eq!(header.envelope, envelope);
+                assert_eq!(header.len, 8 + 8 * doubles.len());
+                assert!(!header.empty);
+                let decoded = decode(&blob).unwrap();
+                assert_eq!(decoded.geometry, Geometry::Point(Some(Coord { x: 1.0, y: 2.0, z: 0.0, m: 0.0 })));
+            }
+        }
+    }
+
+    #[test]
+    fn envelope_falls_back_to_wkb() {
+        let computed = envelope(&blob(0, &[], true, false)).unwrap().unwrap();
+        assert_eq!((computed.min_x, computed.max_x, computed.min_y, computed.max_y), (1.0, 1.0, 2.0, 2.0));
+        let stored = envelope(&blob(1, &[0.0, 9.0, 0.0, 9.0], true, false)).unwrap().unwrap();
+        assert_eq!(stored.max_x, 9.0);
+    }
+
+    #[test]
+    fn empty_flag() {
+        assert!(header(&blob(0, &[], true, true)).unwrap().empty);
+        assert_eq!(envelope(&blob(1, &[1.0, 2.0, 3.0, 4.0], true, true)).unwrap(), None);
+
+        let encoded = encode(&Geometry::Point(None), Dims::XY, 4326);
+        let header = header(&encoded).unwrap();
+        assert!(header.empty);
+        assert_eq!(header.envelope, None);
+        assert_eq!(decode(&encoded).unwrap().geometry, Geometry::Point(None));
+        assert_eq!(envelope(&encoded).unwrap(), None);
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let line = Geometry::LineString(vec![
+            Coord { x: 0.0, y: -1.0, z: 5.0, m: 0.0 },
+            Coord { x: 3.0, y: 2.0, z: -5.0, m: 0.0 },
+        ]);
+        let dims = Dims { z: true, m: false };
+        let encoded = encode(&line, dims, 3857);
+        assert_eq!(encoded[3] >> 1 & 0b111, 2);
+        let decoded = decode(&encoded).unwrap();
+        assert_eq!(decoded, GeoPackageGeometry { srs_id: 3857, envelope: line.envelope(dims), geometry: line, dims });
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        let valid = blob(1, &[1.0, 2.0, 3.0, 4.0], true, false);
+        for len in 0..valid.len() {
+            assert!(decode(&valid[..len]).is_err(), "cut to {len} bytes");
+        }
+        for len in 0..40 {
+            assert!(header(&valid[..len]).is_err(), "cut to {len} bytes");
+        }
+
+        let mut bad = valid.clone();
+        bad[0] = b'X';
+        assert!(header(&bad).is_err());
+        let mut bad = valid.clone();
+        bad[2] = 1;
+        assert!(header(&bad).is_err());
+        // Extended geometries.
+        let mut bad = valid.clone();
+        bad[3] |= 0b0010_0000;
+        assert!(header(&bad).is_err());
+        // Envelope indicators 5 to 7 are reserved.
+        for indicator in 5..8 {
+            let mut bad = valid.clone();
+            bad[3] = 1 | indicator << 1;
+            assert!(header(&bad).is_err());
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! KML geometry elements (OGC 07-147r2). Coordinates must already be
+//! longitude/latitude; M values are dropped.
+use super::{Coord, Dims, Geometry, number};
+
+/// Writes `geometry` as a KML geometry element; multi-geometries and
+/// collections become `MultiGeometry`.
+pub fn write(geometry: &Geometry, dims: Dims) -> String {
+    let mut out = String::new();
+    write_geometry(&mut out, geometry, dims);
+    out
+}
+
+fn write_geometry(out: &mut String, geometry: &Geometry, dims: Dims) {
+    match geometry {
+        Geometry::Point(None) => out.push_str("<MultiGeometry/>"),
+        Geometry::Point(Some(point)) => {
+            out.push_str("<Point>");
+            coordinates(out, std::slice::from_ref(point), dims);
+            out.push_str("</Point>");
+        }
+        Geometry::LineString(line) => {
+            out.push_str("<LineString>");
+            coordinates(out, line, dims);
+            out.push_str("</LineString>");
+        }
+        Geometry::Polygon(rings) => polygon(out, rings, dims),
+        Geometry::MultiPoint(points) => multi(out, points, |out, point| {
+            write_geometry(out, &Geometry::Point(Some(*point)), dims)
+        }),
+        Geometry::MultiLineString(lines) => multi(out, lines, |out, line| {
+            out.push_str("<LineString>");
+            coordinates(out, line, dims);
+            out.push_str("</LineString>");
+        }),
+        Geometry::MultiPolygon(polygons) => multi(out, polygons, |out, rings| polygon(out, rings, dims)),
+        Geometry::GeometryCollection(geometries) => {
+            multi(out, geometries, |out, geometry| write_geometry(out, geometry, dims))
+        }
+    }
+}
+
+fn multi<T>(out: &mut String, items: &[T], mut write: impl FnMut(&mut String, &T)) {
+    out.push_str("<MultiGeometry>");
+    for item in items {
+        write(out, item);
+    }
+    out.push_str("</MultiGeometry>");
+}
+
+fn polygon(out: &mut String, rings: &[Vec<Coord>], dims: Dims) {
+    out.push_str("<Polygon>");
+    for (i, ring) in rings.iter().enumerate() {
+        let boundary = if i == 0 { "outerBoundaryIs" } else { "innerBoundaryIs" };
+        out.push_str(&format!("<{boundary}><LinearRing>"));
+        coordinates(out, ring, dims);
+        out.push_str(&format!("</LinearRing></{boundary}>"));
+    }
+    out.push_str("</Polygon>");
+}
+
+fn coordinates(out: &mut String, coords: &[Coord], dims: Dims) {
+    out.push_str("<coordinates>");
+    for (i, c) in coords.iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        out.push_str(&number(c.x));
+        out.push(',');
+        out.push_str(&number(c.y));
+        if dims.z {
+            out.push(',');
+            out.push_str(&number(c.z));
+        }
+    }
+    out.push_str("</coordinates>");
+}
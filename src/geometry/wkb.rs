@@ -0,0 +1,431 @@
+//! ISO well-known binary, and the PostGIS extended variant (EWKB) that
+//! flags z, m and an embedded SRID in the type code.
+use super::{Coord, Dims, Geometry};
+use crate::error::{Error, Result};
+
+const EWKB_Z: u32 = 0x8000_0000;
+const EWKB_M: u32 = 0x4000_0000;
+const EWKB_SRID: u32 = 0x2000_0000;
+
+/// Writes `geometry` as little-endian ISO WKB.
+pub fn write(geometry: &Geometry, dims: Dims) -> Vec<u8> {
+    let mut writer = Writer { out: Vec::new(), ewkb: false };
+    writer.geometry(geometry, dims, None);
+    writer.out
+}
+
+/// Writes `geometry` as little-endian EWKB with `srid` in the outermost
+/// header.
+pub fn write_ewkb(geometry: &Geometry, dims: Dims, srid: i32) -> Vec<u8> {
+    let mut writer = Writer { out: Vec::new(), ewkb: true };
+    writer.geometry(geometry, dims, Some(srid));
+    writer.out
+}
+
+struct Writer {
+    out: Vec<u8>,
+    ewkb: bool,
+}
+
+impl Writer {
+    fn header(&mut self, type_code: u32, dims: Dims, srid: Option<i32>) {
+        let code = if self.ewkb {
+            type_code
+                | if dims.z { EWKB_Z } else { 0 }
+                | if dims.m { EWKB_M } else { 0 }
+                | if srid.is_some() { EWKB_SRID } else { 0 }
+        } else {
+            type_code
+                + match (dims.z, dims.m) {
+                    (false, false) => 0,
+                    (true, false) => 1000,
+                    (false, true) => 2000,
+                    (true, true) => 3000,
+                }
+        };
+        self.out.push(1);
+        self.out.extend_from_slice(&code.to_le_bytes());
+        if let Some(srid) = srid {
+            self.out.extend_from_slice(&srid.to_le_bytes());
+        }
+    }
+
+    fn count(&mut self, n: usize) {
+        self.out.extend_from_slice(&(n as u32).to_le_bytes());
+    }
+
+    fn point(&mut self, point: &Coord, dims: Dims, srid: Option<i32>) {
+        self.header(1, dims, srid);
+        self.coord(point, dims);
+    }
+
+    fn line(&mut self, line: &[Coord], dims: Dims, srid: Option<i32>) {
+        self.header(2, dims, srid);
+        self.coords(line, dims);
+    }
+
+    fn polygon(&mut self, rings: &[Vec<Coord>], dims: Dims, srid: Option<i32>) {
+        self.header(3, dims, srid);
+        self.count(rings.len());
+        rings.iter().for_each(|ring| self.coords(ring, dims));
+    }
+
+    /// Writes `geometry`; only the outermost header carries `srid`.
+    fn geometry(&mut self, geometry: &Geometry, dims: Dims, srid: Option<i32>) {
+        match geometry {
+            // The empty point is encoded with NaN ordinates.
+            Geometry::Point(point) => self.point(&point.unwrap_or(NAN_COORD), dims, srid),
+            Geometry::LineString(line) => self.line(line, dims, srid),
+            Geometry::Polygon(rings) => self.polygon(rings, dims, srid),
+            Geometry::MultiPoint(points) => {
+                self.header(4, dims, srid);
+                self.count(points.len());
+                points.iter().for_each(|p| self.point(p, dims, None));
+            }
+            Geometry::MultiLineString(lines) => {
+                self.header(5, dims, srid);
+                self.count(lines.len());
+                lines.iter().for_each(|l| self.line(l, dims, None));
+            }
+            Geometry::MultiPolygon(polygons) => {
+                self.header(6, dims, srid);
+                self.count(polygons.len());
+                polygons.iter().for_each(|p| self.polygon(p, dims, None));
+            }
+            Geometry::GeometryCollection(geometries) => {
+                self.header(7, dims, srid);
+                self.count(geometries.len());
+                geometries.iter().for_each(|g| self.geometry(g, dims, None));
+            }
+        }
+    }
+
+    fn coords(&mut self, coords: &[Coord], dims: Dims) {
+        self.count(coords.len());
+        coords.iter().for_each(|c| self.coord(c, dims));
+    }
+
+    fn coord(&mut self, c: &Coord, dims: Dims) {
+        self.out.extend_from_slice(&c.x.to_le_bytes());
+        self.out.extend_from_slice(&c.y.to_le_bytes());
+        if dims.z {
+            self.out.extend_from_slice(&c.z.to_le_bytes());
+        }
+        if dims.m {
+            self.out.extend_from_slice(&c.m.to_le_bytes());
+        }
+    }
+}
+
+const NAN_COORD: Coord = Coord { x: f64::NAN, y: f64::NAN, z: f64::NAN, m: f64::NAN };
+
+/// Reads one WKB geometry from the start of `bytes`; EWKB is accepted and
+/// its SRID ignored.
+pub fn read(bytes: &[u8]) -> Result<(Geometry, Dims)> {
+    read_ewkb(bytes).map(|(geometry, dims, _)| (geometry, dims))
+}
+
+/// Reads one ISO WKB or EWKB geometry from the start of `bytes`, with the
+/// SRID of the outermost EWKB header if it has one.
+pub fn read_ewkb(bytes: &[u8]) -> Result<(Geometry, Dims, Option<i32>)> {
+    let mut reader = Reader { bytes, pos: 0, little_endian: true, srid: None };
+    let (geometry, dims) = reader.geometry(0)?;
+    Ok((geometry, dims, reader.srid))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    little_endian: bool,
+    srid: Option<i32>,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let end = self.pos + N;
+        let chunk = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or_else(|| Error::new("invalid WKB: unexpected end of data"))?;
+        self.pos = end;
+        Ok(chunk.try_into().unwrap())
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take::<4>()?;
+        Ok(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        let bytes = self.take::<8>()?;
+        Ok(if self.little_endian { f64::from_le_bytes(bytes) } else { f64::from_be_bytes(bytes) })
+    }
+
+    /// Reads a count and guards against lengths the remaining data cannot hold.
+    fn count(&mut self, min_item_size: usize) -> Result<usize> {
+        let count = self.u32()? as usize;
+        if count.saturating_mul(min_item_size) > self.bytes.len() - self.pos {
+            return Err(Error::new("invalid WKB: element count exceeds data"));
+        }
+        Ok(count)
+    }
+
+    fn coord(&mut self, dims: Dims) -> Result<Coord> {
+        let x = self.f64()?;
+        let y = self.f64()?;
+        let z = if dims.z { self.f64()? } else { 0.0 };
+        let m = if dims.m { self.f64()? } else { 0.0 };
+        Ok(Coord { x, y, z, m })
+    }
+
+    fn coords(&mut self, dims: Dims) -> Result<Vec<Coord>> {
+        let n = self.count(16)?;
+        (0..n).map(|_| self.coord(dims)).collect()
+    }
+
+    fn rings(&mut self, dims: Dims) -> Result<Vec<Vec<Coord>>> {
+        let n = self.count(4)?;
+        (0..n).map(|_| self.coords(dims)).collect()
+    }
+
+    fn header(&mut self) -> Result<(u32, Dims)> {
+        self.little_endian = match self.take::<1>()?[0] {
+            0 => false,
+            1 => true,
+            other => return Err(Error::new(format!("invalid WKB: byte order {other}"))),
+        };
+        let code = self.u32()?;
+        if code & (EWKB_Z | EWKB_M | EWKB_SRID) != 0 {
+            if code & EWKB_SRID != 0 {
+                let srid = self.u32()? as i32;
+                self.srid.get_or_insert(srid);
+            }
+            let dims = Dims { z: code & EWKB_Z != 0, m: code & EWKB_M != 0 };
+            return Ok((code & 0x0fff_ffff, dims));
+        }
+        let dims = match code / 1000 {
+            0 => Dims::XY,
+            1 => Dims { z: true, m: false },
+            2 => Dims { z: false, m: true },
+            3 => Dims { z: true, m: true },
+            _ => return Err(Error::new(format!("invalid WKB: geometry type {code}"))),
+        };
+        Ok((code % 1000, dims))
+    }
+
+    /// Reads a member of a multi-geometry, which must be of type `expected`.
+    fn member(&mut self, expected: u32, depth: usize) -> Result<Geometry> {
+        let (geometry, _) = self.geometry(depth + 1)?;
+        if geometry.type_code() != expected {
+            return Err(Error::new(format!(
+                "invalid WKB: unexpected {} in multi-geometry",
+                geometry.type_name()
+            )));
+        }
+        Ok(geometry)
+    }
+
+    /// Reads a geometry nested `depth` levels inside multi-geometries and
+    /// collections; the limit keeps crafted input from exhausting the stack.
+    fn geometry(&mut self, depth: usize) -> Result<(Geometry, Dims)> {
+        if depth > 32 {
+            return Err(Error::new("invalid WKB: collections nested too deep"));
+        }
+        let (code, dims) = self.header()?;
+        let geometry = match code {
+            1 => {
+                let c = self.coord(dims)?;
+                Geometry::Point((!(c.x.is_nan() && c.y.is_nan())).then_some(c))
+            }
+            2 => Geometry::LineString(self.coords(dims)?),
+            3 => Geometry::Polygon(self.rings(dims)?),
+            4 => {
+                let n = self.count(21)?;
+                let mut points = Vec::with_capacity(n);
+                for _ in 0..n {
+                    // A MultiPoint holds coordinates only, so an empty member
+                    // cannot be kept; refuse it rather than drop it.
+                    let Geometry::Point(Some(c)) = self.member(1, depth)? else {
+                        return Err(Error::new("invalid WKB: empty point in a MULTIPOINT is not supported"));
+                    };
+                    points.push(c);
+                }
+                Geometry::MultiPoint(points)
+            }
+            5 => {
+                let n = self.count(9)?;
+                let mut lines = Vec::with_capacity(n);
+                for _ in 0..n {
+                    if let Geometry::LineString(line) = self.member(2, depth)? {
+                        lines.push(line);
+                    }
+                }
+                Geometry::MultiLineString(lines)
+            }
+            6 => {
+                let n = self.count(9)?;
+                let mut polygons = Vec::with_capacity(n);
+                for _ in 0..n {
+                    if let Geometry::Polygon(rings) = self.member(3, depth)? {
+                        polygons.push(rings);
+                    }
+                }
+                Geometry::MultiPolygon(polygons)
+            }
+            7 => {
+                let n = self.count(9)?;
+                let mut geometries = Vec::with_capacity(n);
+                for _ in 0..n {
+                    geometries.push(self.geometry(depth + 1)?.0);
+                }
+                Geometry::GeometryCollection(geometries)
+            }
+            _ => return Err(Error::new(format!("invalid WKB: geometry type {code}"))),
+        };
+        Ok((geometry, dims))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(x: f64, y: f64, z: f64, m: f64) -> Coord {
+        Coord { x, y, z, m }
+    }
+
+    fn samples() -> Vec<Geometry> {
+        let line = vec![c(1.0, 2.0, 3.0, 4.0), c(5.0, 6.0, 7.0, 8.0)];
+        let ring = vec![c(0.0, 0.0, 1.0, 2.0), c(4.0, 0.0, 1.0, 2.0), c(4.0, 4.0, 1.0, 2.0), c(0.0, 0.0, 1.0, 2.0)];
+        vec![
+            Geometry::Point(Some(c(1.5, -2.5, 3.0, 4.0))),
+            Geometry::Point(None),
+            Geometry::LineString(line.clone()),
+            Geometry::LineString(Vec::new()),
+            Geometry::Polygon(vec![ring.clone(), ring.clone()]),
+            Geometry::MultiPoint(line.clone()),
+            Geometry::MultiLineString(vec![line.clone(), line.clone()]),
+            Geometry::MultiPolygon(vec![vec![ring.clone()], vec![ring.clone()]]),
+            Geometry::GeometryCollection(vec![
+                Geometry::Point(Some(c(1.0, 1.0, 1.0, 1.0))),
+                Geometry::GeometryCollection(vec![Geometry::LineString(line)]),
+            ]),
+            Geometry::GeometryCollection(Vec::new()),
+        ]
+    }
+
+    /// `geometry` as read back with `dims`: ordinates it lacks become zero.
+    fn strip(geometry: &Geometry, dims: Dims) -> Geometry {
+        let mut geometry = geometry.clone();
+        geometry.for_each_coord_mut(&mut |c| {
+            c.z = if dims.z { c.z } else { 0.0 };
+            c.m = if dims.m { c.m } else { 0.0 };
+        });
+        geometry
+    }
+
+    const ALL_DIMS: [Dims; 4] = [
+        Dims::XY,
+        Dims { z: true, m: false },
+        Dims { z: false, m: true },
+        Dims { z: true, m: true },
+    ];
+
+    #[test]
+    fn round_trip() {
+        for dims in ALL_DIMS {
+            for geometry in samples() {
+                let expected = strip(&geometry, dims);
+                assert_eq!(read(&write(&geometry, dims)).unwrap(), (expected.clone(), dims));
+                assert_eq!(read_ewkb(&write_ewkb(&geometry, dims, 4326)).unwrap(), (expected, dims, Some(4326)));
+            }
+        }
+    }
+
+    #[test]
+    fn iso_type_codes() {
+        let point = Geometry::Point(Some(c(1.0, 2.0, 3.0, 4.0)));
+        for (dims, code) in ALL_DIMS.into_iter().zip([1u32, 1001, 2001, 3001]) {
+            assert_eq!(write(&point, dims)[1..5], code.to_le_bytes());
+        }
+        let ewkb = write_ewkb(&point, Dims { z: true, m: false }, 3857);
+        assert_eq!(ewkb[1..5], (1 | EWKB_Z | EWKB_SRID).to_le_bytes());
+        assert_eq!(ewkb[5..9], 3857i32.to_le_bytes());
+    }
+
+    #[test]
+    fn big_endian() {
+        let mut bytes = vec![0];
+        bytes.extend_from_slice(&2u32.to_be_bytes());
+        bytes.extend_from_slice(&1u32.to_be_bytes());
+        bytes.extend_from_slice(&1.0f64.to_be_bytes());
+        bytes.extend_from_slice(&2.0f64.to_be_bytes());
+        assert_eq!(read(&bytes).unwrap(), (Geometry::LineString(vec![c(1.0, 2.0, 0.0, 0.0)]), Dims::XY));
+    }
+
+    #[test]
+    fn srid_of_outermost_header() {
+        let geometry = Geometry::MultiPoint(vec![c(1.0, 2.0, 0.0, 0.0)]);
+        let (_, _, srid) = read_ewkb(&write_ewkb(&geometry, Dims::XY, 2154)).unwrap();
+        assert_eq!(srid, Some(2154));
+        assert_eq!(read_ewkb(&write(&geometry, Dims::XY)).unwrap().2, None);
+    }
+
+    #[test]
+    fn truncated_input() {
+        for dims in ALL_DIMS {
+            for geometry in samples() {
+                let bytes = write(&geometry, dims);
+                for len in 0..bytes.len() {
+                    assert!(read(&bytes[..len]).is_err(), "{geometry:?} cut to {len} bytes");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hostile_input() {
+        // Byte order and type code out of range.
+        assert!(read(&[2, 1, 0, 0, 0]).is_err());
+        assert!(read(&[1, 8, 0, 0, 0]).is_err());
+        assert!(read(&[1, 0xb9, 0x0f, 0, 0]).is_err());
+        // A count far beyond the data must fail before allocating.
+        let mut bytes = vec![1];
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read(&bytes).is_err());
+        // Multi-geometries only hold their own member type.
+        let mut bytes = vec![1];
+        bytes.extend_from_slice(&4u32.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&write(&Geometry::LineString(Vec::new()), Dims::XY));
+        assert!(read(&bytes).is_err());
+    }
+
+    #[test]
+    fn empty_multipoint_member() {
+        let mut bytes = vec![1];
+        bytes.extend_from_slice(&4u32.to_le_bytes());
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&write(&Geometry::Point(Some(c(1.0, 2.0, 0.0, 0.0))), Dims::XY));
+        bytes.extend_from_slice(&write(&Geometry::Point(None), Dims::XY));
+        assert!(read(&bytes).unwrap_err().to_string().contains("empty point"));
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |levels: usize| {
+            let mut bytes = Vec::new();
+            for _ in 0..levels {
+                bytes.push(1);
+                bytes.extend_from_slice(&7u32.to_le_bytes());
+                bytes.extend_from_slice(&1u32.to_le_bytes());
+            }
+            bytes.extend_from_slice(&write(&Geometry::Point(Some(c(1.0, 2.0, 0.0, 0.0))), Dims::XY));
+            bytes
+        };
+        assert!(read(&nested(32)).is_ok());
+        assert!(read(&nested(33)).is_err());
+        assert!(read(&nested(100_000)).is_err());
+    }
+}
@@ -0,0 +1,368 @@
+//! Well-known text.
+use super::{Coord, Dims, Geometry, number};
+use crate::error::{Error, Result};
+use std::fmt::Write;
+
+/// Writes `geometry` as ISO WKT, e.g. `POINT Z (1 2 3)`.
+pub fn write(geometry: &Geometry, dims: Dims) -> String {
+    let mut out = String::new();
+    write_geometry(&mut out, geometry, dims);
+    out
+}
+
+fn write_geometry(out: &mut String, geometry: &Geometry, dims: Dims) {
+    out.push_str(geometry.type_name());
+    out.push_str(dims.suffix());
+    if geometry.is_empty() {
+        out.push_str(" EMPTY");
+        return;
+    }
+    out.push(' ');
+    match geometry {
+        Geometry::Point(point) => {
+            out.push('(');
+            coord(out, point.as_ref().unwrap(), dims);
+            out.push(')');
+        }
+        Geometry::LineString(line) => coords(out, line, dims),
+        Geometry::Polygon(rings) => list(out, rings, |out, ring| coords(out, ring, dims)),
+        Geometry::MultiPoint(points) => list(out, points, |out, point| {
+            out.push('(');
+            coord(out, point, dims);
+            out.push(')');
+        }),
+        Geometry::MultiLineString(lines) => list(out, lines, |out, line| coords(out, line, dims)),
+        Geometry::MultiPolygon(polygons) => list(out, polygons, |out, rings| {
+            list(out, rings, |out, ring| coords(out, ring, dims))
+        }),
+        Geometry::GeometryCollection(geometries) => {
+            list(out, geometries, |out, geometry| write_geometry(out, geometry, dims))
+        }
+    }
+}
+
+fn list<T>(out: &mut String, items: &[T], mut item: impl FnMut(&mut String, &T)) {
+    out.push('(');
+    for (i, value) in items.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        item(out, value);
+    }
+    out.push(')');
+}
+
+fn coords(out: &mut String, coords: &[Coord], dims: Dims) {
+    list(out, coords, |out, c| coord(out, c, dims));
+}
+
+fn coord(out: &mut String, c: &Coord, dims: Dims) {
+    write!(out, "{} {}", number(c.x), number(c.y)).unwrap();
+    if dims.z {
+        write!(out, " {}", number(c.z)).unwrap();
+    }
+    if dims.m {
+        write!(out, " {}", number(c.m)).unwrap();
+    }
+}
+
+/// Reads ISO WKT such as `POINT Z (1 2 3)` or `MULTIPOINT (1 2, 3 4)`.
+/// Without a `Z`/`M`/`ZM` qualifier the dimensions follow from the number
+/// of ordinates in the first position.
+pub fn read(text: &str) -> Result<(Geometry, Dims)> {
+    let mut reader = Reader { tokens: tokenize(text)?, pos: 0, dims: None };
+    let geometry = reader.geometry(0)?;
+    if reader.pos != reader.tokens.len() {
+        return Err(invalid("trailing characters"));
+    }
+    Ok((geometry, reader.dims.unwrap_or(Dims::XY)))
+}
+
+const TYPES: [&str; 7] = [
+    "GEOMETRYCOLLECTION",
+    "MULTILINESTRING",
+    "MULTIPOLYGON",
+    "MULTIPOINT",
+    "LINESTRING",
+    "POLYGON",
+    "POINT",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Number(f64),
+    Open,
+    Close,
+    Comma,
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(format!("invalid WKT: {msg}"))
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | ',' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    _ => Token::Comma,
+                });
+            }
+            c if c.is_ascii_alphabetic() => {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek().filter(|(_, c)| c.is_ascii_alphabetic()) {
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token::Word(text[start..end].to_ascii_uppercase()));
+            }
+            _ => {
+                let mut end = start;
+                while let Some(&(i, c)) =
+                    chars.peek().filter(|(_, c)| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+                {
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                let number = text[start..end]
+                    .parse()
+                    .map_err(|_| invalid(&format!("unexpected '{}'", &text[start..end.max(start + c.len_utf8())])))?;
+                tokens.push(Token::Number(number));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Reader {
+    tokens: Vec<Token>,
+    pos: usize,
+    dims: Option<Dims>,
+}
+
+impl Reader {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn expect(&mut self, token: Token) -> Result<()> {
+        if self.peek() == Some(&token) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            let expected = match token {
+                Token::Open => "'('",
+                Token::Close => "')'",
+                _ => "','",
+            };
+            Err(invalid(&format!("expected {expected} at token {}", self.pos + 1)))
+        }
+    }
+
+    /// Parses `( item, item, ... )`.
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T>) -> Result<Vec<T>> {
+        self.expect(Token::Open)?;
+        let mut items = vec![item(self)?];
+        while self.peek() == Some(&Token::Comma) {
+            self.pos += 1;
+            items.push(item(self)?);
+        }
+        self.expect(Token::Close)?;
+        Ok(items)
+    }
+
+    fn set_dims(&mut self, dims: Dims) -> Result<()> {
+        match self.dims {
+            None => self.dims = Some(dims),
+            Some(existing) if existing != dims => return Err(invalid("mixed coordinate dimensions")),
+            Some(_) => {}
+        }
+        Ok(())
+    }
+
+    fn coord(&mut self) -> Result<Coord> {
+        let mut ordinates = Vec::with_capacity(4);
+        while let Some(Token::Number(value)) = self.peek() {
+            ordinates.push(*value);
+            self.pos += 1;
+        }
+        let dims = match (ordinates.len(), self.dims) {
+            (2, _) => Dims::XY,
+            (3, Some(Dims { z: false, m: true })) => Dims { z: false, m: true },
+            (3, _) => Dims { z: true, m: false },
+            (4, _) => Dims { z: true, m: true },
+            _ => return Err(invalid("positions need 2 to 4 ordinates")),
+        };
+        self.set_dims(dims)?;
+        let (z, m) = match (dims.z, dims.m) {
+            (true, true) => (ordinates[2], ordinates[3]),
+            (true, false) => (ordinates[2], 0.0),
+            (false, true) => (0.0, ordinates[2]),
+            (false, false) => (0.0, 0.0),
+        };
+        Ok(Coord { x: ordinates[0], y: ordinates[1], z, m })
+    }
+
+    fn coords(&mut self) -> Result<Vec<Coord>> {
+        self.list(Self::coord)
+    }
+
+    fn rings(&mut self) -> Result<Vec<Vec<Coord>>> {
+        self.list(Self::coords)
+    }
+
+    /// A MULTIPOINT member, written `(1 2)` or, in the older form, `1 2`.
+    fn point_member(&mut self) -> Result<Coord> {
+        if self.peek() == Some(&Token::Open) {
+            self.pos += 1;
+            let coord = self.coord()?;
+            self.expect(Token::Close)?;
+            Ok(coord)
+        } else {
+            self.coord()
+        }
+    }
+
+    fn geometry(&mut self, depth: usize) -> Result<Geometry> {
+        let Some(Token::Word(word)) = self.peek().cloned() else {
+            return Err(invalid("expected a geometry type"));
+        };
+        self.pos += 1;
+        let kind = TYPES
+            .iter()
+            .find(|t| word.starts_with(*t))
+            .ok_or_else(|| invalid(&format!("unknown geometry type {word}")))?;
+        // The qualifier may be attached (`POINTZ`) or separate (`POINT Z`).
+        let mut qualifier = word[kind.len()..].to_string();
+        if qualifier.is_empty()
+            && let Some(Token::Word(w)) = self.peek()
+            && matches!(w.as_str(), "Z" | "M" | "ZM")
+        {
+            qualifier = w.clone();
+            self.pos += 1;
+        }
+        match qualifier.as_str() {
+            "" => {}
+            "Z" => self.set_dims(Dims { z: true, m: false })?,
+            "M" => self.set_dims(Dims { z: false, m: true })?,
+            "ZM" => self.set_dims(Dims { z: true, m: true })?,
+            _ => return Err(invalid(&format!("unknown geometry type {word}"))),
+        }
+
+        if let Some(Token::Word(w)) = self.peek()
+            && w == "EMPTY"
+        {
+            self.pos += 1;
+            return Ok(match *kind {
+                "POINT" => Geometry::Point(None),
+                "LINESTRING" => Geometry::LineString(Vec::new()),
+                "POLYGON" => Geometry::Polygon(Vec::new()),
+                "MULTIPOINT" => Geometry::MultiPoint(Vec::new()),
+                "MULTILINESTRING" => Geometry::MultiLineString(Vec::new()),
+                "MULTIPOLYGON" => Geometry::MultiPolygon(Vec::new()),
+                _ => Geometry::GeometryCollection(Vec::new()),
+            });
+        }
+        Ok(match *kind {
+            "POINT" => {
+                self.expect(Token::Open)?;
+                let coord = self.coord()?;
+                self.expect(Token::Close)?;
+                Geometry::Point(Some(coord))
+            }
+            "LINESTRING" => Geometry::LineString(self.coords()?),
+            "POLYGON" => Geometry::Polygon(self.rings()?),
+            "MULTIPOINT" => Geometry::MultiPoint(self.list(Self::point_member)?),
+            "MULTILINESTRING" => Geometry::MultiLineString(self.rings()?),
+            "MULTIPOLYGON" => Geometry::MultiPolygon(self.list(Self::rings)?),
+            _ => {
+                if depth > 32 {
+                    return Err(invalid("collections nested too deep"));
+                }
+                Geometry::GeometryCollection(self.list(|r| r.geometry(depth + 1))?)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(text: &str) {
+        let (geometry, dims) = read(text).unwrap();
+        assert_eq!(write(&geometry, dims), text);
+    }
+
+    #[test]
+    fn round_trips() {
+        for text in [
+            "POINT (1 2)",
+            "POINT Z (1 2 3)",
+            "POINT M (1 2 4)",
+            "POINT ZM (1 2 3 4)",
+            "POINT EMPTY",
+            "LINESTRING (0 0, 1.5 -2.25, 1e-7 1.2345678901234568e16)",
+            "POLYGON ((0 0, 4 0, 4 4, 0 0), (1 1, 2 1, 2 2, 1 1))",
+            "MULTIPOINT ((1 2), (3 4))",
+            "MULTILINESTRING Z ((0 0 1, 1 1 2), (2 2 3, 3 3 4))",
+            "MULTIPOLYGON (((0 0, 1 0, 1 1, 0 0)), ((5 5, 6 5, 6 6, 5 5)))",
+            "GEOMETRYCOLLECTION (POINT (1 2), GEOMETRYCOLLECTION (LINESTRING (0 0, 1 1)))",
+            "GEOMETRYCOLLECTION EMPTY",
+            "MULTIPOLYGON ZM EMPTY",
+        ] {
+            round_trip(text);
+        }
+    }
+
+    #[test]
+    fn lenient_forms() {
+        let expected = read("MULTIPOINT Z ((1 2 3), (4 5 6))").unwrap();
+        assert_eq!(read("multipointz (1 2 3, 4 5 6)").unwrap(), expected);
+        assert_eq!(read("MULTIPOINT((1 2 3),(4 5 6))").unwrap(), expected);
+        // Without a qualifier the first position decides the dimensions.
+        assert_eq!(read("POINT (1 2 3 4)").unwrap().1, Dims { z: true, m: true });
+        assert_eq!(read("POINT M (1 2 3)").unwrap().0, Geometry::Point(Some(Coord { x: 1.0, y: 2.0, z: 0.0, m: 3.0 })));
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        for text in [
+            "",
+            "POINT",
+            "POINT (1)",
+            "POINT (1 2 3 4 5)",
+            "POINT (1 2",
+            "POINT (1 2))",
+            "POINT Z (1 2)",
+            "LINESTRING (0 0, 1 1 1)",
+            "LINESTRING (0 0,)",
+            "CIRCLE (0 0)",
+            "POINTX (1 2)",
+            "POINT (1 2) POINT (3 4)",
+            "POINT (1 é)",
+            "POINT (1 --2)",
+        ] {
+            assert!(read(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |levels: usize| {
+            format!("{}POINT (1 2){}", "GEOMETRYCOLLECTION (".repeat(levels), ")".repeat(levels))
+        };
+        assert!(read(&nested(33)).is_ok());
+        assert!(read(&nested(34)).is_err());
+        assert!(read(&nested(100_000)).is_err());
+    }
+}
@@ -0,0 +1,485 @@
+//! GeoPackage awareness.
+//!
+//! The `.gpkg` commands are exposed as SQL functions, so they work from any
+//! host that loads the extension:
+//!
+//! - `GPKG_IsGeoPackage()`: 1 when the main database is a GeoPackage
+//! - `GPKG_Info(?table?)`: summary of contents, SRS and extensions, or
+//!   details of one `gpkg_contents` entry
+//! - `GPKG_Stats(table)`: feature count, geometry types, extent, vertex
+//!   counts, NULL geometries and attribute ranges of a feature table
+//! - `GPKG_Schema(table)`: the `CREATE` statements of a table, with
+//!   geometry type, SRS, z/m, extent and spatial index of feature tables
+//!   in a comment block
+//! - `GPKG_InitSpatialMetadata()`: creates the required metadata tables
+//! - `GPKG_GeomFormat(geom, ?format?)`: renders a geometry BLOB as `wkt`
+//!   (the default), `geojson`, `hex` or `summary`
+//! - `ST_IsEmpty`, `ST_MinX`, `ST_MaxX`, `ST_MinY`, `ST_MaxY`,
+//!   `ST_GeometryType` and `ST_SRID` of a geometry BLOB, as required by the
+//!   RTree triggers
+//! - `GPKG_ImportGeoJSON(file, table, ?srs_id?)`: loads a GeoJSON file into
+//!   a feature table, creating it if needed; appending needs a matching SRS
+//!   and geometry type; returns the feature count
+//! - `GPKG_ImportCSV(file, table, lat, lon, ?srs_id?)`: loads a CSV file
+//!   into a new point feature table, building the points from the `lat` and
+//!   `lon` columns; returns the row count
+//! - `GPKG_ImportGPX(file, ?prefix?)`: loads GPX waypoints, routes and
+//!   tracks into the feature tables `<prefix>_waypoints`, `_routes` and
+//!   `_tracks`; the prefix defaults to the file name
+//! - `GPKG_ExportKML(table, file)`: writes a feature table as KML, in
+//!   longitude/latitude
+//! - `GPKG_ExportFlatGeobuf(table, file)`: writes a feature table as
+//!   FlatGeobuf with a packed Hilbert R-tree index; rows without a geometry
+//!   are left out
+//! - `GPKG_ExportMVT(table, target, ?minzoom?, ?maxzoom?)`: cuts a feature
+//!   table into Mapbox Vector Tiles for zoom levels 0 to 14 by default,
+//!   written to an MBTiles file when `target` ends in `.mbtiles` and to a
+//!   `z/x/y.pbf` directory otherwise; returns the tile count
+//! - `GPKG_RegisterAttributes(table, ?identifier?, ?description?)`: lists an
+//!   existing non-spatial table in `gpkg_contents` as `attributes`
+//! - `GPKG_CreateSpatialIndex(table)`: adds an RTree index with its triggers
+//!   and reports whether queries can use it
+//! - `GPKG_DropSpatialIndex(table)`: removes the RTree index again
+//! - `GPKG_BBox(table, minx, miny, maxx, maxy)`: (re)creates the view
+//!   `temp.<table>_bbox` of the features intersecting the box, using the
+//!   RTree when it is usable; returns the query it generated
+//! - `GPKG_UpdateExtents(?table?)`: adds missing envelopes to geometry
+//!   headers and recomputes the `gpkg_contents` extent of one or all feature
+//!   tables
+//! - `GPKG_Tiles(table)`: tile matrix set, zoom levels and tile counts of
+//!   a tile pyramid
+//! - `GPKG_ExtractTile(table, zoom, column, row, file)`: writes one tile to
+//!   an image file for inspection
+//! - `GPKG_Preview(table, zoom, column, row, ?width?, ?style?)`: a tile
+//!   drawn as text, in 24-bit colour half blocks (`color`, the default) or
+//!   grey levels (`ascii`)
+//! - `GPKG_ImportTiles(dir, table, ?format?, ?srs_id?, ?scheme?)`: loads
+//!   the `dir/z/x/y` tiles of an XYZ (default) or `tms` directory into a
+//!   tile pyramid in srs 3857 (default) or 4326; format defaults to `png`
+//! - `GPKG_FromMBTiles(file, table)` / `GPKG_ToMBTiles(table, file)`:
+//!   converts between MBTiles files and web Mercator tile pyramids,
+//!   flipping tile rows and mapping the metadata; only PNG, JPEG and WebP
+//!   tiles are imported
+//! - `GPKG_SRSList(?source?)`: the defined SRS and the tables using them, or
+//!   with source `epsg` the definitions `GPKG_AddSRS(code)` can add
+//! - `GPKG_AddSRS(code)` / `GPKG_AddSRS(srs_id, wkt_file)`: adds an SRS from
+//!   the bundled EPSG table or from a WKT file
+//! - `GPKG_RemoveSRS(srs_id)`: removes an SRS no table uses
+//! - `GPKG_Reproject(table, srs_id)`: transforms every geometry of a feature
+//!   table to another SRS; returns the number of geometries rewritten
+//! - `GPKG_Simplify(table, tolerance)`: simplifies every geometry of a
+//!   feature table in place and reports the vertex counts
+//! - `GPKG_AddMetadata(file, ?table?, ?row_id?)`: attaches an XML or JSON
+//!   document to the GeoPackage, a table or a row; returns its id
+//! - `GPKG_Metadata(id)` / `GPKG_RemoveMetadata(id)`: reads or removes a
+//!   metadata document
+//! - `GPKG_Extensions()`: the `gpkg_extensions` entries with scope and
+//!   definition
+//! - `GPKG_Validate(?mode?)`: PASS/WARN/FAIL report on the core requirements
+//!   of the spec; with mode `strict` failures raise an error, so batch runs
+//!   (`sqlite3 -bail`) exit non-zero
+use crate::error::{Error, Result};
+use crate::function::{self, Args, Context, Value};
+use crate::geometry;
+use libsqlite3_sys as ffi;
+use rusqlite::{Connection, OptionalExtension};
+
+mod attributes;
+mod create;
+mod csv;
+mod extensions;
+mod extents;
+mod features;
+mod flatgeobuf;
+mod geojson;
+mod gpx;
+mod info;
+mod kml;
+mod mbtiles;
+mod metadata;
+mod mvt;
+mod preview;
+mod reproject;
+mod rtree;
+mod simplify;
+mod srs;
+mod st;
+mod stats;
+mod tiles;
+mod validate;
+
+pub use create::create;
+pub use reproject::Transformer;
+pub use tiles::{MAX_LATITUDE, WEB_MERCATOR_EXTENT};
+
+/// `application_id` of a GeoPackage 1.2+ file ("GPKG").
+pub const APPLICATION_ID: i32 = 0x4750_4B47;
+/// `application_id` values written by GeoPackage 1.0 ("GP10") and 1.1 ("GP11").
+const LEGACY_APPLICATION_IDS: [i32; 2] = [0x4750_3130, 0x4750_3131];
+/// `user_version` of the GeoPackage version this extension writes (1.4.0).
+pub const USER_VERSION: i32 = 10400;
+
+pub fn application_id(conn: &Connection) -> Result<i32> {
+    Ok(conn.query_row("PRAGMA application_id", [], |row| row.get(0))?)
+}
+
+pub fn user_version(conn: &Connection) -> Result<i32> {
+    Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+/// Whether the main database identifies itself as a GeoPackage.
+pub fn is_geopackage(conn: &Connection) -> Result<bool> {
+    let id = application_id(conn)?;
+    Ok(id == APPLICATION_ID || LEGACY_APPLICATION_IDS.contains(&id))
+}
+
+pub fn table_exists(conn: &Connection, table: &str) -> Result<bool> {
+    let found = conn
+        .query_row(
+            "SELECT 1 FROM sqlite_schema WHERE type IN ('table', 'view') AND name = ?1",
+            [table],
+            |_| Ok(()),
+        )
+        .optional()?;
+    Ok(found.is_some())
+}
+
+fn is_geopackage_fn(ctx: &Context, _args: &Args) -> Result<Value> {
+    Ok(is_geopackage(&ctx.connection()?)?.into())
+}
+
+fn info_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let conn = ctx.connection()?;
+    match args.opt_text(0) {
+        Some(table) => Ok(info::table_info(&conn, &table)?.into()),
+        None => Ok(info::info(&conn)?.into()),
+    }
+}
+
+fn schema_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    Ok(info::schema(&ctx.connection()?, &args.text(0)?)?.into())
+}
+
+fn stats_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    Ok(stats::stats(&ctx.connection()?, &args.text(0)?)?.into())
+}
+
+fn init_spatial_metadata_fn(ctx: &Context, _args: &Args) -> Result<Value> {
+    create(&mut ctx.connection()?)?;
+    Ok(Value::Null)
+}
+
+fn geom_format_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    let Some(blob) = args.opt_blob(0) else {
+        return Ok(Value::Null);
+    };
+    let format = args.opt_text(1).unwrap_or_else(|| "wkt".to_string());
+    Ok(geometry::format(blob, &format)?.into())
+}
+
+fn srs_id(value: i64) -> Result<i32> {
+    i32::try_from(value).map_err(|_| Error::new(format!("invalid srs_id {value}")))
+}
+
+fn import_geojson_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let path = args.text(0)?;
+    let table = args.text(1)?;
+    let srs_id = args.opt_int(2).map(srs_id).transpose()?;
+    Ok(geojson::import(&mut ctx.connection()?, &path, &table, srs_id)?.into())
+}
+
+fn import_csv_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let (path, table) = (args.text(0)?, args.text(1)?);
+    let (lat, lon) = (args.text(2)?, args.text(3)?);
+    let srs_id = args.opt_int(4).map_or(Ok(4326), srs_id)?;
+    Ok(csv::import(&mut ctx.connection()?, &path, &table, &lat, &lon, srs_id)?.into())
+}
+
+fn import_gpx_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let path = args.text(0)?;
+    let prefix = args.opt_text(1);
+    Ok(gpx::import(&mut ctx.connection()?, &path, prefix.as_deref())?.into())
+}
+
+fn export_kml_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let (table, path) = (args.text(0)?, args.text(1)?);
+    Ok(kml::export(&ctx.connection()?, &table, &path)?.into())
+}
+
+fn export_flatgeobuf_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let (table, path) = (args.text(0)?, args.text(1)?);
+    Ok(flatgeobuf::export(&ctx.connection()?, &table, &path)?.into())
+}
+
+fn export_mvt_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let (table, target) = (args.text(0)?, args.text(1)?);
+    Ok(mvt::export(&ctx.connection()?, &table, &target, args.opt_int(2), args.opt_int(3))?.into())
+}
+
+fn register_attributes_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let table = args.text(0)?;
+    let (identifier, description) = (args.opt_text(1), args.opt_text(2));
+    attributes::register(&mut ctx.connection()?, &table, identifier.as_deref(), description.as_deref())?;
+    Ok(Value::Null)
+}
+
+fn create_spatial_index_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    Ok(rtree::create_index(&mut ctx.connection()?, &args.text(0)?)?.into())
+}
+
+fn drop_spatial_index_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    Ok(rtree::drop_index(&mut ctx.connection()?, &args.text(0)?)?.into())
+}
+
+fn bbox_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let table = args.text(0)?;
+    let bbox = [args.double(1)?, args.double(2)?, args.double(3)?, args.double(4)?];
+    Ok(rtree::bbox_view(&ctx.connection()?, &table, bbox)?.into())
+}
+
+fn update_extents_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    Ok(extents::update(&mut ctx.connection()?, args.opt_text(0).as_deref())?.into())
+}
+
+fn tiles_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    Ok(tiles::describe(&ctx.connection()?, &args.text(0)?)?.into())
+}
+
+fn extract_tile_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let table = args.text(0)?;
+    let (zoom, column, row) = (args.int(1)?, args.int(2)?, args.int(3)?);
+    let path = args.text(4)?;
+    Ok(tiles::extract(&ctx.connection()?, &table, zoom, column, row, &path)?.into())
+}
+
+fn preview_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let table = args.text(0)?;
+    let (zoom, column, row) = (args.int(1)?, args.int(2)?, args.int(3)?);
+    let style = args.opt_text(5);
+    Ok(preview::preview(&ctx.connection()?, &table, zoom, column, row, args.opt_int(4), style.as_deref())?.into())
+}
+
+fn import_tiles_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let dir = args.text(0)?;
+    let table = args.text(1)?;
+    let format = args.opt_text(2).unwrap_or_else(|| "png".to_string());
+    let srs_id = args.opt_int(3).map_or(Ok(3857), srs_id)?;
+    let scheme = args.opt_text(4).unwrap_or_else(|| "xyz".to_string());
+    Ok(tiles::import(&mut ctx.connection()?, &dir, &table, &format, srs_id, &scheme)?.into())
+}
+
+fn from_mbtiles_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let path = args.text(0)?;
+    Ok(mbtiles::import(&mut ctx.connection()?, &path, &args.text(1)?)?.into())
+}
+
+fn to_mbtiles_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let table = args.text(0)?;
+    Ok(mbtiles::export(&ctx.connection()?, &table, &args.text(1)?)?.into())
+}
+
+fn srs_list_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    match args.opt_text(0).as_deref() {
+        None => Ok(srs::list(&ctx.connection()?)?.into()),
+        Some("epsg") => Ok(srs::list_bundled().into()),
+        Some(source) => Err(Error::new(format!("unknown SRS source {source}"))),
+    }
+}
+
+fn add_srs_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let id = srs_id(args.int(0)?)?;
+    let mut conn = ctx.connection()?;
+    match args.opt_text(1) {
+        Some(path) => srs::add_wkt_file(&mut conn, id, &path)?,
+        None => srs::add_epsg(&mut conn, id)?,
+    }
+    Ok(i64::from(id).into())
+}
+
+fn remove_srs_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    srs::remove(&ctx.connection()?, srs_id(args.int(0)?)?)?;
+    Ok(Value::Null)
+}
+
+fn reproject_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let table = args.text(0)?;
+    let srs_id = srs_id(args.int(1)?)?;
+    Ok(reproject::reproject(&mut ctx.connection()?, &table, srs_id)?.into())
+}
+
+fn simplify_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let table = args.text(0)?;
+    let tolerance = args.double(1)?;
+    Ok(simplify::simplify(&mut ctx.connection()?, &table, tolerance)?.into())
+}
+
+fn add_metadata_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let path = args.text(0)?;
+    let table = args.opt_text(1);
+    Ok(metadata::add(&mut ctx.connection()?, &path, table.as_deref(), args.opt_int(2))?.into())
+}
+
+fn metadata_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    Ok(metadata::document(&ctx.connection()?, args.int(0)?)?.into())
+}
+
+fn remove_metadata_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    metadata::remove(&mut ctx.connection()?, args.int(0)?)?;
+    Ok(Value::Null)
+}
+
+fn extensions_fn(ctx: &Context, _args: &Args) -> Result<Value> {
+    Ok(extensions::list(&ctx.connection()?)?.into())
+}
+
+fn validate_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let report = validate::validate(&ctx.connection()?)?;
+    match args.opt_text(0).as_deref() {
+        None => Ok(report.to_string().into()),
+        Some("strict") if report.has_failures() => Err(Error::new(report.to_string())),
+        Some("strict") => Ok(report.to_string().into()),
+        Some(mode) => Err(Error::new(format!("unknown validation mode {mode}"))),
+    }
+}
+
+/// Registers the GeoPackage SQL functions on `conn`.
+pub fn register(conn: &Connection) -> Result<()> {
+    // Functions that modify the database may only be called from top-level SQL.
+    let pure = ffi::SQLITE_DETERMINISTIC | ffi::SQLITE_INNOCUOUS;
+    let functions: [function::Definition; 60] = [
+        ("GPKG_IsGeoPackage", 0, 0, is_geopackage_fn),
+        ("GPKG_Info", 0, 0, info_fn),
+        ("GPKG_Info", 1, 0, info_fn),
+        ("GPKG_Schema", 1, 0, schema_fn),
+        ("GPKG_Stats", 1, 0, stats_fn),
+        ("GPKG_InitSpatialMetadata", 0, ffi::SQLITE_DIRECTONLY, init_spatial_metadata_fn),
+        ("GPKG_GeomFormat", 1, pure, geom_format_fn),
+        ("GPKG_GeomFormat", 2, pure, geom_format_fn),
+        ("ST_IsEmpty", 1, pure, st::is_empty_fn),
+        ("ST_MinX", 1, pure, st::min_x_fn),
+        ("ST_MaxX", 1, pure, st::max_x_fn),
+        ("ST_MinY", 1, pure, st::min_y_fn),
+        ("ST_MaxY", 1, pure, st::max_y_fn),
+        ("ST_GeometryType", 1, pure, st::geometry_type_fn),
+        ("ST_SRID", 1, pure, st::srid_fn),
+        ("GPKG_ImportGeoJSON", 2, ffi::SQLITE_DIRECTONLY, import_geojson_fn),
+        ("GPKG_ImportGeoJSON", 3, ffi::SQLITE_DIRECTONLY, import_geojson_fn),
+        ("GPKG_ImportCSV", 4, ffi::SQLITE_DIRECTONLY, import_csv_fn),
+        ("GPKG_ImportCSV", 5, ffi::SQLITE_DIRECTONLY, import_csv_fn),
+        ("GPKG_ImportGPX", 1, ffi::SQLITE_DIRECTONLY, import_gpx_fn),
+        ("GPKG_ImportGPX", 2, ffi::SQLITE_DIRECTONLY, import_gpx_fn),
+        ("GPKG_ExportKML", 2, ffi::SQLITE_DIRECTONLY, export_kml_fn),
+        ("GPKG_ExportFlatGeobuf", 2, ffi::SQLITE_DIRECTONLY, export_flatgeobuf_fn),
+        ("GPKG_ExportMVT", 2, ffi::SQLITE_DIRECTONLY, export_mvt_fn),
+        ("GPKG_ExportMVT", 3, ffi::SQLITE_DIRECTONLY, export_mvt_fn),
+        ("GPKG_ExportMVT", 4, ffi::SQLITE_DIRECTONLY, export_mvt_fn),
+        ("GPKG_RegisterAttributes", 1, ffi::SQLITE_DIRECTONLY, register_attributes_fn),
+        ("GPKG_RegisterAttributes", 2, ffi::SQLITE_DIRECTONLY, register_attributes_fn),
+        ("GPKG_RegisterAttributes", 3, ffi::SQLITE_DIRECTONLY, register_attributes_fn),
+        ("GPKG_CreateSpatialIndex", 1, ffi::SQLITE_DIRECTONLY, create_spatial_index_fn),
+        ("GPKG_DropSpatialIndex", 1, ffi::SQLITE_DIRECTONLY, drop_spatial_index_fn),
+        ("GPKG_BBox", 5, ffi::SQLITE_DIRECTONLY, bbox_fn),
+        ("GPKG_UpdateExtents", 0, ffi::SQLITE_DIRECTONLY, update_extents_fn),
+        ("GPKG_UpdateExtents", 1, ffi::SQLITE_DIRECTONLY, update_extents_fn),
+        ("GPKG_Tiles", 1, 0, tiles_fn),
+        ("GPKG_ExtractTile", 5, ffi::SQLITE_DIRECTONLY, extract_tile_fn),
+        ("GPKG_Preview", 4, 0, preview_fn),
+        ("GPKG_Preview", 5, 0, preview_fn),
+        ("GPKG_Preview", 6, 0, preview_fn),
+        ("GPKG_ImportTiles", 2, ffi::SQLITE_DIRECTONLY, import_tiles_fn),
+        ("GPKG_ImportTiles", 3, ffi::SQLITE_DIRECTONLY, import_tiles_fn),
+        ("GPKG_ImportTiles", 4, ffi::SQLITE_DIRECTONLY, import_tiles_fn),
+        ("GPKG_ImportTiles", 5, ffi::SQLITE_DIRECTONLY, import_tiles_fn),
+        ("GPKG_FromMBTiles", 2, ffi::SQLITE_DIRECTONLY, from_mbtiles_fn),
+        ("GPKG_ToMBTiles", 2, ffi::SQLITE_DIRECTONLY, to_mbtiles_fn),
+        ("GPKG_SRSList", 0, 0, srs_list_fn),
+        ("GPKG_SRSList", 1, 0, srs_list_fn),
+        ("GPKG_AddSRS", 1, ffi::SQLITE_DIRECTONLY, add_srs_fn),
+        ("GPKG_AddSRS", 2, ffi::SQLITE_DIRECTONLY, add_srs_fn),
+        ("GPKG_RemoveSRS", 1, ffi::SQLITE_DIRECTONLY, remove_srs_fn),
+        ("GPKG_Reproject", 2, ffi::SQLITE_DIRECTONLY, reproject_fn),
+        ("GPKG_Simplify", 2, ffi::SQLITE_DIRECTONLY, simplify_fn),
+        ("GPKG_AddMetadata", 1, ffi::SQLITE_DIRECTONLY, add_metadata_fn),
+        ("GPKG_AddMetadata", 2, ffi::SQLITE_DIRECTONLY, add_metadata_fn),
+        ("GPKG_AddMetadata", 3, ffi::SQLITE_DIRECTONLY, add_metadata_fn),
+        ("GPKG_Metadata", 1, 0, metadata_fn),
+        ("GPKG_RemoveMetadata", 1, ffi::SQLITE_DIRECTONLY, remove_metadata_fn),
+        ("GPKG_Extensions", 0, 0, extensions_fn),
+        ("GPKG_Validate", 0, 0, validate_fn),
+        ("GPKG_Validate", 1, 0, validate_fn),
+    ];
+    function::register(conn, &functions)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{open, temp_path};
+    use rusqlite::Connection;
+
+    fn text(conn: &Connection, sql: &str) -> String {
+        conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    fn int(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    fn write(name: &str, contents: &[u8]) -> String {
+        let path = temp_path(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn failed_calls_leave_plain_files_alone() {
+        let csv = write("points.csv", b"lat,lon\n1,2\n");
+        let gpx = write("empty.gpx", b"<gpx version=\"1.1\" creator=\"test\"></gpx>");
+        let xml = write("metadata.xml", b"<metadata/>");
+        let mbtiles = temp_path("tiles.mbtiles");
+        {
+            let source = open(&mbtiles);
+            source.execute_batch(super::mbtiles::SCHEMA).unwrap();
+            source.execute("INSERT INTO tiles VALUES (0, 0, 0, ?1)", [b"\x89PNG\r\n\x1a\n".as_slice()]).unwrap();
+        }
+
+        let conn = open(":memory:");
+        conn.execute_batch("CREATE TABLE foo (x)").unwrap();
+        for sql in [
+            "SELECT GPKG_RegisterAttributes('nope')".to_string(),
+            "SELECT GPKG_AddSRS(4326)".to_string(),
+            format!("SELECT GPKG_ImportCSV('{csv}', 'foo', 'lat', 'lon')"),
+            format!("SELECT GPKG_ImportGPX('{gpx}')"),
+            format!("SELECT GPKG_AddMetadata('{xml}', 'nope')"),
+            format!("SELECT GPKG_FromMBTiles('{mbtiles}', 'foo')"),
+        ] {
+            assert!(conn.query_row(&sql, [], |_| Ok(())).is_err(), "{sql}");
+            assert_eq!(int(&conn, "PRAGMA application_id"), 0, "{sql}");
+            assert_eq!(int(&conn, "SELECT count(*) FROM sqlite_schema"), 1, "{sql}");
+        }
+    }
+
+    #[test]
+    fn spatial_index() {
+        let csv = write("cities.csv", b"name,lat,lon\nLisbon,38.7,-9.1\nOslo,59.9,10.7\nQuito,-0.2,-78.5\n");
+        let conn = open(":memory:");
+        assert_eq!(int(&conn, &format!("SELECT GPKG_ImportCSV('{csv}', 'cities', 'lat', 'lon')")), 3);
+        assert_eq!(text(&conn, "SELECT GPKG_CreateSpatialIndex('cities')"), "rtree_cities_geom; usable");
+
+        let query = text(&conn, "SELECT GPKG_BBox('cities', -20, 30, 20, 70)");
+        assert!(query.contains("rtree_cities_geom"), "{query}");
+        let found = text(&conn, "SELECT group_concat(name) FROM (SELECT name FROM temp.cities_bbox ORDER BY 1)");
+        assert_eq!(found, "Lisbon,Oslo");
+
+        // The triggers keep the index in step with the table.
+        conn.execute_batch("DELETE FROM cities WHERE name = 'Oslo'").unwrap();
+        assert_eq!(int(&conn, "SELECT count(*) FROM rtree_cities_geom"), 2);
+        assert!(text(&conn, "SELECT GPKG_Validate()").ends_with(", 0 failed"));
+
+        // An index that lost entries fails validation; one that lost a
+        // trigger is no longer used.
+        conn.execute_batch("DELETE FROM rtree_cities_geom WHERE id = 1").unwrap();
+        assert!(text(&conn, "SELECT GPKG_Validate()").contains("holds 1 entries for 2 non-empty geometries"));
+        conn.execute_batch("DROP TRIGGER rtree_cities_geom_delete").unwrap();
+        assert!(!text(&conn, "SELECT GPKG_BBox('cities', -20, 30, 20, 70)").contains("rtree_cities_geom"));
+    }
+}
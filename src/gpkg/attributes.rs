@@ -0,0 +1,42 @@
+//! Attributes tables: plain, non-spatial tables listed in `gpkg_contents`.
+use super::create::create_in;
+use super::{features, table_exists};
+use crate::error::{Error, Result};
+use rusqlite::{Connection, OptionalExtension};
+
+/// Records the existing table `table` in `gpkg_contents` with data_type
+/// `attributes`. The identifier defaults to the table name.
+pub fn register(conn: &mut Connection, table: &str, identifier: Option<&str>, description: Option<&str>) -> Result<()> {
+    let tx = conn.savepoint()?;
+    create_in(&tx)?;
+    if !table_exists(&tx, table)? {
+        return Err(Error::new(format!("table {table} does not exist")));
+    }
+    if ["gpkg_", "rtree_", "sqlite_"].iter().any(|prefix| table.starts_with(prefix)) {
+        return Err(Error::new(format!("{table} is an internal table")));
+    }
+    let registered: Option<String> = tx
+        .query_row("SELECT data_type FROM gpkg_contents WHERE table_name = ?1", [table], |row| row.get(0))
+        .optional()?;
+    if let Some(data_type) = registered {
+        return Err(Error::new(format!("{table} is already registered as {data_type}")));
+    }
+    // The spec requires an integer primary key so rows can be referenced.
+    features::primary_key(&tx, table)?;
+    let identifier = identifier.unwrap_or(table);
+    let taken = tx
+        .query_row("SELECT table_name FROM gpkg_contents WHERE identifier = ?1", [identifier], |row| {
+            row.get::<_, String>(0)
+        })
+        .optional()?;
+    if let Some(other) = taken {
+        return Err(Error::new(format!("identifier {identifier} is already used by {other}")));
+    }
+    tx.execute(
+        "INSERT INTO gpkg_contents (table_name, data_type, identifier, description)
+         VALUES (?1, 'attributes', ?2, coalesce(?3, ''))",
+        (table, identifier, description),
+    )?;
+    tx.commit()?;
+    Ok(())
+}
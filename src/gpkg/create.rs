@@ -0,0 +1,169 @@
+//! `GPKG_InitSpatialMetadata()`: turns the main database into a GeoPackage.
+use super::{APPLICATION_ID, USER_VERSION};
+use crate::error::Result;
+use rusqlite::Connection;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS gpkg_spatial_ref_sys (
+  srs_name TEXT NOT NULL,
+  srs_id INTEGER NOT NULL PRIMARY KEY,
+  organization TEXT NOT NULL,
+  organization_coordsys_id INTEGER NOT NULL,
+  definition TEXT NOT NULL,
+  description TEXT
+);
+
+CREATE TABLE IF NOT EXISTS gpkg_contents (
+  table_name TEXT NOT NULL PRIMARY KEY,
+  data_type TEXT NOT NULL,
+  identifier TEXT UNIQUE,
+  description TEXT DEFAULT '',
+  last_change DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+  min_x DOUBLE,
+  min_y DOUBLE,
+  max_x DOUBLE,
+  max_y DOUBLE,
+  srs_id INTEGER,
+  CONSTRAINT fk_gc_r_srs_id FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys(srs_id)
+);
+
+CREATE TABLE IF NOT EXISTS gpkg_geometry_columns (
+  table_name TEXT NOT NULL,
+  column_name TEXT NOT NULL,
+  geometry_type_name TEXT NOT NULL,
+  srs_id INTEGER NOT NULL,
+  z TINYINT NOT NULL,
+  m TINYINT NOT NULL,
+  CONSTRAINT pk_geom_cols PRIMARY KEY (table_name, column_name),
+  CONSTRAINT uk_gc_table_name UNIQUE (table_name),
+  CONSTRAINT fk_gc_tn FOREIGN KEY (table_name) REFERENCES gpkg_contents(table_name),
+  CONSTRAINT fk_gc_srs FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys (srs_id)
+);
+";
+
+/// `gpkg_extensions` is optional; it is created when an extension is first used.
+pub const EXTENSIONS_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS gpkg_extensions (
+  table_name TEXT,
+  column_name TEXT,
+  extension_name TEXT NOT NULL,
+  definition TEXT NOT NULL,
+  scope TEXT NOT NULL,
+  CONSTRAINT ge_tce UNIQUE (table_name, column_name, extension_name)
+);
+";
+
+/// The tile pyramid metadata tables, created with the first tile layer.
+pub const TILES_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS gpkg_tile_matrix_set (
+  table_name TEXT NOT NULL PRIMARY KEY,
+  srs_id INTEGER NOT NULL,
+  min_x DOUBLE NOT NULL,
+  min_y DOUBLE NOT NULL,
+  max_x DOUBLE NOT NULL,
+  max_y DOUBLE NOT NULL,
+  CONSTRAINT fk_gtms_table_name FOREIGN KEY (table_name) REFERENCES gpkg_contents(table_name),
+  CONSTRAINT fk_gtms_srs FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys (srs_id)
+);
+
+CREATE TABLE IF NOT EXISTS gpkg_tile_matrix (
+  table_name TEXT NOT NULL,
+  zoom_level INTEGER NOT NULL,
+  matrix_width INTEGER NOT NULL,
+  matrix_height INTEGER NOT NULL,
+  tile_width INTEGER NOT NULL,
+  tile_height INTEGER NOT NULL,
+  pixel_x_size DOUBLE NOT NULL,
+  pixel_y_size DOUBLE NOT NULL,
+  CONSTRAINT pk_ttm PRIMARY KEY (table_name, zoom_level),
+  CONSTRAINT fk_tmm_table_name FOREIGN KEY (table_name) REFERENCES gpkg_contents(table_name)
+);
+";
+
+/// The metadata extension tables, created when metadata is first attached.
+pub const METADATA_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS gpkg_metadata (
+  id INTEGER CONSTRAINT m_pk PRIMARY KEY ASC NOT NULL,
+  md_scope TEXT NOT NULL DEFAULT 'dataset',
+  md_standard_uri TEXT NOT NULL,
+  mime_type TEXT NOT NULL DEFAULT 'text/xml',
+  metadata TEXT NOT NULL DEFAULT ''
+);
+
+CREATE TABLE IF NOT EXISTS gpkg_metadata_reference (
+  reference_scope TEXT NOT NULL,
+  table_name TEXT,
+  column_name TEXT,
+  row_id_value INTEGER,
+  timestamp DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+  md_file_id INTEGER NOT NULL,
+  md_parent_id INTEGER,
+  CONSTRAINT crmr_mfi_fk FOREIGN KEY (md_file_id) REFERENCES gpkg_metadata(id),
+  CONSTRAINT crmr_mpi_fk FOREIGN KEY (md_parent_id) REFERENCES gpkg_metadata(id)
+);
+";
+
+const WGS84_DEFINITION: &str = "GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",\
+SPHEROID[\"WGS 84\",6378137,298.257223563,AUTHORITY[\"EPSG\",\"7030\"]],\
+AUTHORITY[\"EPSG\",\"6326\"]],PRIMEM[\"Greenwich\",0,AUTHORITY[\"EPSG\",\"8901\"]],\
+UNIT[\"degree\",0.0174532925199433,AUTHORITY[\"EPSG\",\"9122\"]],\
+AXIS[\"Latitude\",NORTH],AXIS[\"Longitude\",EAST],AUTHORITY[\"EPSG\",\"4326\"]]";
+
+/// The SRS rows every GeoPackage must contain:
+/// `(srs_name, srs_id, organization, organization_coordsys_id, definition, description)`.
+const REQUIRED_SRS: [(&str, i64, &str, i64, &str, &str); 3] = [
+    (
+        "WGS 84 geodetic",
+        4326,
+        "EPSG",
+        4326,
+        WGS84_DEFINITION,
+        "longitude/latitude coordinates in decimal degrees on the WGS 84 spheroid",
+    ),
+    (
+        "Undefined cartesian SRS",
+        -1,
+        "NONE",
+        -1,
+        "undefined",
+        "undefined cartesian coordinate reference system",
+    ),
+    (
+        "Undefined geographic SRS",
+        0,
+        "NONE",
+        0,
+        "undefined",
+        "undefined geographic coordinate reference system",
+    ),
+];
+
+/// Creates the mandatory GeoPackage tables and SRS entries and stamps the
+/// file with the GeoPackage `application_id` and `user_version`.
+///
+/// Existing tables and SRS rows are left untouched, so calling this on an
+/// existing GeoPackage is harmless.
+pub fn create(conn: &mut Connection) -> Result<()> {
+    let tx = conn.savepoint()?;
+    create_in(&tx)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// [`create`] for callers that already hold a savepoint, so that a failure
+/// later on rolls the GeoPackage tables back too.
+pub fn create_in(conn: &Connection) -> Result<()> {
+    conn.execute_batch(SCHEMA)?;
+    for (name, id, organization, coordsys_id, definition, description) in REQUIRED_SRS {
+        conn.execute(
+            "INSERT OR IGNORE INTO gpkg_spatial_ref_sys
+               (srs_name, srs_id, organization, organization_coordsys_id, definition, description)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            (name, id, organization, coordsys_id, definition, description),
+        )?;
+    }
+    conn.execute_batch(&format!(
+        "PRAGMA application_id = {APPLICATION_ID}; PRAGMA user_version = {USER_VERSION};"
+    ))?;
+    Ok(())
+}
@@ -0,0 +1,228 @@
+//! `GPKG_ImportCSV(file, table, lat, lon, ?srs_id?)`: loads a CSV file into
+//! a new point feature table, building the points from two coordinate
+//! columns.
+use super::create::create_in;
+use super::features::{self, GeometryColumn};
+use super::table_exists;
+use crate::error::{Error, Result};
+use crate::geometry::{Coord, Dims, Geometry, gpb};
+use crate::quote_identifier;
+use rusqlite::Connection;
+use rusqlite::types::Value;
+use std::collections::HashSet;
+use std::fs;
+
+/// Splits RFC 4180 CSV into records of fields. Quoted fields may contain
+/// commas, doubled quotes and line breaks. Returns each record with the
+/// line it starts on.
+fn records(text: &str) -> Result<Vec<(usize, Vec<String>)>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let (mut line, mut start) = (1, 1);
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            '\n' if quoted => {
+                line += 1;
+                field.push(c);
+            }
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                if record.len() > 1 || !record[0].is_empty() {
+                    records.push((start, std::mem::take(&mut record)));
+                }
+                record.clear();
+                line += 1;
+                start = line;
+            }
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(Error::new(format!("unterminated quoted field on line {start}")));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push((start, record));
+    }
+    Ok(records)
+}
+
+/// Declared type of a column: INTEGER or DOUBLE when every non-empty value
+/// parses as one, TEXT otherwise.
+fn declared_type<'a>(mut values: impl Iterator<Item = &'a str>) -> &'static str {
+    let mut declared = "INTEGER";
+    for value in values.by_ref().filter(|v| !v.is_empty()) {
+        if value.parse::<i64>().is_ok() {
+            continue;
+        }
+        if value.parse::<f64>().is_ok() {
+            declared = "DOUBLE";
+        } else {
+            return "TEXT";
+        }
+    }
+    declared
+}
+
+fn sql_value(value: &str, declared: &str) -> Value {
+    if value.is_empty() {
+        return Value::Null;
+    }
+    match declared {
+        "INTEGER" => value.parse().map_or_else(|_| Value::Text(value.to_string()), Value::Integer),
+        "DOUBLE" => value.parse().map_or_else(|_| Value::Text(value.to_string()), Value::Real),
+        _ => Value::Text(value.to_string()),
+    }
+}
+
+/// Imports the CSV file `path` into the new feature table `table`, with a
+/// point built from the `lat` and `lon` columns of each row in `srs_id`.
+/// Rows with an empty coordinate get a NULL geometry. Returns the number
+/// of rows imported.
+pub fn import(conn: &mut Connection, path: &str, table: &str, lat: &str, lon: &str, srs_id: i32) -> Result<i64> {
+    let text = fs::read_to_string(path).map_err(|e| Error::new(format!("cannot read {path}: {e}")))?;
+    let mut records = records(&text)?.into_iter();
+    let Some((_, header)) = records.next() else {
+        return Err(Error::new(format!("{path} is empty")));
+    };
+    let rows: Vec<(usize, Vec<String>)> = records.collect();
+    if let Some((line, row)) = rows.iter().find(|(_, row)| row.len() != header.len()) {
+        return Err(Error::new(format!(
+            "{path} line {line}: {} fields, the header has {}",
+            row.len(),
+            header.len()
+        )));
+    }
+    let position = |name: &str| {
+        header
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(name))
+            .ok_or_else(|| Error::new(format!("{path} has no column {name}")))
+    };
+    let (lat_index, lon_index) = (position(lat)?, position(lon)?);
+
+    // The CSV columns keep their names unless they clash with fid or geom.
+    let geometry_column =
+        GeometryColumn { column: "geom".to_string(), type_name: "POINT".to_string(), srs_id, z: 0, m: 0 };
+    let mut taken: HashSet<String> = ["fid".to_string(), geometry_column.column.clone()].into();
+    let mut columns = Vec::with_capacity(header.len());
+    for (i, name) in header.iter().enumerate() {
+        let name = match name.trim() {
+            "" => format!("field_{}", i + 1),
+            name => name.to_string(),
+        };
+        let mut candidate = name.clone();
+        let mut n = 1;
+        while !taken.insert(candidate.to_lowercase()) {
+            candidate = format!("{name}_{n}");
+            n += 1;
+        }
+        columns.push((candidate, declared_type(rows.iter().map(|(_, row)| row[i].trim()))));
+    }
+
+    let tx = conn.savepoint()?;
+    create_in(&tx)?;
+    if table_exists(&tx, table)? {
+        return Err(Error::new(format!("table {table} already exists")));
+    }
+    features::create_feature_table(&tx, table, &geometry_column, &columns)?;
+
+    let mut names = vec![quote_identifier(&geometry_column.column)];
+    names.extend(columns.iter().map(|(name, _)| quote_identifier(name)));
+    let placeholders: Vec<String> = (1..=names.len()).map(|i| format!("?{i}")).collect();
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        quote_identifier(table),
+        names.join(", "),
+        placeholders.join(", ")
+    );
+
+    let mut extent = None;
+    {
+        let mut insert = tx.prepare(&sql)?;
+        for (line, row) in &rows {
+            let coordinate = |i: usize, name: &str| -> Result<Option<f64>> {
+                match row[i].trim() {
+                    "" => Ok(None),
+                    value => value
+                        .parse()
+                        .map(Some)
+                        .map_err(|_| Error::new(format!("{path} line {line}: {name} {value:?} is not a number"))),
+                }
+            };
+            let mut values = Vec::with_capacity(names.len());
+            values.push(match (coordinate(lon_index, lon)?, coordinate(lat_index, lat)?) {
+                (Some(x), Some(y)) => {
+                    let point = Geometry::Point(Some(Coord { x, y, ..Coord::default() }));
+                    features::expand(&mut extent, point.envelope(Dims::XY));
+                    Value::Blob(gpb::encode(&point, Dims::XY, srs_id))
+                }
+                _ => Value::Null,
+            });
+            values.extend(row.iter().zip(&columns).map(|(value, (_, declared))| sql_value(value.trim(), declared)));
+            insert.execute(rusqlite::params_from_iter(values))?;
+        }
+    }
+    features::extend_contents(&tx, table, extent)?;
+    tx.commit()?;
+    Ok(rows.len() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Each record as its line and its fields joined with `|`.
+    fn fields(text: &str) -> Vec<(usize, String)> {
+        records(text).unwrap().into_iter().map(|(line, record)| (line, record.join("|"))).collect()
+    }
+
+    #[test]
+    fn plain_records() {
+        assert_eq!(fields("a,b\n1,2\n"), [(1, "a|b".into()), (2, "1|2".into())]);
+        // CRLF, a missing final line break and empty fields.
+        assert_eq!(fields("a,b\r\n,\r\n3,"), [(1, "a|b".into()), (2, "|".into()), (3, "3|".into())]);
+        // Blank lines are skipped but still counted.
+        assert_eq!(fields("a\n\nb\n"), [(1, "a".into()), (3, "b".into())]);
+        // A byte order mark is not part of the first header.
+        assert_eq!(fields("\u{feff}lat,lon\n"), [(1, "lat|lon".into())]);
+    }
+
+    #[test]
+    fn quoted_fields() {
+        assert_eq!(fields("\"a,b\",c\n"), [(1, "a,b|c".into())]);
+        assert_eq!(fields("\"say \"\"hi\"\"\",\"\"\n"), [(1, "say \"hi\"|".into())]);
+        // Line breaks inside quotes stay in the field, and the next record
+        // reports the line it starts on.
+        assert_eq!(fields("\"1\n2\",x\r\ny,z\n"), [(1, "1\n2|x".into()), (3, "y|z".into())]);
+        assert_eq!(fields("\"a\r\nb\"\n"), [(1, "a\r\nb".into())]);
+    }
+
+    #[test]
+    fn unterminated_quote() {
+        let error = records("a,b\n\"open,1\n2,3\n").unwrap_err().to_string();
+        assert!(error.contains("line 2"), "{error}");
+    }
+
+    #[test]
+    fn declared_types() {
+        assert_eq!(declared_type(["1", "", "-2"].into_iter()), "INTEGER");
+        assert_eq!(declared_type(["1", "2.5"].into_iter()), "DOUBLE");
+        assert_eq!(declared_type(["1.5", "x", "2"].into_iter()), "TEXT");
+        assert_eq!(declared_type(["", ""].into_iter()), "INTEGER");
+    }
+}
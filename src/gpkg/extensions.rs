@@ -0,0 +1,109 @@
+//! `gpkg_extensions`: which extensions a GeoPackage uses, and on what.
+//!
+//! Features that depend on an extension register it when they create data
+//! that needs it and unregister it when that data goes away, so readers can
+//! tell what they have to understand.
+use super::create::EXTENSIONS_SCHEMA;
+use super::table_exists;
+use crate::error::Result;
+use rusqlite::Connection;
+use std::fmt::Write;
+
+/// An extension as recorded in `gpkg_extensions`.
+pub struct Extension {
+    pub name: &'static str,
+    pub definition: &'static str,
+    /// `read-write` if readers must understand it, `write-only` if only writers must.
+    pub scope: &'static str,
+}
+
+pub const RTREE_INDEX: Extension = Extension {
+    name: "gpkg_rtree_index",
+    definition: "http://www.geopackage.org/spec/#extension_rtree",
+    scope: "write-only",
+};
+
+/// Registered on both `gpkg_metadata` and `gpkg_metadata_reference`.
+pub const METADATA: Extension = Extension {
+    name: "gpkg_metadata",
+    definition: "http://www.geopackage.org/spec/#extension_metadata",
+    scope: "read-write",
+};
+
+pub const WEBP: Extension = Extension {
+    name: "gpkg_webp",
+    definition: "http://www.geopackage.org/spec/#extension_tiles_webp",
+    scope: "read-write",
+};
+
+/// Extensions adopted by the GeoPackage specification; `gpkg_geom_<TYPE>`
+/// (non-linear geometry types) is matched by prefix.
+const REGISTERED: [&str; 10] = [
+    "gpkg_rtree_index",
+    "gpkg_metadata",
+    "gpkg_schema",
+    "gpkg_crs_wkt",
+    "gpkg_crs_wkt_1_1",
+    "gpkg_webp",
+    "gpkg_zoom_other",
+    "gpkg_related_tables",
+    "gpkg_geometry_type_trigger",
+    "gpkg_srs_id_trigger",
+];
+
+/// Whether `name` is one of the extensions adopted by the specification.
+pub fn is_registered(name: &str) -> bool {
+    REGISTERED.contains(&name) || name.starts_with("gpkg_geom_")
+}
+
+/// Records that `extension` is used on `table`/`column` (both `None` for
+/// the whole GeoPackage), creating `gpkg_extensions` if needed.
+pub fn register(conn: &Connection, table: Option<&str>, column: Option<&str>, extension: &Extension) -> Result<()> {
+    conn.execute_batch(EXTENSIONS_SCHEMA)?;
+    // The UNIQUE constraint treats NULLs as distinct, so replace by hand.
+    unregister(conn, table, column, extension.name)?;
+    conn.execute(
+        "INSERT INTO gpkg_extensions (table_name, column_name, extension_name, definition, scope)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        (table, column, extension.name, extension.definition, extension.scope),
+    )?;
+    Ok(())
+}
+
+/// Removes the registration of `name` on `table`/`column`.
+pub fn unregister(conn: &Connection, table: Option<&str>, column: Option<&str>, name: &str) -> Result<()> {
+    if table_exists(conn, "gpkg_extensions")? {
+        conn.execute(
+            "DELETE FROM gpkg_extensions
+             WHERE table_name IS ?1 AND column_name IS ?2 AND extension_name = ?3",
+            (table, column, name),
+        )?;
+    }
+    Ok(())
+}
+
+/// One line per `gpkg_extensions` entry: name, target, scope and definition.
+pub fn list(conn: &Connection) -> Result<String> {
+    if !table_exists(conn, "gpkg_extensions")? {
+        return Ok(String::new());
+    }
+    let mut stmt = conn.prepare(
+        "SELECT extension_name, table_name, column_name, scope, definition
+         FROM gpkg_extensions ORDER BY extension_name, table_name, column_name",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut out = String::new();
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(0)?;
+        let target = match (row.get::<_, Option<String>>(1)?, row.get::<_, Option<String>>(2)?) {
+            (Some(table), Some(column)) => format!("{table}.{column}"),
+            (Some(table), None) => table,
+            _ => "(geopackage)".to_string(),
+        };
+        let scope: String = row.get(3)?;
+        let definition: String = row.get(4)?;
+        let known = if is_registered(&name) { "" } else { ", not adopted by the spec" };
+        writeln!(out, "{name} on {target} ({scope}{known}): {definition}").unwrap();
+    }
+    Ok(out.trim_end().to_string())
+}
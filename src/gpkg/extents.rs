@@ -0,0 +1,104 @@
+//! `GPKG_UpdateExtents()`: fills in missing GPB envelopes and recomputes the
+//! `gpkg_contents` extents of feature tables, which drift after bulk edits.
+use super::features;
+use super::table_exists;
+use crate::error::{Error, Result};
+use crate::geometry::{gpb, number};
+use crate::quote_identifier;
+use rusqlite::Connection;
+use std::fmt::Write;
+
+/// Updates `table`, or every feature table, and reports one line per table.
+/// Geometry values that are not BLOBs are skipped and counted in the report.
+pub fn update(conn: &mut Connection, table: Option<&str>) -> Result<String> {
+    let tx = conn.savepoint()?;
+    let tables = match table {
+        Some(table) => {
+            if features::geometry_column(&tx, table)?.is_none() {
+                return Err(Error::new(format!("{table} is not a registered feature table")));
+            }
+            vec![table.to_string()]
+        }
+        None if table_exists(&tx, "gpkg_geometry_columns")? => {
+            let mut stmt = tx.prepare("SELECT table_name FROM gpkg_geometry_columns ORDER BY table_name")?;
+            stmt.query_map([], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?
+        }
+        None => Vec::new(),
+    };
+    let mut out = String::new();
+    for table in &tables {
+        update_table(&tx, table, &mut out)?;
+    }
+    tx.commit()?;
+    Ok(out.trim_end().to_string())
+}
+
+fn update_table(conn: &Connection, table: &str, out: &mut String) -> Result<()> {
+    let column = features::geometry_column(conn, table)?
+        .ok_or_else(|| Error::new(format!("{table} is not a registered feature table")))?;
+    if !table_exists(conn, table)? {
+        writeln!(out, "{table}: table does not exist, skipped").unwrap();
+        return Ok(());
+    }
+    let key = features::primary_key(conn, table)?;
+    let (mut geometries, mut added, mut skipped) = (0, 0, 0);
+    let mut extent = None;
+    {
+        let mut select = conn.prepare(&format!(
+            "SELECT {}, {} FROM {} WHERE {1} NOT NULL",
+            quote_identifier(&key),
+            quote_identifier(&column.column),
+            quote_identifier(table)
+        ))?;
+        let mut update = conn.prepare(&format!(
+            "UPDATE {} SET {} = ?1 WHERE {} = ?2",
+            quote_identifier(table),
+            quote_identifier(&column.column),
+            quote_identifier(&key)
+        ))?;
+        let mut rows = select.query([])?;
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let Ok(blob) = row.get::<_, Vec<u8>>(1) else {
+                skipped += 1;
+                continue;
+            };
+            let error = |e: Error| Error::new(format!("{table} row {id}: {e}"));
+            let header = gpb::header(&blob).map_err(error)?;
+            geometries += 1;
+            if header.empty {
+                continue;
+            }
+            let envelope = match header.envelope {
+                Some(envelope) => Some(envelope),
+                None => {
+                    let decoded = gpb::decode(&blob).map_err(error)?;
+                    update.execute((gpb::encode(&decoded.geometry, decoded.dims, decoded.srs_id), id))?;
+                    added += 1;
+                    decoded.geometry.envelope(decoded.dims)
+                }
+            };
+            features::expand(&mut extent, envelope);
+        }
+    }
+    features::set_extent(conn, table, extent)?;
+
+    write!(out, "{table}: {geometries} geometries, {added} envelopes added, ").unwrap();
+    if skipped > 0 {
+        write!(out, "{skipped} non-BLOB values skipped, ").unwrap();
+    }
+    write!(out, "extent ").unwrap();
+    match extent {
+        Some(e) => writeln!(
+            out,
+            "[{}, {}, {}, {}]",
+            number(e.min_x),
+            number(e.min_y),
+            number(e.max_x),
+            number(e.max_y)
+        )
+        .unwrap(),
+        None => writeln!(out, "none").unwrap(),
+    }
+    Ok(())
+}
@@ -0,0 +1,178 @@
+//! Feature tables: creation, registration and `gpkg_contents` extents.
+use super::table_exists;
+use crate::error::{Error, Result};
+use crate::geometry::{Envelope, Geometry};
+use crate::quote_identifier;
+use rusqlite::{Connection, OptionalExtension};
+
+/// A row of `gpkg_geometry_columns`.
+#[derive(Debug, Clone)]
+pub struct GeometryColumn {
+    pub column: String,
+    pub type_name: String,
+    pub srs_id: i32,
+    pub z: i64,
+    pub m: i64,
+}
+
+impl GeometryColumn {
+    /// Whether the declared geometry type admits `geometry`: `GEOMETRY`
+    /// takes any type and `GEOMETRYCOLLECTION` the multi types as well.
+    pub fn accepts(&self, geometry: &Geometry) -> bool {
+        let actual = geometry.type_name();
+        match self.type_name.to_uppercase().as_str() {
+            "GEOMETRY" => true,
+            "GEOMETRYCOLLECTION" => actual.starts_with("MULTI") || actual == "GEOMETRYCOLLECTION",
+            declared => declared == actual,
+        }
+    }
+}
+
+pub fn geometry_column(conn: &Connection, table: &str) -> Result<Option<GeometryColumn>> {
+    if !table_exists(conn, "gpkg_geometry_columns")? {
+        return Ok(None);
+    }
+    let column = conn
+        .query_row(
+            "SELECT column_name, geometry_type_name, srs_id, z, m
+             FROM gpkg_geometry_columns WHERE table_name = ?1",
+            [table],
+            |row| {
+                Ok(GeometryColumn {
+                    column: row.get(0)?,
+                    type_name: row.get(1)?,
+                    srs_id: row.get(2)?,
+                    z: row.get(3)?,
+                    m: row.get(4)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(column)
+}
+
+pub fn srs_exists(conn: &Connection, srs_id: i32) -> Result<bool> {
+    let found = conn
+        .query_row("SELECT 1 FROM gpkg_spatial_ref_sys WHERE srs_id = ?1", [srs_id], |_| Ok(()))
+        .optional()?;
+    Ok(found.is_some())
+}
+
+/// The `z`/`m` flag for `gpkg_geometry_columns`: 0 prohibited, 1 mandatory, 2 optional.
+pub fn dimension_flag(with: usize, total: usize) -> i64 {
+    match with {
+        0 => 0,
+        n if n == total => 1,
+        _ => 2,
+    }
+}
+
+/// Creates a feature table with an `fid` primary key, the geometry column
+/// and `columns` (name, declared type), and registers it in
+/// `gpkg_contents` and `gpkg_geometry_columns`.
+pub fn create_feature_table(
+    conn: &Connection,
+    table: &str,
+    geometry: &GeometryColumn,
+    columns: &[(String, &str)],
+) -> Result<()> {
+    if table_exists(conn, table)? {
+        return Err(Error::new(format!("table {table} already exists")));
+    }
+    if !srs_exists(conn, geometry.srs_id)? {
+        return Err(Error::new(format!(
+            "srs_id {} is not defined in gpkg_spatial_ref_sys",
+            geometry.srs_id
+        )));
+    }
+    let mut ddl = format!(
+        "CREATE TABLE {} (fid INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL, {} {}",
+        quote_identifier(table),
+        quote_identifier(&geometry.column),
+        geometry.type_name
+    );
+    for (name, declared_type) in columns {
+        ddl.push_str(&format!(", {} {declared_type}", quote_identifier(name)));
+    }
+    ddl.push(')');
+    conn.execute(&ddl, [])?;
+    conn.execute(
+        "INSERT INTO gpkg_contents (table_name, data_type, identifier, srs_id)
+         VALUES (?1, 'features', ?1, ?2)",
+        (table, geometry.srs_id),
+    )?;
+    conn.execute(
+        "INSERT INTO gpkg_geometry_columns (table_name, column_name, geometry_type_name, srs_id, z, m)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        (table, &geometry.column, &geometry.type_name, geometry.srs_id, geometry.z, geometry.m),
+    )?;
+    Ok(())
+}
+
+/// Grows `extent` to include `envelope`.
+pub fn expand(extent: &mut Option<Envelope>, envelope: Option<Envelope>) {
+    let Some(e) = envelope else {
+        return;
+    };
+    match extent {
+        None => *extent = Some(Envelope { z: None, m: None, ..e }),
+        Some(x) => {
+            x.min_x = x.min_x.min(e.min_x);
+            x.max_x = x.max_x.max(e.max_x);
+            x.min_y = x.min_y.min(e.min_y);
+            x.max_y = x.max_y.max(e.max_y);
+        }
+    }
+}
+
+/// Widens the `gpkg_contents` extent of `table` to cover `extent` and bumps `last_change`.
+pub fn extend_contents(conn: &Connection, table: &str, extent: Option<Envelope>) -> Result<()> {
+    match extent {
+        Some(e) => conn.execute(
+            "UPDATE gpkg_contents SET
+               min_x = min(coalesce(min_x, ?2), ?2), min_y = min(coalesce(min_y, ?3), ?3),
+               max_x = max(coalesce(max_x, ?4), ?4), max_y = max(coalesce(max_y, ?5), ?5),
+               last_change = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+             WHERE table_name = ?1",
+            (table, e.min_x, e.min_y, e.max_x, e.max_y),
+        )?,
+        None => conn.execute(
+            "UPDATE gpkg_contents SET last_change = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+             WHERE table_name = ?1",
+            [table],
+        )?,
+    };
+    Ok(())
+}
+
+/// Sets the `gpkg_contents` extent of `table` to `extent` (NULL when there
+/// is none) and bumps `last_change`.
+pub fn set_extent(conn: &Connection, table: &str, extent: Option<Envelope>) -> Result<()> {
+    let (min_x, min_y, max_x, max_y) =
+        extent.map_or((None, None, None, None), |e| (Some(e.min_x), Some(e.min_y), Some(e.max_x), Some(e.max_y)));
+    conn.execute(
+        "UPDATE gpkg_contents SET min_x = ?2, min_y = ?3, max_x = ?4, max_y = ?5,
+           last_change = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+         WHERE table_name = ?1",
+        (table, min_x, min_y, max_x, max_y),
+    )?;
+    Ok(())
+}
+
+/// Names of the columns of `table`, in declaration order.
+pub fn column_names(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?1)")?;
+    let names = stmt.query_map([table], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+    Ok(names)
+}
+
+/// The integer primary key column of `table`, which feature tables must have.
+pub fn primary_key(conn: &Connection, table: &str) -> Result<String> {
+    let mut stmt = conn.prepare("SELECT name, type FROM pragma_table_info(?1) WHERE pk > 0")?;
+    let keys: Vec<(String, String)> =
+        stmt.query_map([table], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<rusqlite::Result<_>>()?;
+    match keys.as_slice() {
+        [(name, declared_type)] if declared_type.eq_ignore_ascii_case("INTEGER") => Ok(name.clone()),
+        _ => Err(Error::new(format!("{table} has no INTEGER PRIMARY KEY column"))),
+    }
+}
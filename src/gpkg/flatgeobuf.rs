@@ -0,0 +1,476 @@
+//! `GPKG_ExportFlatGeobuf(table, file)`: writes a feature table as
+//! FlatGeobuf 3 with a packed Hilbert R-tree, so clients can fetch only the
+//! features in a bounding box with HTTP range requests.
+//!
+//! Features are written in Hilbert order of their envelope centres. The
+//! file is produced in passes over the table (envelopes, feature sizes,
+//! features), so only the envelopes are held in memory. Rows without a
+//! geometry have no place in the index and are left out.
+use super::features::{self, GeometryColumn};
+use crate::error::{Error, Result};
+use crate::flatbuffers::{Field, Table};
+use crate::geometry::{Coord, Dims, Envelope, Geometry, gpb, number};
+use crate::quote_identifier;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OptionalExtension, Statement};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const MAGIC: [u8; 8] = [b'f', b'g', b'b', 3, b'f', b'g', b'b', 0];
+const NODE_SIZE: u64 = 16;
+/// Bytes per index node: an envelope and an offset.
+const NODE_BYTES: u64 = 40;
+
+/// FlatGeobuf `ColumnType` values used for the declared column types.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColumnType {
+    Bool = 2,
+    Long = 7,
+    Double = 10,
+    String = 11,
+    DateTime = 13,
+    Binary = 14,
+}
+
+impl ColumnType {
+    fn of(declared: &str) -> ColumnType {
+        let declared = declared.to_uppercase();
+        if declared == "BOOLEAN" {
+            ColumnType::Bool
+        } else if declared.contains("INT") {
+            ColumnType::Long
+        } else if ["REAL", "FLOA", "DOUB"].iter().any(|t| declared.contains(t)) {
+            ColumnType::Double
+        } else if declared.starts_with("DATE") {
+            ColumnType::DateTime
+        } else if declared == "BLOB" {
+            ColumnType::Binary
+        } else {
+            ColumnType::String
+        }
+    }
+}
+
+/// FlatGeobuf `GeometryType` of a GeoPackage geometry type name; 0 (Unknown)
+/// lets every feature carry its own type.
+fn geometry_type(type_name: &str) -> u8 {
+    match type_name.to_uppercase().as_str() {
+        "POINT" => 1,
+        "LINESTRING" => 2,
+        "POLYGON" => 3,
+        "MULTIPOINT" => 4,
+        "MULTILINESTRING" => 5,
+        "MULTIPOLYGON" => 6,
+        "GEOMETRYCOLLECTION" => 7,
+        _ => 0,
+    }
+}
+
+/// Position of a 16-bit (x, y) on the Hilbert curve.
+fn hilbert(x: u32, y: u32) -> u32 {
+    let mut a = x ^ y;
+    let mut b = 0xFFFF ^ a;
+    let mut c = 0xFFFF ^ (x | y);
+    let mut d = x & (y ^ 0xFFFF);
+
+    let mut aa = a | (b >> 1);
+    let mut bb = (a >> 1) ^ a;
+    let mut cc = ((c >> 1) ^ (b & (d >> 1))) ^ c;
+    let mut dd = ((a & (c >> 1)) ^ (d >> 1)) ^ d;
+
+    (a, b, c, d) = (aa, bb, cc, dd);
+    aa = (a & (a >> 2)) ^ (b & (b >> 2));
+    bb = (a & (b >> 2)) ^ (b & ((a ^ b) >> 2));
+    cc ^= (a & (c >> 2)) ^ (b & (d >> 2));
+    dd ^= (b & (c >> 2)) ^ ((a ^ b) & (d >> 2));
+
+    (a, b, c, d) = (aa, bb, cc, dd);
+    aa = (a & (a >> 4)) ^ (b & (b >> 4));
+    bb = (a & (b >> 4)) ^ (b & ((a ^ b) >> 4));
+    cc ^= (a & (c >> 4)) ^ (b & (d >> 4));
+    dd ^= (b & (c >> 4)) ^ ((a ^ b) & (d >> 4));
+
+    (a, b, c, d) = (aa, bb, cc, dd);
+    cc ^= (a & (c >> 8)) ^ (b & (d >> 8));
+    dd ^= (b & (c >> 8)) ^ ((a ^ b) & (d >> 8));
+
+    a = cc ^ (cc >> 1);
+    b = dd ^ (dd >> 1);
+    let spread = |mut v: u32| {
+        v = (v | (v << 8)) & 0x00FF_00FF;
+        v = (v | (v << 4)) & 0x0F0F_0F0F;
+        v = (v | (v << 2)) & 0x3333_3333;
+        (v | (v << 1)) & 0x5555_5555
+    };
+    let i0 = x ^ y;
+    let i1 = b | (0xFFFF ^ (i0 | a));
+    (spread(i1) << 1) | spread(i0)
+}
+
+/// Number of nodes per tree level, leaves first, as readers compute it.
+fn level_sizes(items: u64) -> Vec<u64> {
+    let mut sizes = vec![items];
+    let mut n = items;
+    loop {
+        n = n.div_ceil(NODE_SIZE);
+        sizes.push(n);
+        if n == 1 {
+            return sizes;
+        }
+    }
+}
+
+/// The packed R-tree over `leaves` (envelope, feature offset), root first.
+fn index(leaves: &[([f64; 4], u64)]) -> Vec<u8> {
+    let sizes = level_sizes(leaves.len() as u64);
+    let total: u64 = sizes.iter().sum();
+    // Levels are stored root first, so the leaves fill the end.
+    let mut starts = Vec::with_capacity(sizes.len());
+    let mut end = total;
+    for size in &sizes {
+        starts.push(end - size);
+        end -= size;
+    }
+    let mut nodes = vec![([f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY], 0); total as usize];
+    nodes[starts[0] as usize..].copy_from_slice(leaves);
+    for level in 0..sizes.len() - 1 {
+        let (start, end) = (starts[level], starts[level] + sizes[level]);
+        let mut parent = starts[level + 1] as usize;
+        let mut child = start;
+        while child < end {
+            let mut node = ([f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY], child);
+            for (bounds, _) in &nodes[child as usize..end.min(child + NODE_SIZE) as usize] {
+                node.0 = [node.0[0].min(bounds[0]), node.0[1].min(bounds[1]), node.0[2].max(bounds[2]), node.0[3].max(bounds[3])];
+            }
+            nodes[parent] = node;
+            parent += 1;
+            child += NODE_SIZE;
+        }
+    }
+    let mut out = Vec::with_capacity((total * NODE_BYTES) as usize);
+    for (bounds, offset) in nodes {
+        bounds.iter().for_each(|v| out.extend_from_slice(&v.to_le_bytes()));
+        out.extend_from_slice(&offset.to_le_bytes());
+    }
+    out
+}
+
+/// The FlatGeobuf `Geometry` table of `geometry`.
+fn geometry_table(geometry: &Geometry, dims: Dims, header: Dims) -> Table {
+    let mut xy = Vec::new();
+    let (mut z, mut m) = (Vec::new(), Vec::new());
+    let mut ends = Vec::new();
+    let mut push = |coords: &[Coord]| {
+        for c in coords {
+            xy.extend([c.x, c.y]);
+            if header.z {
+                z.push(if dims.z { c.z } else { f64::NAN });
+            }
+            if header.m {
+                m.push(if dims.m { c.m } else { f64::NAN });
+            }
+        }
+        (xy.len() / 2) as u32
+    };
+    let mut parts = Vec::new();
+    match geometry {
+        Geometry::Point(point) => {
+            push(point.as_slice());
+        }
+        Geometry::LineString(coords) | Geometry::MultiPoint(coords) => {
+            push(coords);
+        }
+        Geometry::Polygon(lines) | Geometry::MultiLineString(lines) => {
+            for line in lines {
+                ends.push(push(line));
+            }
+            if ends.len() < 2 {
+                ends.clear();
+            }
+        }
+        Geometry::MultiPolygon(polygons) => {
+            for polygon in polygons {
+                parts.push(geometry_table(&Geometry::Polygon(polygon.clone()), dims, header));
+            }
+        }
+        Geometry::GeometryCollection(geometries) => {
+            parts.extend(geometries.iter().map(|g| geometry_table(g, dims, header)));
+        }
+    }
+
+    let mut table = Table::new();
+    if !ends.is_empty() {
+        table = table.set(0, Field::U32s(ends));
+    }
+    if !xy.is_empty() {
+        table = table.set(1, Field::F64s(xy));
+    }
+    if !z.is_empty() {
+        table = table.set(2, Field::F64s(z));
+    }
+    if !m.is_empty() {
+        table = table.set(3, Field::F64s(m));
+    }
+    table = table.set(6, Field::U8(geometry.type_code() as u8));
+    if !parts.is_empty() {
+        table = table.set(7, Field::Tables(parts));
+    }
+    table
+}
+
+/// A length-prefixed string or binary value.
+fn sized(data: &[u8]) -> Vec<u8> {
+    let mut out = (data.len() as u32).to_le_bytes().to_vec();
+    out.extend_from_slice(data);
+    out
+}
+
+/// The encoding of a property value of `column_type`, `None` when the
+/// value cannot be represented.
+fn property(column_type: ColumnType, value: ValueRef<'_>) -> Option<Vec<u8>> {
+    match (column_type, value) {
+        (ColumnType::Bool, ValueRef::Integer(v)) => Some(vec![(v != 0) as u8]),
+        (ColumnType::Long, ValueRef::Integer(v)) => Some(v.to_le_bytes().to_vec()),
+        (ColumnType::Long, ValueRef::Real(v)) if v.fract() == 0.0 => Some((v as i64).to_le_bytes().to_vec()),
+        (ColumnType::Double, ValueRef::Integer(v)) => Some((v as f64).to_le_bytes().to_vec()),
+        (ColumnType::Double, ValueRef::Real(v)) => Some(v.to_le_bytes().to_vec()),
+        (ColumnType::String | ColumnType::DateTime, ValueRef::Text(text)) => Some(sized(text)),
+        (ColumnType::String, ValueRef::Integer(v)) => Some(sized(v.to_string().as_bytes())),
+        (ColumnType::String, ValueRef::Real(v)) => Some(sized(number(v).as_bytes())),
+        (ColumnType::Binary, ValueRef::Blob(data) | ValueRef::Text(data)) => Some(sized(data)),
+        _ => None,
+    }
+}
+
+/// The feature table to export: geometry column, primary key and the
+/// other columns with their FlatGeobuf types.
+struct Layer {
+    table: String,
+    geometry: GeometryColumn,
+    key: String,
+    columns: Vec<(String, ColumnType)>,
+    dims: Dims,
+}
+
+impl Layer {
+    fn new(conn: &Connection, table: &str) -> Result<Layer> {
+        let geometry = features::geometry_column(conn, table)?
+            .ok_or_else(|| Error::new(format!("{table} is not a registered feature table")))?;
+        let key = features::primary_key(conn, table)?;
+        let mut stmt = conn.prepare("SELECT name, type FROM pragma_table_info(?1) ORDER BY cid")?;
+        let columns = stmt
+            .query_map([table], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|(name, _)| !name.eq_ignore_ascii_case(&key) && !name.eq_ignore_ascii_case(&geometry.column))
+            .map(|(name, declared)| (name, ColumnType::of(&declared)))
+            .collect();
+        let dims = Dims { z: geometry.z != 0, m: geometry.m != 0 };
+        Ok(Layer { table: table.to_string(), geometry, key, columns, dims })
+    }
+
+    /// The `Header` table for `count` features within `extent`.
+    fn header(&self, conn: &Connection, count: u64, extent: Option<[f64; 4]>) -> Result<Vec<u8>> {
+        let columns = self
+            .columns
+            .iter()
+            .map(|(name, column_type)| {
+                Table::new().set(0, Field::String(name.clone())).set(1, Field::U8(*column_type as u8))
+            })
+            .collect();
+        let mut header = Table::new()
+            .set(0, Field::String(self.table.clone()))
+            .set(2, Field::U8(geometry_type(&self.geometry.type_name)))
+            .set(3, Field::Bool(self.dims.z))
+            .set(4, Field::Bool(self.dims.m))
+            .set(7, Field::Tables(columns))
+            .set(8, Field::U64(count))
+            .set(9, Field::U16(if count > 0 { NODE_SIZE as u16 } else { 0 }));
+        if let Some(extent) = extent {
+            header = header.set(1, Field::F64s(extent.to_vec()));
+        }
+        let srs: Option<(String, String, i32, String)> = conn
+            .query_row(
+                "SELECT srs_name, organization, organization_coordsys_id, definition
+                 FROM gpkg_spatial_ref_sys WHERE srs_id = ?1",
+                [self.geometry.srs_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?;
+        if let Some((name, organization, code, definition)) = srs
+            && self.geometry.srs_id > 0
+        {
+            let mut crs = Table::new()
+                .set(0, Field::String(organization))
+                .set(1, Field::I32(code))
+                .set(2, Field::String(name));
+            if definition != "undefined" {
+                crs = crs.set(4, Field::String(definition));
+            }
+            header = header.set(10, Field::Table(crs));
+        }
+        Ok(header.finish())
+    }
+
+    fn select<'c>(&self, conn: &'c Connection) -> Result<Statement<'c>> {
+        let mut columns = vec![quote_identifier(&self.geometry.column)];
+        columns.extend(self.columns.iter().map(|(name, _)| quote_identifier(name)));
+        Ok(conn.prepare(&format!(
+            "SELECT {} FROM {} WHERE {} = ?1",
+            columns.join(", "),
+            quote_identifier(&self.table),
+            quote_identifier(&self.key)
+        ))?)
+    }
+
+    /// The size-prefixed `Feature` with primary key `id`.
+    fn feature(&self, select: &mut Statement<'_>, id: i64) -> Result<Vec<u8>> {
+        let mut rows = select.query([id])?;
+        let row = rows.next()?.ok_or_else(|| Error::new(format!("{} row {id} disappeared", self.table)))?;
+        let blob: Vec<u8> = row.get(0)?;
+        let decoded = gpb::decode(&blob).map_err(|e| Error::new(format!("{} row {id}: {e}", self.table)))?;
+        let mut properties = Vec::new();
+        for (i, (name, column_type)) in self.columns.iter().enumerate() {
+            let value = row.get_ref(i + 1)?;
+            if value == ValueRef::Null {
+                continue;
+            }
+            let encoded = property(*column_type, value).ok_or_else(|| {
+                Error::new(format!("{} row {id}: {name} holds a value that is not {column_type:?}", self.table))
+            })?;
+            properties.extend_from_slice(&(i as u16).to_le_bytes());
+            properties.extend(encoded);
+        }
+        let mut feature = Table::new().set(0, Field::Table(geometry_table(&decoded.geometry, decoded.dims, self.dims)));
+        if !properties.is_empty() {
+            feature = feature.set(1, Field::Bytes(properties));
+        }
+        let buf = feature.finish();
+        let mut out = (buf.len() as u32).to_le_bytes().to_vec();
+        out.extend(buf);
+        Ok(out)
+    }
+}
+
+/// Writes `table` to the new file `path` and returns the number of
+/// features written.
+pub fn export(conn: &Connection, table: &str, path: &str) -> Result<i64> {
+    let layer = Layer::new(conn, table)?;
+    if Path::new(path).exists() {
+        return Err(Error::new(format!("{path} already exists")));
+    }
+
+    // Pass 1: envelopes, sorted along the Hilbert curve.
+    let mut items: Vec<(i64, [f64; 4])> = Vec::new();
+    {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, {} FROM {} WHERE {1} NOT NULL",
+            quote_identifier(&layer.key),
+            quote_identifier(&layer.geometry.column),
+            quote_identifier(table)
+        ))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let blob: Vec<u8> = row.get(1)?;
+            let envelope = gpb::envelope(&blob).map_err(|e| Error::new(format!("{table} row {id}: {e}")))?;
+            if let Some(Envelope { min_x, min_y, max_x, max_y, .. }) = envelope {
+                items.push((id, [min_x, min_y, max_x, max_y]));
+            }
+        }
+    }
+    let extent = items.iter().map(|(_, b)| *b).reduce(|a, b| {
+        [a[0].min(b[0]), a[1].min(b[1]), a[2].max(b[2]), a[3].max(b[3])]
+    });
+    if let Some([min_x, min_y, max_x, max_y]) = extent {
+        let scale = |v: f64, min: f64, max: f64| {
+            if max > min { ((v - min) / (max - min) * 65535.0) as u32 } else { 0 }
+        };
+        items.sort_by_cached_key(|(_, b)| {
+            hilbert(scale((b[0] + b[2]) / 2.0, min_x, max_x), scale((b[1] + b[3]) / 2.0, min_y, max_y))
+        });
+    }
+
+    // Pass 2: feature sizes, which give the offsets the index points to.
+    let mut select = layer.select(conn)?;
+    let mut leaves = Vec::with_capacity(items.len());
+    let mut offset = 0;
+    for (id, bounds) in &items {
+        leaves.push((*bounds, offset));
+        offset += layer.feature(&mut select, *id)?.len() as u64;
+    }
+
+    // Pass 3: the file itself.
+    let header = layer.header(conn, items.len() as u64, extent)?;
+    let file = File::create_new(path).map_err(|e| Error::new(format!("cannot create {path}: {e}")))?;
+    let mut out = BufWriter::new(file);
+    let written = (|| -> Result<()> {
+        out.write_all(&MAGIC)?;
+        out.write_all(&(header.len() as u32).to_le_bytes())?;
+        out.write_all(&header)?;
+        if !items.is_empty() {
+            out.write_all(&index(&leaves))?;
+        }
+        for (id, _) in &items {
+            out.write_all(&layer.feature(&mut select, *id)?)?;
+        }
+        out.flush()?;
+        Ok(())
+    })();
+    if let Err(e) = written {
+        let _ = fs::remove_file(path);
+        return Err(e);
+    }
+    Ok(items.len() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hilbert_curve() {
+        assert_eq!(hilbert(0, 0), 0);
+        assert_eq!(hilbert(0xFFFF, 0), u32::MAX);
+        // The first 4^k positions fill the 2^k square at the origin, and
+        // consecutive positions are neighbouring cells.
+        let mut cells: Vec<(u32, (u32, u32))> =
+            (0..16).flat_map(|x| (0..16).map(move |y| (hilbert(x, y), (x, y)))).collect();
+        cells.sort();
+        assert!(cells.iter().enumerate().all(|(i, (h, _))| *h == i as u32));
+        for pair in cells.windows(2) {
+            let ((_, (x0, y0)), (_, (x1, y1))) = (pair[0], pair[1]);
+            assert_eq!(x0.abs_diff(x1) + y0.abs_diff(y1), 1, "{pair:?}");
+        }
+    }
+
+    #[test]
+    fn levels() {
+        assert_eq!(level_sizes(1), [1, 1]);
+        assert_eq!(level_sizes(16), [16, 1]);
+        assert_eq!(level_sizes(17), [17, 2, 1]);
+        assert_eq!(level_sizes(257), [257, 17, 2, 1]);
+    }
+
+    #[test]
+    fn packed_rtree() {
+        let leaves: Vec<([f64; 4], u64)> =
+            (0..17).map(|i| ([i as f64, 0.0, i as f64 + 1.0, 1.0], 100 * i)).collect();
+        let bytes = index(&leaves);
+        assert_eq!(bytes.len() as u64, 20 * NODE_BYTES);
+        let node = |i: usize| {
+            let at = i * NODE_BYTES as usize;
+            let bounds: [f64; 4] =
+                std::array::from_fn(|j| f64::from_le_bytes(bytes[at + 8 * j..at + 8 * j + 8].try_into().unwrap()));
+            (bounds, u64::from_le_bytes(bytes[at + 32..at + 40].try_into().unwrap()))
+        };
+        // Root, then the two parents, then the leaves; parents point to
+        // their first child.
+        assert_eq!(node(0), ([0.0, 0.0, 17.0, 1.0], 1));
+        assert_eq!(node(1), ([0.0, 0.0, 16.0, 1.0], 3));
+        assert_eq!(node(2), ([16.0, 0.0, 17.0, 1.0], 19));
+        assert_eq!(node(3), leaves[0]);
+        assert_eq!(node(19), leaves[16]);
+    }
+}
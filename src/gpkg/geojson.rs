@@ -0,0 +1,242 @@
+//! `GPKG_ImportGeoJSON(file, table, ?srs_id?)`: loads a GeoJSON file into a feature table.
+use super::features::{self, GeometryColumn};
+use super::create::create_in;
+use super::table_exists;
+use crate::error::{Error, Result};
+use crate::geometry::{Dims, Geometry, geojson, gpb};
+use crate::json::{self, Json};
+use crate::quote_identifier;
+use rusqlite::Connection;
+use rusqlite::types::Value;
+use std::collections::HashSet;
+use std::fs;
+
+/// Declared column type inferred from the property values of all features.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColumnType {
+    Unknown,
+    Boolean,
+    Integer,
+    Double,
+    Text,
+}
+
+impl ColumnType {
+    fn of(value: &Json) -> ColumnType {
+        match value {
+            Json::Null => ColumnType::Unknown,
+            Json::Bool(_) => ColumnType::Boolean,
+            Json::Integer(_) => ColumnType::Integer,
+            Json::Real(_) => ColumnType::Double,
+            _ => ColumnType::Text,
+        }
+    }
+
+    fn merge(self, other: ColumnType) -> ColumnType {
+        use ColumnType::*;
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Unknown, b) => b,
+            (a, Unknown) => a,
+            (Integer, Double) | (Double, Integer) => Double,
+            _ => Text,
+        }
+    }
+
+    fn declared(self) -> &'static str {
+        match self {
+            ColumnType::Boolean => "BOOLEAN",
+            ColumnType::Integer => "INTEGER",
+            ColumnType::Double => "DOUBLE",
+            ColumnType::Unknown | ColumnType::Text => "TEXT",
+        }
+    }
+}
+
+fn sql_value(value: &Json) -> Value {
+    match value {
+        Json::Null => Value::Null,
+        Json::Bool(b) => Value::Integer(*b as i64),
+        Json::Integer(i) => Value::Integer(*i),
+        Json::Real(r) => Value::Real(*r),
+        Json::String(s) => Value::Text(s.clone()),
+        Json::Array(_) | Json::Object(_) => Value::Text(value.to_json()),
+    }
+}
+
+struct Feature<'a> {
+    geometry: Option<(Geometry, Dims)>,
+    properties: &'a [(String, Json)],
+}
+
+fn feature(json: &Json) -> Result<Feature<'_>> {
+    let geometry = match json.get("geometry") {
+        None | Some(Json::Null) => None,
+        Some(geometry) => Some(geojson::read(geometry)?),
+    };
+    let properties = match json.get("properties") {
+        Some(Json::Object(members)) => members.as_slice(),
+        _ => &[],
+    };
+    Ok(Feature { geometry, properties })
+}
+
+/// The features of a FeatureCollection, a single Feature or a bare geometry.
+fn features(doc: &Json) -> Result<Vec<Feature<'_>>> {
+    match doc.get("type").and_then(Json::as_str) {
+        Some("FeatureCollection") => doc
+            .get("features")
+            .and_then(Json::as_array)
+            .ok_or_else(|| Error::new("FeatureCollection without features"))?
+            .iter()
+            .map(feature)
+            .collect(),
+        Some("Feature") => Ok(vec![feature(doc)?]),
+        Some(_) => Ok(vec![Feature { geometry: Some(geojson::read(doc)?), properties: &[] }]),
+        None => Err(Error::new("not a GeoJSON object")),
+    }
+}
+
+/// Maps property names to column names that cannot clash with `taken`
+/// (compared case-insensitively, like SQLite does).
+fn column_name(name: &str, taken: &mut HashSet<String>) -> String {
+    let mut candidate = name.to_string();
+    let mut n = 1;
+    while !taken.insert(candidate.to_lowercase()) {
+        candidate = format!("{name}_{n}");
+        n += 1;
+    }
+    candidate
+}
+
+/// Imports the features of a GeoJSON file into `table`, creating and
+/// registering the table in `srs_id` (default 4326) if needed. Appending to
+/// an existing table requires its SRS and a compatible geometry type.
+/// Returns the number of features imported.
+pub fn import(conn: &mut Connection, path: &str, table: &str, srs_id: Option<i32>) -> Result<i64> {
+    let text = fs::read_to_string(path).map_err(|e| Error::new(format!("cannot read {path}: {e}")))?;
+    let doc = json::parse(&text)?;
+    let features = features(&doc)?;
+
+    // Property columns in order of first appearance, with their merged types.
+    let mut properties: Vec<(&str, ColumnType)> = Vec::new();
+    for feature in &features {
+        for (name, value) in feature.properties {
+            match properties.iter_mut().find(|(n, _)| n == name) {
+                Some((_, t)) => *t = t.merge(ColumnType::of(value)),
+                None => properties.push((name, ColumnType::of(value))),
+            }
+        }
+    }
+
+    let tx = conn.savepoint()?;
+    create_in(&tx)?;
+
+    let geometry_column = match features::geometry_column(&tx, table)? {
+        Some(column) => {
+            if let Some(srs_id) = srs_id.filter(|&s| s != column.srs_id) {
+                return Err(Error::new(format!(
+                    "{table} uses srs_id {}, not {srs_id}; reproject the file or import it into another table",
+                    column.srs_id
+                )));
+            }
+            column
+        }
+        None if table_exists(&tx, table)? => {
+            return Err(Error::new(format!("{table} exists but is not a registered feature table")));
+        }
+        None => {
+            let geometries: Vec<&(Geometry, Dims)> = features.iter().filter_map(|f| f.geometry.as_ref()).collect();
+            let mut types = geometries.iter().map(|(g, _)| g.type_name());
+            let first = types.next();
+            let type_name = match first {
+                Some(name) if types.all(|t| t == name) => name,
+                _ => "GEOMETRY",
+            };
+            let with_z = geometries.iter().filter(|(_, dims)| dims.z).count();
+            let column = GeometryColumn {
+                column: "geom".to_string(),
+                type_name: type_name.to_string(),
+                srs_id: srs_id.unwrap_or(4326),
+                z: features::dimension_flag(with_z, geometries.len()),
+                m: 0,
+            };
+            features::create_feature_table(&tx, table, &column, &[])?;
+            column
+        }
+    };
+
+    // Appended geometries must pass GPKG_Validate like the existing ones.
+    let column = &geometry_column;
+    for (i, feature) in features.iter().enumerate() {
+        let Some((geometry, dims)) = &feature.geometry else {
+            continue;
+        };
+        let problem = if !column.accepts(geometry) {
+            format!("{} does not fit the {} column of {table}", geometry.type_name(), column.type_name)
+        } else if (column.z == 0 && dims.z) || (column.z == 1 && !dims.z) {
+            format!("z values conflict with z = {} of {table}", column.z)
+        } else if column.m == 1 {
+            format!("{table} requires m values, which GeoJSON cannot carry")
+        } else {
+            continue;
+        };
+        return Err(Error::new(format!("feature {}: {problem}", i + 1)));
+    }
+
+    // Add a column per property the table does not have yet.
+    let existing = features::column_names(&tx, table)?;
+    let mut taken: HashSet<String> = existing.iter().map(|c| c.to_lowercase()).collect();
+    let mut targets = Vec::with_capacity(properties.len());
+    for (name, column_type) in &properties {
+        let reserved = |c: &String| c.eq_ignore_ascii_case(&geometry_column.column) || c.eq_ignore_ascii_case("fid");
+        if let Some(column) = existing.iter().find(|c| c.eq_ignore_ascii_case(name) && !reserved(c)) {
+            targets.push(column.clone());
+            continue;
+        }
+        let column = column_name(name, &mut taken);
+        tx.execute(
+            &format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                quote_identifier(table),
+                quote_identifier(&column),
+                column_type.declared()
+            ),
+            [],
+        )?;
+        targets.push(column);
+    }
+
+    let mut columns = vec![quote_identifier(&geometry_column.column)];
+    columns.extend(targets.iter().map(|c| quote_identifier(c)));
+    let placeholders: Vec<String> = (1..=columns.len()).map(|i| format!("?{i}")).collect();
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        quote_identifier(table),
+        columns.join(", "),
+        placeholders.join(", ")
+    );
+
+    let mut extent = None;
+    {
+        let mut insert = tx.prepare(&sql)?;
+        for feature in &features {
+            let mut values = Vec::with_capacity(columns.len());
+            values.push(match &feature.geometry {
+                Some((geometry, dims)) => {
+                    features::expand(&mut extent, geometry.envelope(*dims));
+                    Value::Blob(gpb::encode(geometry, *dims, geometry_column.srs_id))
+                }
+                None => Value::Null,
+            });
+            for (name, _) in &properties {
+                let value = feature.properties.iter().find(|(n, _)| n == name).map(|(_, v)| v);
+                values.push(value.map_or(Value::Null, sql_value));
+            }
+            insert.execute(rusqlite::params_from_iter(values))?;
+        }
+    }
+    features::extend_contents(&tx, table, extent)?;
+    tx.commit()?;
+    Ok(features.len() as i64)
+}
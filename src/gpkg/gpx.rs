@@ -0,0 +1,162 @@
+//! `GPKG_ImportGPX(file, ?prefix?)`: loads the waypoints, routes and tracks
+//! of a GPX 1.0/1.1 file into separate feature tables.
+//!
+//! Waypoints become `<prefix>_waypoints` (POINT), routes `<prefix>_routes`
+//! (LINESTRING) and tracks `<prefix>_tracks` (MULTILINESTRING, one line per
+//! segment), all in EPSG:4326. Elevations become z values when every point
+//! of a table has one.
+use super::create::create_in;
+use super::features::{self, GeometryColumn};
+use super::table_exists;
+use crate::error::{Error, Result};
+use crate::geometry::{Coord, Dims, Geometry, gpb};
+use crate::quote_identifier;
+use crate::xml::{self, Element};
+use rusqlite::Connection;
+use rusqlite::types::Value;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+const WAYPOINT_COLUMNS: [(&str, &str); 6] =
+    [("name", "TEXT"), ("ele", "DOUBLE"), ("time", "DATETIME"), ("desc", "TEXT"), ("sym", "TEXT"), ("type", "TEXT")];
+const LINE_COLUMNS: [(&str, &str); 4] = [("name", "TEXT"), ("desc", "TEXT"), ("type", "TEXT"), ("number", "INTEGER")];
+
+/// A point with its elevation, if given.
+fn point(path: &str, element: &Element) -> Result<(Coord, bool)> {
+    let coordinate = |name: &str| {
+        element.attribute(name).and_then(|v| v.trim().parse::<f64>().ok()).ok_or_else(|| {
+            Error::new(format!("{path}: <{}> without a valid {name} attribute", element.name))
+        })
+    };
+    let ele = element.child_text("ele").and_then(|e| e.parse::<f64>().ok());
+    let coord = Coord { x: coordinate("lon")?, y: coordinate("lat")?, z: ele.unwrap_or(0.0), m: 0.0 };
+    Ok((coord, ele.is_some()))
+}
+
+fn points<'a>(path: &str, elements: impl Iterator<Item = &'a Element>) -> Result<(Vec<Coord>, bool)> {
+    let mut all_ele = true;
+    let mut coords = Vec::new();
+    for element in elements {
+        let (coord, ele) = point(path, element)?;
+        all_ele &= ele;
+        coords.push(coord);
+    }
+    Ok((coords, all_ele))
+}
+
+fn text_value(element: &Element, name: &str) -> Value {
+    element.child_text(name).map_or(Value::Null, |t| Value::Text(t.to_string()))
+}
+
+/// A feature to insert: geometry, whether all its points have elevations,
+/// and the attribute values in column order.
+type Feature = (Geometry, bool, Vec<Value>);
+
+/// Creates `table` with `columns` and inserts `rows`. Returns the row count.
+fn load(conn: &Connection, table: &str, type_name: &str, columns: &[(&str, &str)], rows: Vec<Feature>) -> Result<usize> {
+    let z = rows.iter().all(|(_, ele, _)| *ele);
+    let dims = Dims { z, m: false };
+    let geometry_column =
+        GeometryColumn { column: "geom".to_string(), type_name: type_name.to_string(), srs_id: 4326, z: z as i64, m: 0 };
+    let declared: Vec<(String, &str)> = columns.iter().map(|(name, t)| (name.to_string(), *t)).collect();
+    features::create_feature_table(conn, table, &geometry_column, &declared)?;
+
+    let mut names = vec![quote_identifier(&geometry_column.column)];
+    names.extend(columns.iter().map(|(name, _)| quote_identifier(name)));
+    let placeholders: Vec<String> = (1..=names.len()).map(|i| format!("?{i}")).collect();
+    let mut insert = conn.prepare(&format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        quote_identifier(table),
+        names.join(", "),
+        placeholders.join(", ")
+    ))?;
+    let mut extent = None;
+    let count = rows.len();
+    for (geometry, _, values) in rows {
+        features::expand(&mut extent, geometry.envelope(dims));
+        let mut params = vec![Value::Blob(gpb::encode(&geometry, dims, 4326))];
+        params.extend(values);
+        insert.execute(rusqlite::params_from_iter(params))?;
+    }
+    features::extend_contents(conn, table, extent)?;
+    Ok(count)
+}
+
+/// Imports `path` into the tables `<prefix>_waypoints`, `_routes` and
+/// `_tracks`; `prefix` defaults to the file name without extension. Only
+/// the tables that get features are created. Reports one line per table.
+pub fn import(conn: &mut Connection, path: &str, prefix: Option<&str>) -> Result<String> {
+    let text = fs::read_to_string(path).map_err(|e| Error::new(format!("cannot read {path}: {e}")))?;
+    let gpx = xml::parse(&text).map_err(|e| Error::new(format!("{path}: {e}")))?;
+    if gpx.name != "gpx" {
+        return Err(Error::new(format!("{path} is not a GPX file")));
+    }
+    let prefix = match prefix {
+        Some(prefix) => prefix.to_string(),
+        None => Path::new(path).file_stem().map_or("gpx".into(), |s| s.to_string_lossy().into_owned()),
+    };
+
+    let mut waypoints = Vec::new();
+    for wpt in gpx.children("wpt") {
+        let (coord, ele) = point(path, wpt)?;
+        let values = vec![
+            text_value(wpt, "name"),
+            wpt.child_text("ele").and_then(|e| e.parse().ok()).map_or(Value::Null, Value::Real),
+            text_value(wpt, "time"),
+            text_value(wpt, "desc"),
+            text_value(wpt, "sym"),
+            text_value(wpt, "type"),
+        ];
+        waypoints.push((Geometry::Point(Some(coord)), ele, values));
+    }
+    let line_values = |element: &Element| {
+        vec![
+            text_value(element, "name"),
+            text_value(element, "desc"),
+            text_value(element, "type"),
+            element.child_text("number").and_then(|n| n.parse().ok()).map_or(Value::Null, Value::Integer),
+        ]
+    };
+    let mut routes = Vec::new();
+    for rte in gpx.children("rte") {
+        let (coords, ele) = points(path, rte.children("rtept"))?;
+        routes.push((Geometry::LineString(coords), ele, line_values(rte)));
+    }
+    let mut tracks = Vec::new();
+    for trk in gpx.children("trk") {
+        let mut segments = Vec::new();
+        let mut all_ele = true;
+        for segment in trk.children("trkseg") {
+            let (coords, ele) = points(path, segment.children("trkpt"))?;
+            all_ele &= ele;
+            segments.push(coords);
+        }
+        tracks.push((Geometry::MultiLineString(segments), all_ele, line_values(trk)));
+    }
+
+    let tx = conn.savepoint()?;
+    create_in(&tx)?;
+    let mut out = String::new();
+    let layers = [
+        ("waypoints", "POINT", &WAYPOINT_COLUMNS[..], waypoints),
+        ("routes", "LINESTRING", &LINE_COLUMNS[..], routes),
+        ("tracks", "MULTILINESTRING", &LINE_COLUMNS[..], tracks),
+    ];
+    for (layer, type_name, columns, rows) in layers {
+        if rows.is_empty() {
+            continue;
+        }
+        let table = format!("{prefix}_{layer}");
+        if table_exists(&tx, &table)? {
+            return Err(Error::new(format!("table {table} already exists")));
+        }
+        let count = load(&tx, &table, type_name, columns, rows)?;
+        writeln!(out, "{table}: {count} {layer}").unwrap();
+    }
+    if out.is_empty() {
+        return Err(Error::new(format!("{path} has no waypoints, routes or tracks")));
+    }
+    tx.commit()?;
+    Ok(out.trim_end().to_string())
+}
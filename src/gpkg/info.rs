@@ -0,0 +1,288 @@
+//! `GPKG_Info()`: a human-readable summary of a GeoPackage.
+use super::{application_id, features, is_geopackage, metadata, rtree, table_exists, user_version};
+use crate::error::{Error, Result};
+use crate::quote_identifier;
+use rusqlite::{Connection, OptionalExtension};
+use std::fmt::Write;
+
+pub fn info(conn: &Connection) -> Result<String> {
+    let mut out = String::new();
+    let id = application_id(conn)?;
+    if is_geopackage(conn)? {
+        let version = user_version(conn)?;
+        writeln!(
+            out,
+            "GeoPackage {}.{}.{} (application_id 0x{id:08X}, user_version {version})",
+            version / 10000,
+            version / 100 % 100,
+            version % 100
+        )
+        .unwrap();
+    } else {
+        writeln!(out, "Not a GeoPackage (application_id 0x{id:08X})").unwrap();
+    }
+
+    if table_exists(conn, "gpkg_contents")? {
+        contents(conn, &mut out)?;
+    }
+    if table_exists(conn, "gpkg_spatial_ref_sys")? {
+        spatial_ref_sys(conn, &mut out)?;
+    }
+    if table_exists(conn, "gpkg_extensions")? {
+        extensions(conn, &mut out)?;
+    }
+    Ok(out.trim_end().to_string())
+}
+
+/// Details of a single `gpkg_contents` entry.
+pub fn table_info(conn: &Connection, table: &str) -> Result<String> {
+    if !table_exists(conn, "gpkg_contents")? {
+        return Err(Error::new("not a GeoPackage: gpkg_contents does not exist"));
+    }
+    let (data_type, identifier, description, last_change, srs_id, extent) = conn
+        .query_row(
+            "SELECT data_type, identifier, description, last_change, srs_id,
+                    min_x, min_y, max_x, max_y
+             FROM gpkg_contents WHERE table_name = ?1",
+            [table],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<i64>>(4)?,
+                    extent(row.get(5)?, row.get(6)?, row.get(7)?, row.get(8)?),
+                ))
+            },
+        )
+        .optional()?
+        .ok_or_else(|| Error::new(format!("{table} is not registered in gpkg_contents")))?;
+
+    let mut out = String::new();
+    writeln!(out, "{table} ({data_type})").unwrap();
+    if let Some(identifier) = identifier.filter(|s| !s.is_empty() && s != table) {
+        writeln!(out, "  identifier:  {identifier}").unwrap();
+    }
+    if let Some(description) = description.filter(|s| !s.is_empty()) {
+        writeln!(out, "  description: {description}").unwrap();
+    }
+    if let Some(last_change) = last_change {
+        writeln!(out, "  last change: {last_change}").unwrap();
+    }
+    if let Some(srs_id) = srs_id {
+        let name = if table_exists(conn, "gpkg_spatial_ref_sys")? {
+            conn.query_row(
+                "SELECT srs_name FROM gpkg_spatial_ref_sys WHERE srs_id = ?1",
+                [srs_id],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+        } else {
+            None
+        };
+        let name = name.map(|name| format!(" ({name})")).unwrap_or_default();
+        writeln!(out, "  srs:         {srs_id}{name}").unwrap();
+    }
+    if let Some(extent) = extent {
+        writeln!(out, "  extent:      {extent}").unwrap();
+    }
+    if let Some(geometry) = geometry_column(conn, table)? {
+        writeln!(out, "  geometry:    {geometry}").unwrap();
+        let index = rtree::status(conn, table)?.unwrap_or_else(|| "none".to_string());
+        writeln!(out, "  rtree index: {index}").unwrap();
+    }
+    if let Some(zoom) = zoom_levels(conn, table)? {
+        writeln!(out, "  zoom levels: {zoom}").unwrap();
+    }
+    for (i, line) in metadata::linked(conn, table)?.iter().enumerate() {
+        let label = if i == 0 { "metadata:" } else { "" };
+        writeln!(out, "  {label:<12} {line}").unwrap();
+    }
+    if table_exists(conn, table)? {
+        let rows: i64 =
+            conn.query_row(&format!("SELECT count(*) FROM {}", quote_identifier(table)), [], |row| row.get(0))?;
+        writeln!(out, "  rows:        {rows}").unwrap();
+    } else {
+        writeln!(out, "  rows:        table does not exist").unwrap();
+    }
+    Ok(out.trim_end().to_string())
+}
+
+/// The `CREATE` statements of `table` and its indexes and triggers, like
+/// the shell's `.schema`. Feature tables get a leading comment block with
+/// what the statements do not say: geometry type, SRS, z/m, extent and
+/// spatial index.
+pub fn schema(conn: &Connection, table: &str) -> Result<String> {
+    let mut stmt = conn.prepare(
+        "SELECT sql FROM sqlite_schema WHERE tbl_name = ?1 AND sql NOT NULL
+         ORDER BY type NOT IN ('table', 'view'), type, name",
+    )?;
+    let statements: Vec<String> = stmt.query_map([table], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+    if statements.is_empty() {
+        return Err(Error::new(format!("table {table} does not exist")));
+    }
+
+    let mut out = String::new();
+    if let Some(column) = features::geometry_column(conn, table)? {
+        let flag = |value: i64| match value {
+            0 => "prohibited",
+            1 => "mandatory",
+            _ => "optional",
+        };
+        let srs_name: Option<String> = conn
+            .query_row("SELECT srs_name FROM gpkg_spatial_ref_sys WHERE srs_id = ?1", [column.srs_id], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        let extent = conn
+            .query_row(
+                "SELECT min_x, min_y, max_x, max_y FROM gpkg_contents WHERE table_name = ?1",
+                [table],
+                |row| Ok(extent(row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?
+            .flatten();
+        writeln!(out, "-- GeoPackage feature table").unwrap();
+        writeln!(out, "--   geometry: {} in {}", column.type_name, column.column).unwrap();
+        writeln!(out, "--   srs:      {} ({})", column.srs_id, srs_name.as_deref().unwrap_or("undefined")).unwrap();
+        writeln!(out, "--   z/m:      z {}, m {}", flag(column.z), flag(column.m)).unwrap();
+        writeln!(out, "--   extent:   {}", extent.as_deref().unwrap_or("not set")).unwrap();
+        let index = rtree::status(conn, table)?.unwrap_or_else(|| "none".to_string());
+        writeln!(out, "--   rtree:    {index}").unwrap();
+    }
+    for sql in statements {
+        writeln!(out, "{sql};").unwrap();
+    }
+    Ok(out.trim_end().to_string())
+}
+
+fn extent(min_x: Option<f64>, min_y: Option<f64>, max_x: Option<f64>, max_y: Option<f64>) -> Option<String> {
+    match (min_x, min_y, max_x, max_y) {
+        (Some(a), Some(b), Some(c), Some(d)) => Some(format!("[{a}, {b}, {c}, {d}]")),
+        _ => None,
+    }
+}
+
+/// `POINT Z in geom` for a registered feature table.
+fn geometry_column(conn: &Connection, table: &str) -> Result<Option<String>> {
+    if !table_exists(conn, "gpkg_geometry_columns")? {
+        return Ok(None);
+    }
+    let geometry = conn
+        .query_row(
+            "SELECT column_name, geometry_type_name, z, m
+             FROM gpkg_geometry_columns WHERE table_name = ?1",
+            [table],
+            |row| {
+                let dims = match (row.get::<_, i64>(2)?, row.get::<_, i64>(3)?) {
+                    (0, 0) => "",
+                    (0, _) => " M",
+                    (_, 0) => " Z",
+                    _ => " ZM",
+                };
+                Ok(format!("{}{dims} in {}", row.get::<_, String>(1)?, row.get::<_, String>(0)?))
+            },
+        )
+        .optional()?;
+    Ok(geometry)
+}
+
+/// `0-18` for a registered tile pyramid.
+fn zoom_levels(conn: &Connection, table: &str) -> Result<Option<String>> {
+    if !table_exists(conn, "gpkg_tile_matrix")? {
+        return Ok(None);
+    }
+    let zoom = conn.query_row(
+        "SELECT min(zoom_level), max(zoom_level) FROM gpkg_tile_matrix WHERE table_name = ?1",
+        [table],
+        |row| Ok((row.get::<_, Option<i64>>(0)?, row.get::<_, Option<i64>>(1)?)),
+    )?;
+    Ok(match zoom {
+        (Some(min), Some(max)) => Some(format!("{min}-{max}")),
+        _ => None,
+    })
+}
+
+fn contents(conn: &Connection, out: &mut String) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT table_name, data_type, srs_id, min_x, min_y, max_x, max_y
+         FROM gpkg_contents ORDER BY data_type, table_name",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<i64>>(2)?,
+                extent(row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?),
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    writeln!(out, "\nContents ({}):", rows.len()).unwrap();
+    for (table, data_type, srs_id, extent) in rows {
+        let mut line = format!("  {data_type:<10} {table}");
+        if let Some(geometry) = geometry_column(conn, &table)? {
+            write!(line, " {geometry}").unwrap();
+        }
+        if let Some(zoom) = zoom_levels(conn, &table)? {
+            write!(line, " zoom {zoom}").unwrap();
+        }
+        if let Some(srs_id) = srs_id {
+            write!(line, ", srs {srs_id}").unwrap();
+        }
+        if let Some(extent) = extent {
+            write!(line, ", extent {extent}").unwrap();
+        }
+        writeln!(out, "{line}").unwrap();
+    }
+    Ok(())
+}
+
+fn spatial_ref_sys(conn: &Connection, out: &mut String) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT srs_id, srs_name, organization, organization_coordsys_id
+         FROM gpkg_spatial_ref_sys ORDER BY srs_id",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(format!(
+                "  {:>6}  {} ({}:{})",
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    writeln!(out, "\nSpatial reference systems ({}):", rows.len()).unwrap();
+    for row in rows {
+        writeln!(out, "{row}").unwrap();
+    }
+    Ok(())
+}
+
+fn extensions(conn: &Connection, out: &mut String) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT extension_name, table_name, column_name, scope
+         FROM gpkg_extensions ORDER BY extension_name, table_name, column_name",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            let target = match (row.get::<_, Option<String>>(1)?, row.get::<_, Option<String>>(2)?) {
+                (Some(table), Some(column)) => format!(" on {table}.{column}"),
+                (Some(table), None) => format!(" on {table}"),
+                _ => String::new(),
+            };
+            Ok(format!("  {}{target} ({})", row.get::<_, String>(0)?, row.get::<_, String>(3)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    writeln!(out, "\nExtensions ({}):", rows.len()).unwrap();
+    for row in rows {
+        writeln!(out, "{row}").unwrap();
+    }
+    Ok(())
+}
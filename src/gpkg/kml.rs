@@ -0,0 +1,99 @@
+//! `GPKG_ExportKML(table, file)`: writes a feature table as a KML document,
+//! one `Placemark` per row.
+//!
+//! KML coordinates are longitude/latitude, so geometries in another SRS are
+//! transformed to EPSG:4326. A `name` column becomes the placemark name;
+//! the other attributes go to `ExtendedData`.
+use super::features::{self, GeometryColumn};
+use super::reproject::Transformer;
+use crate::error::{Error, Result};
+use crate::geometry::{gpb, kml, number};
+use crate::quote_identifier;
+use crate::xml::escape;
+use rusqlite::Connection;
+use rusqlite::types::ValueRef;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+fn text(value: ValueRef<'_>) -> Option<String> {
+    match value {
+        ValueRef::Null | ValueRef::Blob(_) => None,
+        ValueRef::Integer(i) => Some(i.to_string()),
+        ValueRef::Real(r) => Some(number(r)),
+        ValueRef::Text(t) => Some(String::from_utf8_lossy(t).into_owned()),
+    }
+}
+
+/// Writes `table` to the new file `path` and returns the number of
+/// placemarks.
+pub fn export(conn: &Connection, table: &str, path: &str) -> Result<i64> {
+    let GeometryColumn { column, srs_id, .. } = features::geometry_column(conn, table)?
+        .ok_or_else(|| Error::new(format!("{table} is not a registered feature table")))?;
+    if Path::new(path).exists() {
+        return Err(Error::new(format!("{path} already exists")));
+    }
+    let transformer = match srs_id {
+        4326 => None,
+        _ => Some(Transformer::new(conn, srs_id, 4326)?),
+    };
+    let key = features::primary_key(conn, table)?;
+    let attributes: Vec<String> = features::column_names(conn, table)?
+        .into_iter()
+        .filter(|c| !c.eq_ignore_ascii_case(&key) && !c.eq_ignore_ascii_case(&column))
+        .collect();
+    let name_index = attributes.iter().position(|c| c.eq_ignore_ascii_case("name"));
+
+    let mut selected = vec![quote_identifier(&key), quote_identifier(&column)];
+    selected.extend(attributes.iter().map(|c| quote_identifier(c)));
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM {} ORDER BY {}",
+        selected.join(", "),
+        quote_identifier(table),
+        quote_identifier(&key)
+    ))?;
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n");
+    writeln!(out, "<name>{}</name>", escape(table)).unwrap();
+    let mut count = 0;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        writeln!(out, "<Placemark id=\"{}.{id}\">", escape(table)).unwrap();
+        if let Some(i) = name_index
+            && let Some(name) = text(row.get_ref(i + 2)?)
+        {
+            writeln!(out, "<name>{}</name>", escape(&name)).unwrap();
+        }
+        let data: Vec<(&String, String)> = attributes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| Some(*i) != name_index)
+            .filter_map(|(i, c)| Some((c, text(row.get_ref(i + 2).ok()?)?)))
+            .collect();
+        if !data.is_empty() {
+            out.push_str("<ExtendedData>\n");
+            for (name, value) in data {
+                writeln!(out, "<Data name=\"{}\"><value>{}</value></Data>", escape(name), escape(&value)).unwrap();
+            }
+            out.push_str("</ExtendedData>\n");
+        }
+        if let Some(blob) = row.get::<_, Option<Vec<u8>>>(1)? {
+            let error = |e: Error| Error::new(format!("{table} row {id}: {e}"));
+            let mut decoded = gpb::decode(&blob).map_err(error)?;
+            if !decoded.geometry.is_empty() {
+                if let Some(transformer) = &transformer {
+                    transformer.apply(&mut decoded.geometry, decoded.dims).map_err(error)?;
+                }
+                writeln!(out, "{}", kml::write(&decoded.geometry, decoded.dims)).unwrap();
+            }
+        }
+        out.push_str("</Placemark>\n");
+        count += 1;
+    }
+    out.push_str("</Document>\n</kml>\n");
+    fs::write(path, out).map_err(|e| Error::new(format!("cannot write {path}: {e}")))?;
+    Ok(count)
+}
@@ -0,0 +1,225 @@
+//! MBTiles conversion in both directions.
+//!
+//! MBTiles stores web Mercator tiles with TMS rows (row 0 at the bottom)
+//! and describes them in a `metadata` name/value table, with bounds in
+//! longitude/latitude.
+use super::tiles::{self, BATCH, Grid, MAX_LATITUDE, WEB_MERCATOR_EXTENT};
+use super::extensions::{self, WEBP};
+use super::{features, table_exists};
+use crate::error::{Error, Result};
+use crate::geometry::number;
+use crate::quote_identifier;
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::{FRAC_PI_4, PI};
+use std::path::Path;
+
+pub const SCHEMA: &str = "
+CREATE TABLE metadata (name TEXT, value TEXT);
+CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB);
+CREATE UNIQUE INDEX tile_index ON tiles (zoom_level, tile_column, tile_row);
+";
+
+pub fn to_mercator(lon: f64, lat: f64) -> (f64, f64) {
+    let lat = lat.clamp(-MAX_LATITUDE, MAX_LATITUDE);
+    (lon * WEB_MERCATOR_EXTENT / 180.0, (FRAC_PI_4 + lat.to_radians() / 2.0).tan().ln() * WEB_MERCATOR_EXTENT / PI)
+}
+
+pub fn to_lon_lat(x: f64, y: f64) -> (f64, f64) {
+    (x * 180.0 / WEB_MERCATOR_EXTENT, (2.0 * (y * PI / WEB_MERCATOR_EXTENT).exp().atan() - PI / 2.0).to_degrees())
+}
+
+pub fn open(path: &str, flags: OpenFlags) -> Result<Connection> {
+    Connection::open_with_flags(path, flags | OpenFlags::SQLITE_OPEN_URI)
+        .map_err(|e| Error::new(format!("cannot open {path}: {e}")))
+}
+
+/// Loads the MBTiles file `path` into the tile pyramid `table` (created if
+/// needed) and returns the number of tiles.
+///
+/// Each tile is checked by its signature, since the `format` metadata is
+/// optional: anything but PNG, JPEG or WebP, such as vector tiles, fails.
+pub fn import(conn: &mut Connection, path: &str, table: &str) -> Result<i64> {
+    let source = open(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    if !table_exists(&source, "tiles")? || !table_exists(&source, "metadata")? {
+        return Err(Error::new(format!("{path} is not an MBTiles file")));
+    }
+    let metadata: HashMap<String, String> = {
+        let mut stmt = source.prepare("SELECT name, value FROM metadata WHERE value NOT NULL")?;
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<rusqlite::Result<_>>()?
+    };
+    if let Some(format) = metadata.get("format")
+        && !matches!(format.as_str(), "png" | "jpg" | "jpeg" | "webp")
+    {
+        return Err(Error::new(format!("{path} holds {format} tiles; only raster tiles can be imported")));
+    }
+
+    let grid = Grid::for_srs(3857)?;
+    tiles::create_pyramid(conn, table, grid)?;
+    let mut zooms = HashSet::new();
+    let mut webp = false;
+    let mut count = 0;
+    let mut stmt = source.prepare("SELECT zoom_level, tile_column, tile_row, tile_data FROM tiles")?;
+    let mut rows = stmt.query([])?;
+    let mut done = false;
+    while !done {
+        let tx = conn.savepoint()?;
+        {
+            let mut insert = tx.prepare(&format!(
+                "INSERT OR REPLACE INTO {} (zoom_level, tile_column, tile_row, tile_data) VALUES (?1, ?2, ?3, ?4)",
+                quote_identifier(table)
+            ))?;
+            for _ in 0..BATCH {
+                let Some(row) = rows.next()? else {
+                    done = true;
+                    break;
+                };
+                let (zoom, column, tms_row, data): (i64, i64, i64, Vec<u8>) =
+                    (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?);
+                let outside = || Error::new(format!("{path}: tile {zoom}/{column}/{tms_row} is outside the tile matrix"));
+                if !(0..=30).contains(&zoom) {
+                    return Err(outside());
+                }
+                let (width, height) = grid.matrix_size(zoom);
+                if !(0..width).contains(&column) || !(0..height).contains(&tms_row) {
+                    return Err(outside());
+                }
+                match tiles::image_format(&data) {
+                    None => {
+                        return Err(Error::new(format!(
+                            "{path}: tile {zoom}/{column}/{tms_row} is not a PNG, JPEG or WebP image"
+                        )));
+                    }
+                    Some("webp") if !webp => {
+                        extensions::register(&tx, Some(table), Some("tile_data"), &WEBP)?;
+                        webp = true;
+                    }
+                    Some(_) => {}
+                }
+                if zooms.insert(zoom) {
+                    tiles::add_tile_matrix(&tx, table, grid, zoom, tiles::image_size(&data).unwrap_or((256, 256)))?;
+                }
+                insert.execute((zoom, column, height - 1 - tms_row, &data))?;
+                count += 1;
+            }
+        }
+        tx.commit()?;
+    }
+
+    // The contents extent and description come from the metadata.
+    let bounds: Option<Vec<f64>> =
+        metadata.get("bounds").and_then(|b| b.split(',').map(|v| v.trim().parse().ok()).collect());
+    if let Some([west, south, east, north]) = bounds.as_deref() {
+        let (min_x, min_y) = to_mercator(*west, *south);
+        let (max_x, max_y) = to_mercator(*east, *north);
+        conn.execute(
+            "UPDATE gpkg_contents SET min_x = ?2, min_y = ?3, max_x = ?4, max_y = ?5 WHERE table_name = ?1",
+            (table, min_x, min_y, max_x, max_y),
+        )?;
+    }
+    if let Some(description) = metadata.get("description") {
+        conn.execute("UPDATE gpkg_contents SET description = ?2 WHERE table_name = ?1", (table, description))?;
+    }
+    if let Some(name) = metadata.get("name") {
+        // identifier is UNIQUE; keep the table name if another entry uses it.
+        conn.execute(
+            "UPDATE gpkg_contents SET identifier = ?2
+             WHERE table_name = ?1 AND NOT EXISTS (SELECT 1 FROM gpkg_contents WHERE identifier = ?2)",
+            (table, name),
+        )?;
+    }
+    features::extend_contents(conn, table, None)?;
+    Ok(count)
+}
+
+/// Writes the tile pyramid `table` to the new MBTiles file `path` and
+/// returns the number of tiles. Only web Mercator pyramids on the standard
+/// XYZ grid can be converted.
+pub fn export(conn: &Connection, table: &str, path: &str) -> Result<i64> {
+    let grid = Grid::for_srs(3857)?;
+    let set: Option<(i32, [f64; 4])> = if table_exists(conn, "gpkg_tile_matrix_set")? {
+        conn.query_row(
+            "SELECT srs_id, min_x, min_y, max_x, max_y FROM gpkg_tile_matrix_set WHERE table_name = ?1",
+            [table],
+            |row| Ok((row.get(0)?, [row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?])),
+        )
+        .optional()?
+    } else {
+        None
+    };
+    let (srs_id, bounds) = set.ok_or_else(|| Error::new(format!("{table} is not a registered tile pyramid")))?;
+    let on_grid = bounds.iter().zip(grid.bounds).all(|(a, b)| (a - b).abs() < 1e-3);
+    if srs_id != grid.srs_id || !on_grid {
+        return Err(Error::new(format!("{table} is not on the web Mercator (EPSG:3857) XYZ tile grid")));
+    }
+    let matrices: Vec<(i64, i64, i64)> = {
+        let mut stmt = conn.prepare(
+            "SELECT zoom_level, matrix_width, matrix_height FROM gpkg_tile_matrix WHERE table_name = ?1 ORDER BY zoom_level",
+        )?;
+        stmt.query_map([table], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?.collect::<rusqlite::Result<_>>()?
+    };
+    if let Some((zoom, _, _)) = matrices.iter().find(|(zoom, w, h)| grid.matrix_size(*zoom) != (*w, *h)) {
+        return Err(Error::new(format!("zoom level {zoom} of {table} does not match the XYZ tile grid")));
+    }
+    let (Some(min_zoom), Some(max_zoom)) = (matrices.first(), matrices.last()) else {
+        return Err(Error::new(format!("{table} has no tile matrices")));
+    };
+    if Path::new(path).exists() {
+        return Err(Error::new(format!("{path} already exists")));
+    }
+
+    let (identifier, description, extent) = conn.query_row(
+        "SELECT identifier, description, min_x, min_y, max_x, max_y FROM gpkg_contents WHERE table_name = ?1",
+        [table],
+        |row| {
+            let extent = match (row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?) {
+                (Some(a), Some(b), Some(c), Some(d)) => Some([a, b, c, d]),
+                _ => None,
+            };
+            Ok((row.get::<_, Option<String>>(0)?, row.get::<_, Option<String>>(1)?, extent))
+        },
+    )?;
+    let [min_x, min_y, max_x, max_y] = extent.unwrap_or(grid.bounds);
+    let (west, south) = to_lon_lat(min_x, min_y);
+    let (east, north) = to_lon_lat(max_x, max_y);
+
+    let mut target = open(path, OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE)?;
+    let tx = target.transaction()?;
+    tx.execute_batch(SCHEMA)?;
+    let mut count = 0;
+    let mut format = None;
+    {
+        let mut select = conn.prepare(&format!(
+            "SELECT zoom_level, tile_column, tile_row, tile_data FROM {} ORDER BY zoom_level, tile_column, tile_row",
+            quote_identifier(table)
+        ))?;
+        let mut insert = tx.prepare("INSERT INTO tiles VALUES (?1, ?2, ?3, ?4)")?;
+        let mut rows = select.query([])?;
+        while let Some(row) = rows.next()? {
+            let (zoom, column, tile_row, data): (i64, i64, i64, Vec<u8>) =
+                (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?);
+            format = format.or(tiles::image_format(&data));
+            insert.execute((zoom, column, grid.matrix_size(zoom).1 - 1 - tile_row, &data))?;
+            count += 1;
+        }
+    }
+
+    let name = identifier.filter(|s| !s.is_empty()).unwrap_or_else(|| table.to_string());
+    let bounds = [west, south, east, north].map(number).join(",");
+    let mut metadata = vec![
+        ("name", name),
+        ("type", "baselayer".to_string()),
+        ("format", format.unwrap_or("png").replace("jpeg", "jpg")),
+        ("bounds", bounds),
+        ("minzoom", min_zoom.0.to_string()),
+        ("maxzoom", max_zoom.0.to_string()),
+    ];
+    if let Some(description) = description.filter(|s| !s.is_empty()) {
+        metadata.push(("description", description));
+    }
+    for (name, value) in metadata {
+        tx.execute("INSERT INTO metadata VALUES (?1, ?2)", (name, value))?;
+    }
+    tx.commit()?;
+    Ok(count)
+}
@@ -0,0 +1,132 @@
+//! Metadata documents (`gpkg_metadata`) and what they describe
+//! (`gpkg_metadata_reference`): the whole GeoPackage, a table or a row.
+use super::create::{METADATA_SCHEMA, create_in};
+use super::extensions::{self, METADATA};
+use super::table_exists;
+use crate::error::{Error, Result};
+use crate::quote_identifier;
+use rusqlite::{Connection, OptionalExtension};
+use std::fmt::Write;
+use std::fs;
+
+const METADATA_TABLES: [&str; 2] = ["gpkg_metadata", "gpkg_metadata_reference"];
+
+/// `(mime_type, md_standard_uri)` for a document, judged by its content.
+fn document_type(document: &str) -> Result<(&'static str, &'static str)> {
+    let start = document.trim_start();
+    if start.starts_with('<') {
+        if document.contains("http://www.isotc211.org/2005/gmd") {
+            Ok(("text/xml", "http://schemas.opengis.net/iso/19139/"))
+        } else {
+            Ok(("text/xml", "http://www.w3.org/XML/"))
+        }
+    } else if start.starts_with('{') || start.starts_with('[') {
+        Ok(("application/json", "https://www.rfc-editor.org/rfc/rfc8259"))
+    } else {
+        Err(Error::new("metadata documents must be XML or JSON"))
+    }
+}
+
+/// Attaches the XML or JSON document in `path` to the GeoPackage, to
+/// `table`, or to row `row_id` of `table`. Returns the metadata id.
+pub fn add(conn: &mut Connection, path: &str, table: Option<&str>, row_id: Option<i64>) -> Result<i64> {
+    let document = fs::read_to_string(path).map_err(|e| Error::new(format!("cannot read {path}: {e}")))?;
+    let (mime_type, standard) = document_type(&document)?;
+    let tx = conn.savepoint()?;
+    create_in(&tx)?;
+    let scope = match (table, row_id) {
+        (None, _) => "geopackage",
+        (Some(table), _) if !table_exists(&tx, table)? => {
+            return Err(Error::new(format!("table {table} does not exist")));
+        }
+        (Some(_), None) => "table",
+        (Some(table), Some(row_id)) => {
+            let found = tx
+                .query_row(&format!("SELECT 1 FROM {} WHERE rowid = ?1", quote_identifier(table)), [row_id], |_| {
+                    Ok(())
+                })
+                .optional()?;
+            if found.is_none() {
+                return Err(Error::new(format!("{table} has no row {row_id}")));
+            }
+            "row"
+        }
+    };
+
+    tx.execute_batch(METADATA_SCHEMA)?;
+    for table in METADATA_TABLES {
+        extensions::register(&tx, Some(table), None, &METADATA)?;
+    }
+    tx.execute(
+        "INSERT INTO gpkg_metadata (md_scope, md_standard_uri, mime_type, metadata) VALUES ('dataset', ?1, ?2, ?3)",
+        (standard, mime_type, &document),
+    )?;
+    let id = tx.last_insert_rowid();
+    tx.execute(
+        "INSERT INTO gpkg_metadata_reference (reference_scope, table_name, row_id_value, md_file_id)
+         VALUES (?1, ?2, ?3, ?4)",
+        (scope, table, row_id, id),
+    )?;
+    tx.commit()?;
+    Ok(id)
+}
+
+/// The document with metadata id `id`.
+pub fn document(conn: &Connection, id: i64) -> Result<String> {
+    let document = if table_exists(conn, "gpkg_metadata")? {
+        conn.query_row("SELECT metadata FROM gpkg_metadata WHERE id = ?1", [id], |row| row.get(0)).optional()?
+    } else {
+        None
+    };
+    document.ok_or_else(|| Error::new(format!("no metadata with id {id}")))
+}
+
+/// Removes metadata `id` with its references. The extension is unregistered
+/// once no metadata is left.
+pub fn remove(conn: &mut Connection, id: i64) -> Result<()> {
+    let tx = conn.savepoint()?;
+    document(&tx, id)?;
+    tx.execute("UPDATE gpkg_metadata_reference SET md_parent_id = NULL WHERE md_parent_id = ?1", [id])?;
+    tx.execute("DELETE FROM gpkg_metadata_reference WHERE md_file_id = ?1", [id])?;
+    tx.execute("DELETE FROM gpkg_metadata WHERE id = ?1", [id])?;
+    let left: i64 = tx.query_row("SELECT count(*) FROM gpkg_metadata", [], |row| row.get(0))?;
+    if left == 0 {
+        for table in METADATA_TABLES {
+            extensions::unregister(&tx, Some(table), None, METADATA.name)?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Lines describing the metadata linked to `table` or its rows, e.g.
+/// `#3 text/xml (dataset), 2048 bytes, on row 5`.
+pub fn linked(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    if !table_exists(conn, "gpkg_metadata_reference")? || !table_exists(conn, "gpkg_metadata")? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare(
+        "SELECT m.id, m.mime_type, m.md_scope, length(m.metadata), r.column_name, r.row_id_value
+         FROM gpkg_metadata_reference r JOIN gpkg_metadata m ON m.id = r.md_file_id
+         WHERE r.table_name = ?1 ORDER BY m.id",
+    )?;
+    let mut rows = stmt.query([table])?;
+    let mut lines = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut line = format!(
+            "#{} {} ({}), {} bytes",
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, i64>(3)?
+        );
+        match (row.get::<_, Option<String>>(4)?, row.get::<_, Option<i64>>(5)?) {
+            (Some(column), Some(row_id)) => write!(line, ", on {column} of row {row_id}").unwrap(),
+            (Some(column), None) => write!(line, ", on column {column}").unwrap(),
+            (None, Some(row_id)) => write!(line, ", on row {row_id}").unwrap(),
+            (None, None) => {}
+        }
+        lines.push(line);
+    }
+    Ok(lines)
+}
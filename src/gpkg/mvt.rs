@@ -0,0 +1,708 @@
+//! `GPKG_ExportMVT(table, target, ?minzoom?, ?maxzoom?)`: cuts a feature
+//! table into Mapbox Vector Tiles on the web Mercator XYZ grid.
+//!
+//! A `target` ending in `.mbtiles` becomes an MBTiles file with
+//! gzip-compressed tiles; any other target is a directory of
+//! `z/x/y.pbf` files. Tiles are visited from zoom 0 down, and only the
+//! children of tiles holding features are queried. Each query selects the
+//! features intersecting the tile through the spatial index when it is
+//! usable (see `rtree::BBoxQuery`).
+//!
+//! Geometries are simplified to the tile resolution and clipped to the tile
+//! plus a buffer; geometry collections are split into one feature per
+//! point, line and polygon part. The primary key becomes the feature id,
+//! except on split features, and the other columns its attributes; NULL
+//! and BLOB values are left out.
+use super::features::{self, GeometryColumn};
+use super::mbtiles::{self, to_lon_lat, to_mercator};
+use super::reproject::Transformer;
+use super::rtree::BBoxQuery;
+use super::tiles::WEB_MERCATOR_EXTENT;
+use crate::error::{Error, Result};
+use crate::geometry::{Coord, Dims, Geometry, gpb, number};
+use crate::json::Json;
+use crate::protobuf::{Message, zigzag};
+use crate::quote_identifier;
+use crate::spatial_functions::simplify;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags, Transaction};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+/// Tile coordinate range of a tile side.
+const EXTENT: f64 = 4096.0;
+/// Tile units around each tile that geometries keep after clipping.
+const BUFFER: f64 = 64.0;
+const MAX_ZOOM: i64 = 24;
+
+/// Converts between the layer SRS and web Mercator.
+enum Projection {
+    Mercator,
+    LonLat,
+    /// Transformers to and from EPSG:4326.
+    Other(Box<(Transformer, Transformer)>),
+}
+
+impl Projection {
+    fn new(conn: &Connection, srs_id: i32) -> Result<Projection> {
+        Ok(match srs_id {
+            3857 => Projection::Mercator,
+            4326 => Projection::LonLat,
+            _ => {
+                let transformers = (Transformer::new(conn, srs_id, 4326)?, Transformer::new(conn, 4326, srs_id)?);
+                Projection::Other(Box::new(transformers))
+            }
+        })
+    }
+
+    fn to_mercator(&self, geometry: &mut Geometry, dims: Dims) -> Result<()> {
+        if let Projection::Other(transformers) = self {
+            transformers.0.apply(geometry, dims)?;
+        }
+        if !matches!(self, Projection::Mercator) {
+            geometry.for_each_coord_mut(&mut |c| (c.x, c.y) = to_mercator(c.x, c.y));
+        }
+        Ok(())
+    }
+
+    /// The envelope in the layer SRS of a web Mercator box.
+    fn bbox(&self, [min_x, min_y, max_x, max_y]: [f64; 4]) -> Result<[f64; 4]> {
+        let (west, south) = to_lon_lat(min_x, min_y);
+        let (east, north) = to_lon_lat(max_x, max_y);
+        match self {
+            Projection::Mercator => Ok([min_x, min_y, max_x, max_y]),
+            Projection::LonLat => Ok([west, south, east, north]),
+            Projection::Other(transformers) => {
+                let from_lon_lat = &transformers.1;
+                // Follow the edges, which need not stay straight.
+                const STEPS: usize = 8;
+                let mut edge = Vec::with_capacity(4 * STEPS);
+                for i in 0..STEPS {
+                    let t = i as f64 / STEPS as f64;
+                    let (lon, lat) = (west + (east - west) * t, south + (north - south) * t);
+                    edge.extend([(lon, south), (east, lat), (east - (east - west) * t, north), (west, north - (north - south) * t)]);
+                }
+                let mut boundary =
+                    Geometry::LineString(edge.into_iter().map(|(x, y)| Coord { x, y, z: 0.0, m: 0.0 }).collect());
+                from_lon_lat.apply(&mut boundary, Dims::XY)?;
+                let e = boundary.envelope(Dims::XY).ok_or_else(|| Error::new("empty tile boundary"))?;
+                Ok([e.min_x, e.min_y, e.max_x, e.max_y])
+            }
+        }
+    }
+}
+
+/// Web Mercator bounds of tile `x`/`y` at `zoom`, widened by `margin` tile
+/// units.
+fn tile_bounds(zoom: i64, x: i64, y: i64, margin: f64) -> [f64; 4] {
+    let size = 2.0 * WEB_MERCATOR_EXTENT / (1i64 << zoom) as f64;
+    let margin = margin / EXTENT * size;
+    let (min_x, max_y) = (-WEB_MERCATOR_EXTENT + x as f64 * size, WEB_MERCATOR_EXTENT - y as f64 * size);
+    [min_x - margin, max_y - size - margin, min_x + size + margin, max_y + margin]
+}
+
+type Point = [f64; 2];
+
+/// The parts of a geometry in tile coordinates, grouped by MVT type.
+#[derive(Default)]
+struct Parts {
+    points: Vec<Point>,
+    lines: Vec<Vec<Point>>,
+    polygons: Vec<Vec<Vec<Point>>>,
+}
+
+impl Parts {
+    fn add(&mut self, geometry: &Geometry, to_tile: &impl Fn(&Coord) -> Point) {
+        let line = |coords: &[Coord]| coords.iter().map(to_tile).collect::<Vec<_>>();
+        let polygon = |rings: &[Vec<Coord>]| rings.iter().map(|r| line(r)).collect::<Vec<_>>();
+        match geometry {
+            Geometry::Point(point) => self.points.extend(point.iter().map(to_tile)),
+            Geometry::LineString(coords) => self.lines.push(line(coords)),
+            Geometry::Polygon(rings) => self.polygons.push(polygon(rings)),
+            Geometry::MultiPoint(points) => self.points.extend(points.iter().map(to_tile)),
+            Geometry::MultiLineString(lines) => self.lines.extend(lines.iter().map(|l| line(l))),
+            Geometry::MultiPolygon(polygons) => self.polygons.extend(polygons.iter().map(|p| polygon(p))),
+            Geometry::GeometryCollection(geometries) => geometries.iter().for_each(|g| self.add(g, to_tile)),
+        }
+    }
+}
+
+const MIN: f64 = -BUFFER;
+const MAX: f64 = EXTENT + BUFFER;
+
+fn inside([x, y]: Point) -> bool {
+    (MIN..=MAX).contains(&x) && (MIN..=MAX).contains(&y)
+}
+
+/// The part of segment `a`-`b` inside the clip box (Liang-Barsky).
+fn clip_segment(a: Point, b: Point) -> Option<(Point, Point)> {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let (mut t0, mut t1) = (0.0f64, 1.0f64);
+    for (p, q) in [(-dx, a[0] - MIN), (dx, MAX - a[0]), (-dy, a[1] - MIN), (dy, MAX - a[1])] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    let at = |t: f64| if t == 0.0 { a } else if t == 1.0 { b } else { [a[0] + t * dx, a[1] + t * dy] };
+    (t0 <= t1).then(|| (at(t0), at(t1)))
+}
+
+/// The runs of `line` inside the clip box.
+fn clip_line(line: &[Point]) -> Vec<Vec<Point>> {
+    let mut runs = Vec::new();
+    let mut run: Vec<Point> = Vec::new();
+    for pair in line.windows(2) {
+        match clip_segment(pair[0], pair[1]) {
+            Some((start, end)) => {
+                if run.last() != Some(&start) {
+                    runs.push(std::mem::take(&mut run));
+                    run.push(start);
+                }
+                run.push(end);
+                if end != pair[1] {
+                    runs.push(std::mem::take(&mut run));
+                }
+            }
+            None => runs.push(std::mem::take(&mut run)),
+        }
+    }
+    runs.push(run);
+    runs.retain(|r| r.len() >= 2);
+    runs
+}
+
+/// `ring` clipped to the clip box (Sutherland-Hodgman), open.
+fn clip_ring(ring: &[Point]) -> Vec<Point> {
+    let mut points: Vec<Point> = ring.to_vec();
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    let edges: [(usize, f64, bool); 4] = [(0, MIN, true), (0, MAX, false), (1, MIN, true), (1, MAX, false)];
+    for (axis, limit, keep_above) in edges {
+        let keep = |p: &Point| if keep_above { p[axis] >= limit } else { p[axis] <= limit };
+        let crossing = |a: &Point, b: &Point| {
+            let t = (limit - a[axis]) / (b[axis] - a[axis]);
+            [a[0] + t * (b[0] - a[0]), a[1] + t * (b[1] - a[1])]
+        };
+        let input = std::mem::take(&mut points);
+        for (i, current) in input.iter().enumerate() {
+            let previous = &input[(i + input.len() - 1) % input.len()];
+            match (keep(previous), keep(current)) {
+                (true, true) => points.push(*current),
+                (true, false) => points.push(crossing(previous, current)),
+                (false, true) => points.extend([crossing(previous, current), *current]),
+                (false, false) => {}
+            }
+        }
+    }
+    points
+}
+
+/// Rounds to whole tile units and drops repeated vertices.
+fn quantize(points: &[Point]) -> Vec<[i32; 2]> {
+    let mut out: Vec<[i32; 2]> = Vec::with_capacity(points.len());
+    for [x, y] in points {
+        let p = [x.round() as i32, y.round() as i32];
+        if out.last() != Some(&p) {
+            out.push(p);
+        }
+    }
+    out
+}
+
+/// Twice the signed area by the surveyor's formula; positive for rings
+/// that are clockwise with y pointing down.
+fn area(ring: &[[i32; 2]]) -> i64 {
+    let n = ring.len();
+    (0..n)
+        .map(|i| {
+            let ([x0, y0], [x1, y1]) = (ring[i], ring[(i + 1) % n]);
+            i64::from(x0) * i64::from(y1) - i64::from(x1) * i64::from(y0)
+        })
+        .sum()
+}
+
+/// A ring ready for encoding, wound clockwise for exteriors and
+/// counter-clockwise for holes, or `None` if nothing of it is left.
+fn ring(points: &[Point], exterior: bool) -> Option<Vec<[i32; 2]>> {
+    let mut ring = quantize(&clip_ring(points));
+    if ring.len() > 1 && ring.first() == ring.last() {
+        ring.pop();
+    }
+    let area = if ring.len() >= 3 { area(&ring) } else { 0 };
+    if area == 0 {
+        return None;
+    }
+    if (area > 0) != exterior {
+        ring.reverse();
+    }
+    Some(ring)
+}
+
+/// The MVT geometry command stream, with parameters relative to the
+/// previous vertex.
+#[derive(Default)]
+struct Commands {
+    stream: Vec<u32>,
+    cursor: [i32; 2],
+}
+
+impl Commands {
+    const MOVE_TO: u32 = 1;
+    const LINE_TO: u32 = 2;
+    const CLOSE_PATH: u32 = 7;
+
+    fn command(&mut self, id: u32, count: usize) {
+        self.stream.push(id | (count as u32) << 3);
+    }
+
+    fn vertices(&mut self, points: &[[i32; 2]]) {
+        for p in points {
+            let delta = [p[0] - self.cursor[0], p[1] - self.cursor[1]];
+            self.stream.extend(delta.map(|d| zigzag(i64::from(d)) as u32));
+            self.cursor = *p;
+        }
+    }
+
+    fn path(&mut self, points: &[[i32; 2]], close: bool) {
+        self.command(Self::MOVE_TO, 1);
+        self.vertices(&points[..1]);
+        self.command(Self::LINE_TO, points.len() - 1);
+        self.vertices(&points[1..]);
+        if close {
+            self.command(Self::CLOSE_PATH, 1);
+        }
+    }
+}
+
+/// The encoded geometries of `parts` as `(type, commands)`: 1 for points,
+/// 2 for lines, 3 for polygons.
+fn encode_parts(parts: &Parts) -> Vec<(u64, Vec<u32>)> {
+    let mut encoded = Vec::new();
+
+    let points: Vec<[i32; 2]> = parts.points.iter().filter(|p| inside(**p)).map(|p| quantize(&[*p])[0]).collect();
+    if !points.is_empty() {
+        let mut commands = Commands::default();
+        commands.command(Commands::MOVE_TO, points.len());
+        commands.vertices(&points);
+        encoded.push((1, commands.stream));
+    }
+
+    let mut commands = Commands::default();
+    for run in parts.lines.iter().flat_map(|l| clip_line(l)) {
+        let line = quantize(&run);
+        if line.len() >= 2 {
+            commands.path(&line, false);
+        }
+    }
+    if !commands.stream.is_empty() {
+        encoded.push((2, commands.stream));
+    }
+
+    let mut commands = Commands::default();
+    for polygon in &parts.polygons {
+        let Some(exterior) = polygon.first().and_then(|r| ring(r, true)) else {
+            continue;
+        };
+        commands.path(&exterior, true);
+        for hole in polygon[1..].iter().filter_map(|r| ring(r, false)) {
+            commands.path(&hole, true);
+        }
+    }
+    if !commands.stream.is_empty() {
+        encoded.push((3, commands.stream));
+    }
+    encoded
+}
+
+/// An attribute value, comparable so each distinct value is stored once.
+#[derive(PartialEq, Eq, Hash, Clone)]
+enum Tag {
+    String(String),
+    /// The bits of an `f64`.
+    Double(u64),
+    Int(i64),
+}
+
+/// One layer of one tile.
+#[derive(Default)]
+struct TileLayer {
+    keys: Vec<String>,
+    key_index: HashMap<String, u32>,
+    values: Vec<Tag>,
+    value_index: HashMap<Tag, u32>,
+    features: Vec<Message>,
+}
+
+impl TileLayer {
+    fn tag(&mut self, key: &str, value: Tag) -> [u32; 2] {
+        let key = *self.key_index.entry(key.to_string()).or_insert_with(|| {
+            self.keys.push(key.to_string());
+            self.keys.len() as u32 - 1
+        });
+        let value = *self.value_index.entry(value.clone()).or_insert_with(|| {
+            self.values.push(value);
+            self.values.len() as u32 - 1
+        });
+        [key, value]
+    }
+
+    fn finish(self, name: &str) -> Vec<u8> {
+        let mut layer = Message::new();
+        layer.uint(15, 2);
+        layer.string(1, name);
+        self.features.iter().for_each(|f| layer.message(2, f));
+        self.keys.iter().for_each(|k| layer.string(3, k));
+        for value in &self.values {
+            let mut encoded = Message::new();
+            match value {
+                Tag::String(s) => encoded.string(1, s),
+                Tag::Double(bits) => encoded.double(3, f64::from_bits(*bits)),
+                Tag::Int(i) if *i < 0 => encoded.sint(6, *i),
+                Tag::Int(i) => encoded.uint(5, *i as u64),
+            }
+            layer.message(4, &encoded);
+        }
+        layer.uint(5, EXTENT as u64);
+        let mut tile = Message::new();
+        tile.message(3, &layer);
+        tile.finish()
+    }
+}
+
+/// An encoded tile and the web Mercator envelope of its features.
+type EncodedTile = (Vec<u8>, [f64; 4]);
+
+/// A feature table and the columns exported from it.
+struct Layer {
+    table: String,
+    key: String,
+    geometry: GeometryColumn,
+    /// Attribute columns with their declared types.
+    attributes: Vec<(String, String)>,
+    projection: Projection,
+    bbox: BBoxQuery,
+}
+
+impl Layer {
+    fn new(conn: &Connection, table: &str) -> Result<Layer> {
+        let geometry = features::geometry_column(conn, table)?
+            .ok_or_else(|| Error::new(format!("{table} is not a registered feature table")))?;
+        let key = features::primary_key(conn, table)?;
+        let mut attributes: Vec<(String, String)> = {
+            let mut stmt = conn.prepare("SELECT name, type FROM pragma_table_info(?1)")?;
+            stmt.query_map([table], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<rusqlite::Result<_>>()?
+        };
+        attributes.retain(|(name, _)| !name.eq_ignore_ascii_case(&key) && !name.eq_ignore_ascii_case(&geometry.column));
+        let projection = Projection::new(conn, geometry.srs_id)?;
+        let bbox = BBoxQuery::new(conn, table)?;
+        Ok(Layer { table: table.to_string(), key, geometry, attributes, projection, bbox })
+    }
+
+    /// The query for the features around tile `x`/`y` at `zoom`.
+    fn query(&self, zoom: i64, x: i64, y: i64) -> Result<String> {
+        self.bbox.query(self.projection.bbox(tile_bounds(zoom, x, y, BUFFER))?)
+    }
+
+    fn any(&self, conn: &Connection, zoom: i64, x: i64, y: i64) -> Result<bool> {
+        Ok(conn.query_row(&format!("SELECT EXISTS ({})", self.query(zoom, x, y)?), [], |row| row.get(0))?)
+    }
+
+    /// The encoded tile `x`/`y` at `zoom`, with whether any feature was near
+    /// it, and the web Mercator envelope of the features it holds.
+    fn tile(&self, conn: &Connection, zoom: i64, x: i64, y: i64) -> Result<(bool, Option<EncodedTile>)> {
+        let mut selected = vec![quote_identifier(&self.key), quote_identifier(&self.geometry.column)];
+        selected.extend(self.attributes.iter().map(|(name, _)| quote_identifier(name)));
+        let mut stmt =
+            conn.prepare(&format!("SELECT {} FROM ({})", selected.join(", "), self.query(zoom, x, y)?))?;
+        let mut rows = stmt.query([])?;
+
+        let [min_x, _, _, max_y] = tile_bounds(zoom, x, y, 0.0);
+        let size = 2.0 * WEB_MERCATOR_EXTENT / (1i64 << zoom) as f64;
+        let to_tile = |c: &Coord| [(c.x - min_x) / size * EXTENT, (max_y - c.y) / size * EXTENT];
+        let mut near = false;
+        let mut extent: Option<[f64; 4]> = None;
+        let mut layer = TileLayer::default();
+        while let Some(row) = rows.next()? {
+            near = true;
+            let id: i64 = row.get(0)?;
+            let blob: Vec<u8> = row.get(1)?;
+            let context = |e: Error| Error::new(format!("{} row {id}: {e}", self.table));
+            let mut decoded = gpb::decode(&blob).map_err(context)?;
+            self.projection.to_mercator(&mut decoded.geometry, decoded.dims).map_err(context)?;
+            let geometry = simplify(&decoded.geometry, size / EXTENT);
+            let mut parts = Parts::default();
+            parts.add(&geometry, &to_tile);
+            let encoded = encode_parts(&parts);
+            if encoded.is_empty() {
+                continue;
+            }
+            if let Some(e) = decoded.geometry.envelope(decoded.dims) {
+                let [a, b, c, d] = extent.get_or_insert([e.min_x, e.min_y, e.max_x, e.max_y]);
+                (*a, *b, *c, *d) = (a.min(e.min_x), b.min(e.min_y), c.max(e.max_x), d.max(e.max_y));
+            }
+
+            let mut tags = Vec::new();
+            for (i, (name, _)) in self.attributes.iter().enumerate() {
+                let value = match row.get_ref(i + 2)? {
+                    ValueRef::Null | ValueRef::Blob(_) => continue,
+                    ValueRef::Integer(v) => Tag::Int(v),
+                    ValueRef::Real(v) => Tag::Double(v.to_bits()),
+                    ValueRef::Text(t) => Tag::String(String::from_utf8_lossy(t).into_owned()),
+                };
+                tags.extend(layer.tag(name, value));
+            }
+            // Ids must be unique within a layer, so the features a
+            // collection is split into go without one.
+            let id = u64::try_from(id).ok().filter(|_| encoded.len() == 1);
+            for (geometry_type, commands) in encoded {
+                let mut feature = Message::new();
+                if let Some(id) = id {
+                    feature.uint(1, id);
+                }
+                if !tags.is_empty() {
+                    feature.packed(2, &tags);
+                }
+                feature.uint(3, geometry_type);
+                feature.packed(4, &commands);
+                layer.features.push(feature);
+            }
+        }
+        if layer.features.is_empty() {
+            return Ok((near, None));
+        }
+        Ok((near, extent.map(|extent| (layer.finish(&self.table), extent))))
+    }
+
+    /// The `json` metadata entry of MBTiles, which lists the layer fields.
+    fn vector_layers(&self, min_zoom: i64, max_zoom: i64) -> String {
+        let fields = self
+            .attributes
+            .iter()
+            .map(|(name, declared)| {
+                let declared = declared.to_uppercase();
+                let field_type = if declared == "BOOLEAN" {
+                    "Boolean"
+                } else if ["INT", "REAL", "FLOA", "DOUB"].iter().any(|t| declared.contains(t)) {
+                    "Number"
+                } else {
+                    "String"
+                };
+                (name.clone(), Json::String(field_type.to_string()))
+            })
+            .collect();
+        let layer = Json::Object(vec![
+            ("id".to_string(), Json::String(self.table.clone())),
+            ("fields".to_string(), Json::Object(fields)),
+            ("minzoom".to_string(), Json::Integer(min_zoom)),
+            ("maxzoom".to_string(), Json::Integer(max_zoom)),
+        ]);
+        Json::Object(vec![("vector_layers".to_string(), Json::Array(vec![layer]))]).to_json()
+    }
+}
+
+fn write_tile(tx: Option<&Transaction>, dir: &Path, zoom: i64, x: i64, y: i64, data: &[u8]) -> Result<()> {
+    match tx {
+        Some(tx) => {
+            let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+            gzip.write_all(data)?;
+            tx.execute("INSERT INTO tiles VALUES (?1, ?2, ?3, ?4)", (zoom, x, (1i64 << zoom) - 1 - y, gzip.finish()?))?;
+        }
+        None => {
+            let path = dir.join(zoom.to_string()).join(x.to_string()).join(format!("{y}.pbf"));
+            fs::create_dir_all(path.parent().unwrap())?;
+            File::create_new(&path)
+                .and_then(|mut file| file.write_all(data))
+                .map_err(|e| Error::new(format!("cannot write {}: {e}", path.display())))?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the vector tiles of `table` from `min_zoom` (default 0) to
+/// `max_zoom` (default 14) to `target` and returns the number of tiles.
+pub fn export(conn: &Connection, table: &str, target: &str, min_zoom: Option<i64>, max_zoom: Option<i64>) -> Result<i64> {
+    let (min_zoom, max_zoom) = (min_zoom.unwrap_or(0), max_zoom.unwrap_or(14));
+    if !(0..=MAX_ZOOM).contains(&min_zoom) || !(min_zoom..=MAX_ZOOM).contains(&max_zoom) {
+        return Err(Error::new(format!(
+            "invalid zoom range {min_zoom} to {max_zoom}; zoom levels go from 0 to {MAX_ZOOM}"
+        )));
+    }
+    let layer = Layer::new(conn, table)?;
+    let is_mbtiles = target.to_lowercase().ends_with(".mbtiles");
+    if is_mbtiles && Path::new(target).exists() {
+        return Err(Error::new(format!("{target} already exists")));
+    }
+    let mut output = if is_mbtiles {
+        Some(mbtiles::open(target, OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE)?)
+    } else {
+        None
+    };
+    let tx = output.as_mut().map(Connection::transaction).transpose()?;
+    if let Some(tx) = &tx {
+        tx.execute_batch(mbtiles::SCHEMA)?;
+    }
+
+    let mut count = 0;
+    let mut extent: Option<[f64; 4]> = None;
+    let mut level = vec![(0, 0)];
+    for zoom in 0..=max_zoom {
+        let mut next = Vec::new();
+        for (x, y) in level {
+            let near = if zoom < min_zoom {
+                layer.any(conn, zoom, x, y)?
+            } else {
+                let (near, tile) = layer.tile(conn, zoom, x, y)?;
+                if let Some((data, [a, b, c, d])) = tile {
+                    write_tile(tx.as_ref(), Path::new(target), zoom, x, y, &data)?;
+                    let e = extent.get_or_insert([a, b, c, d]);
+                    *e = [e[0].min(a), e[1].min(b), e[2].max(c), e[3].max(d)];
+                    count += 1;
+                }
+                near
+            };
+            if near {
+                next.extend([(2 * x, 2 * y), (2 * x + 1, 2 * y), (2 * x, 2 * y + 1), (2 * x + 1, 2 * y + 1)]);
+            }
+        }
+        level = next;
+    }
+
+    if let Some(tx) = tx {
+        let (identifier, description): (Option<String>, Option<String>) = conn.query_row(
+            "SELECT identifier, description FROM gpkg_contents WHERE table_name = ?1",
+            [table],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let mut metadata = vec![
+            ("name", identifier.filter(|s| !s.is_empty()).unwrap_or_else(|| table.to_string())),
+            ("type", "overlay".to_string()),
+            ("format", "pbf".to_string()),
+            ("minzoom", min_zoom.to_string()),
+            ("maxzoom", max_zoom.to_string()),
+            ("json", layer.vector_layers(min_zoom, max_zoom)),
+        ];
+        if let Some([min_x, min_y, max_x, max_y]) = extent {
+            let (west, south) = to_lon_lat(min_x, min_y);
+            let (east, north) = to_lon_lat(max_x, max_y);
+            metadata.push(("bounds", [west, south, east, north].map(number).join(",")));
+        }
+        if let Some(description) = description.filter(|s| !s.is_empty()) {
+            metadata.push(("description", description));
+        }
+        for (name, value) in metadata {
+            tx.execute("INSERT INTO metadata VALUES (?1, ?2)", (name, value))?;
+        }
+        tx.commit()?;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coord(x: f64, y: f64) -> Coord {
+        Coord { x, y, z: 0.0, m: 0.0 }
+    }
+
+    fn identity(c: &Coord) -> Point {
+        [c.x, c.y]
+    }
+
+    #[test]
+    fn bounds() {
+        let e = WEB_MERCATOR_EXTENT;
+        assert_eq!(tile_bounds(0, 0, 0, 0.0), [-e, -e, e, e]);
+        assert_eq!(tile_bounds(1, 1, 0, 0.0), [0.0, 0.0, e, e]);
+        let [min_x, _, max_x, _] = tile_bounds(0, 0, 0, EXTENT);
+        assert_eq!((min_x, max_x), (-3.0 * e, 3.0 * e));
+    }
+
+    #[test]
+    fn segments() {
+        assert_eq!(clip_segment([0.0, 0.0], [10.0, 10.0]), Some(([0.0, 0.0], [10.0, 10.0])));
+        assert_eq!(clip_segment([-100.0, 0.0], [-70.0, 10.0]), None);
+        assert_eq!(clip_segment([-100.0, 10.0], [100.0, 10.0]), Some(([MIN, 10.0], [100.0, 10.0])));
+        assert_eq!(clip_segment([10.0, MAX + 10.0], [10.0, MIN - 10.0]), Some(([10.0, MAX], [10.0, MIN])));
+        // Passes by a corner without entering.
+        assert_eq!(clip_segment([MIN - 10.0, MIN + 5.0], [MIN + 5.0, MIN - 10.0]), None);
+    }
+
+    #[test]
+    fn lines() {
+        // In, out across the right edge, and back in.
+        let runs = clip_line(&[[0.0, 0.0], [5000.0, 0.0], [5000.0, 100.0], [0.0, 100.0]]);
+        assert_eq!(runs, vec![vec![[0.0, 0.0], [MAX, 0.0]], vec![[MAX, 100.0], [0.0, 100.0]]]);
+        assert!(clip_line(&[[-500.0, -500.0], [-500.0, 5000.0]]).is_empty());
+        assert_eq!(clip_line(&[[1.0, 1.0], [2.0, 2.0], [3.0, 1.0]]), vec![vec![[1.0, 1.0], [2.0, 2.0], [3.0, 1.0]]]);
+    }
+
+    #[test]
+    fn rings() {
+        let square = [[-1000.0, -1000.0], [5000.0, -1000.0], [5000.0, 5000.0], [-1000.0, 5000.0], [-1000.0, -1000.0]];
+        let mut clipped = clip_ring(&square);
+        clipped.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(clipped, vec![[MIN, MIN], [MIN, MAX], [MAX, MIN], [MAX, MAX]]);
+        assert!(clip_ring(&[[-500.0, -500.0], [-400.0, -500.0], [-400.0, -400.0], [-500.0, -500.0]]).is_empty());
+    }
+
+    #[test]
+    fn winding() {
+        // Counter-clockwise on screen, so exteriors get reversed.
+        let ccw = [[0.0, 0.0], [0.0, 10.0], [10.0, 10.0], [10.0, 0.0], [0.0, 0.0]];
+        let exterior = ring(&ccw, true).unwrap();
+        assert_eq!(exterior.len(), 4);
+        assert!(area(&exterior) > 0);
+        assert!(area(&ring(&ccw, false).unwrap()) < 0);
+        // Collapses once rounded to tile units.
+        assert_eq!(ring(&[[0.0, 0.0], [0.2, 0.0], [0.2, 0.2], [0.0, 0.0]], true), None);
+        assert_eq!(ring(&[[0.0, 0.0], [5.0, 5.0], [10.0, 10.0], [0.0, 0.0]], true), None);
+    }
+
+    // Command streams from the examples of the MVT 2.1 specification.
+    #[test]
+    fn commands() {
+        let mut parts = Parts::default();
+        parts.add(&Geometry::Point(Some(coord(25.0, 17.0))), &identity);
+        assert_eq!(encode_parts(&parts), vec![(1, vec![9, 50, 34])]);
+
+        let mut parts = Parts::default();
+        parts.add(&Geometry::MultiPoint(vec![coord(5.0, 7.0), coord(3.0, 2.0)]), &identity);
+        assert_eq!(encode_parts(&parts), vec![(1, vec![17, 10, 14, 3, 9])]);
+
+        let mut parts = Parts::default();
+        parts.add(&Geometry::LineString(vec![coord(2.0, 2.0), coord(2.0, 10.0), coord(10.0, 10.0)]), &identity);
+        assert_eq!(encode_parts(&parts), vec![(2, vec![9, 4, 4, 18, 0, 16, 16, 0])]);
+
+        let mut parts = Parts::default();
+        let triangle = vec![coord(3.0, 6.0), coord(8.0, 12.0), coord(20.0, 34.0), coord(3.0, 6.0)];
+        parts.add(&Geometry::Polygon(vec![triangle]), &identity);
+        assert_eq!(encode_parts(&parts), vec![(3, vec![9, 6, 12, 18, 10, 12, 24, 44, 15])]);
+    }
+
+    #[test]
+    fn collections_split_by_type() {
+        let mut parts = Parts::default();
+        let collection = Geometry::GeometryCollection(vec![
+            Geometry::Point(Some(coord(1.0, 1.0))),
+            Geometry::LineString(vec![coord(0.0, 0.0), coord(4.0, 4.0)]),
+            Geometry::Point(None),
+            Geometry::Point(Some(coord(-1000.0, 1.0))),
+        ]);
+        parts.add(&collection, &identity);
+        let types: Vec<u64> = encode_parts(&parts).into_iter().map(|(t, _)| t).collect();
+        assert_eq!(types, [1, 2]);
+        assert!(encode_parts(&Parts::default()).is_empty());
+    }
+}
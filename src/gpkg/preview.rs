@@ -0,0 +1,112 @@
+//! `GPKG_Preview(table, zoom, column, row, ?width?, ?style?)`: a tile
+//! rendered as text, to check a tile layer without exporting files.
+//!
+//! The `color` style (the default) draws two pixels per character with
+//! `▀` and 24-bit ANSI colours; `ascii` draws one grey level per character
+//! for terminals or shells that do not pass escape sequences through.
+use super::tiles;
+use crate::error::{Error, Result};
+use rusqlite::Connection;
+use std::fmt::Write;
+
+/// Characters from dark to light for the `ascii` style.
+const RAMP: &[u8] = b" .:-=+*#%@";
+
+/// The image downsampled to `width` columns by averaging boxes of pixels;
+/// `None` marks mostly transparent pixels.
+fn downsample(image: &image::RgbaImage, width: u32, height: u32) -> Vec<Vec<Option<[u8; 3]>>> {
+    let (source_width, source_height) = image.dimensions();
+    // The source pixels behind target pixel `i` of `n`, at least one.
+    let span = |i: u32, n: u32, size: u32| {
+        let start = i * size / n;
+        start..((i + 1) * size / n).clamp(start + 1, size)
+    };
+    (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    let (mut sum, mut alpha, mut n) = ([0u64; 3], 0u64, 0u64);
+                    for py in span(y, height, source_height) {
+                        for px in span(x, width, source_width) {
+                            let [r, g, b, a] = image.get_pixel(px, py).0;
+                            for (s, v) in sum.iter_mut().zip([r, g, b]) {
+                                *s += u64::from(v) * u64::from(a);
+                            }
+                            alpha += u64::from(a);
+                            n += 1;
+                        }
+                    }
+                    (n > 0 && alpha >= 128 * n).then(|| sum.map(|s| (s / alpha) as u8))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn color(out: &mut String, top: Option<[u8; 3]>, bottom: Option<[u8; 3]>) {
+    match (top, bottom) {
+        (None, None) => out.push_str("\x1b[0m "),
+        (Some([r, g, b]), None) => write!(out, "\x1b[0m\x1b[38;2;{r};{g};{b}m▀").unwrap(),
+        (None, Some([r, g, b])) => write!(out, "\x1b[0m\x1b[38;2;{r};{g};{b}m▄").unwrap(),
+        (Some([r, g, b]), Some([br, bg, bb])) => {
+            write!(out, "\x1b[38;2;{r};{g};{b}m\x1b[48;2;{br};{bg};{bb}m▀").unwrap()
+        }
+    }
+}
+
+/// Renders the tile at `zoom`/`column`/`row` of `table` `width` characters
+/// wide (default 64) in `style` `color` or `ascii`.
+pub fn preview(
+    conn: &Connection,
+    table: &str,
+    zoom: i64,
+    column: i64,
+    row: i64,
+    width: Option<i64>,
+    style: Option<&str>,
+) -> Result<String> {
+    let width = width.unwrap_or(64);
+    if !(1..=512).contains(&width) {
+        return Err(Error::new(format!("width must be 1 to 512 characters, not {width}")));
+    }
+    let data = tiles::tile(conn, table, zoom, column, row)?;
+    let image = image::load_from_memory(&data)
+        .map_err(|e| Error::new(format!("cannot decode tile {zoom}/{column}/{row}: {e}")))?
+        .to_rgba8();
+    let (source_width, source_height) = image.dimensions();
+    let width = (width as u32).min(source_width);
+
+    let mut out = String::new();
+    match style.unwrap_or("color") {
+        "color" => {
+            // Two pixel rows per line.
+            let height = (source_height * width / source_width).max(2).next_multiple_of(2);
+            let pixels = downsample(&image, width, height);
+            for pair in pixels.chunks(2) {
+                for (top, bottom) in pair[0].iter().zip(&pair[1]) {
+                    color(&mut out, *top, *bottom);
+                }
+                out.push_str("\x1b[0m\n");
+            }
+        }
+        "ascii" => {
+            // Characters are about twice as tall as wide.
+            let height = (source_height * width / source_width / 2).max(1);
+            for line in downsample(&image, width, height) {
+                for pixel in line {
+                    let c = match pixel {
+                        None => b' ',
+                        Some([r, g, b]) => {
+                            let luma = (299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b)) / 1000;
+                            RAMP[luma as usize * (RAMP.len() - 1) / 255]
+                        }
+                    };
+                    out.push(c as char);
+                }
+                out.push('\n');
+            }
+        }
+        other => return Err(Error::new(format!("unknown preview style {other}; use color or ascii"))),
+    }
+    Ok(out.trim_end_matches('\n').to_string())
+}
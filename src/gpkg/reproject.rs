@@ -0,0 +1,139 @@
+//! Coordinate reprojection between the SRS of `gpkg_spatial_ref_sys`.
+//!
+//! Projections come from `proj4rs`, which does not read WKT. An SRS is
+//! resolved from the `EXTENSION["PROJ4",...]` of its definition, from a
+//! definition that is itself a PROJ string, or from its EPSG code.
+use super::features::{self, GeometryColumn};
+use crate::error::{Error, Result};
+use crate::geometry::{Dims, Geometry, gpb};
+use crate::quote_identifier;
+use proj4rs::Proj;
+use proj4rs::transform::transform;
+use rusqlite::{Connection, OptionalExtension};
+
+/// The PROJ string in the `EXTENSION["PROJ4","..."]` of a WKT definition.
+fn proj4_extension(wkt: &str) -> Option<&str> {
+    let start = wkt.find("EXTENSION[\"PROJ4\"")? + "EXTENSION[\"PROJ4\"".len();
+    let rest = wkt[start..].trim_start().strip_prefix(',')?.trim_start().strip_prefix('"')?;
+    Some(&rest[..rest.find('"')?])
+}
+
+fn projection(conn: &Connection, srs_id: i32) -> Result<Proj> {
+    let (organization, code, definition): (String, i64, String) = conn
+        .query_row(
+            "SELECT organization, organization_coordsys_id, definition FROM gpkg_spatial_ref_sys WHERE srs_id = ?1",
+            [srs_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?
+        .ok_or_else(|| Error::new(format!("srs_id {srs_id} is not defined in gpkg_spatial_ref_sys")))?;
+    let proj = if let Some(proj4) = proj4_extension(&definition) {
+        Proj::from_proj_string(proj4)
+    } else if definition.trim_start().starts_with('+') {
+        Proj::from_proj_string(definition.trim())
+    } else if organization.eq_ignore_ascii_case("EPSG")
+        && let Ok(code) = u16::try_from(code)
+    {
+        Proj::from_epsg_code(code)
+    } else {
+        return Err(Error::new(format!(
+            "cannot derive a projection for srs_id {srs_id} ({organization}:{code}); \
+             add a PROJ4 EXTENSION to its definition"
+        )));
+    };
+    proj.map_err(|e| Error::new(format!("srs_id {srs_id}: {e}")))
+}
+
+/// Transforms coordinates from one SRS to another.
+pub struct Transformer {
+    from: Proj,
+    to: Proj,
+}
+
+impl Transformer {
+    pub fn new(conn: &Connection, from: i32, to: i32) -> Result<Self> {
+        Ok(Transformer { from: projection(conn, from)?, to: projection(conn, to)? })
+    }
+
+    /// Transforms `geometry` in place; z is passed through the projection,
+    /// m is left alone.
+    pub fn apply(&self, geometry: &mut Geometry, dims: Dims) -> Result<()> {
+        let mut error = None;
+        geometry.for_each_coord_mut(&mut |c| {
+            if error.is_some() {
+                return;
+            }
+            // proj4rs works in radians for geographic coordinates.
+            let mut point = if self.from.is_latlong() {
+                (c.x.to_radians(), c.y.to_radians(), c.z)
+            } else {
+                (c.x, c.y, c.z)
+            };
+            if let Err(e) = transform(&self.from, &self.to, &mut point) {
+                error = Some(Error::new(format!("cannot transform ({} {}): {e}", c.x, c.y)));
+                return;
+            }
+            if self.to.is_latlong() {
+                point = (point.0.to_degrees(), point.1.to_degrees(), point.2);
+            }
+            c.x = point.0;
+            c.y = point.1;
+            if dims.z {
+                c.z = point.2;
+            }
+        });
+        error.map_or(Ok(()), Err)
+    }
+}
+
+/// Rewrites every geometry of `table` in `srs_id` and updates
+/// `gpkg_geometry_columns` and `gpkg_contents` to match. Returns the number
+/// of geometries transformed.
+pub fn reproject(conn: &mut Connection, table: &str, srs_id: i32) -> Result<i64> {
+    let tx = conn.savepoint()?;
+    let GeometryColumn { column, srs_id: from, .. } = features::geometry_column(&tx, table)?
+        .ok_or_else(|| Error::new(format!("{table} is not a registered feature table")))?;
+    if !features::srs_exists(&tx, srs_id)? {
+        return Err(Error::new(format!("srs_id {srs_id} is not defined in gpkg_spatial_ref_sys")));
+    }
+    if from == srs_id {
+        return Ok(0);
+    }
+    let transformer = Transformer::new(&tx, from, srs_id)?;
+    let key = features::primary_key(&tx, table)?;
+
+    let mut count = 0;
+    let mut extent = None;
+    {
+        let mut select = tx.prepare(&format!(
+            "SELECT {}, {} FROM {} WHERE {1} NOT NULL",
+            quote_identifier(&key),
+            quote_identifier(&column),
+            quote_identifier(table)
+        ))?;
+        let mut update = tx.prepare(&format!(
+            "UPDATE {} SET {} = ?1 WHERE {} = ?2",
+            quote_identifier(table),
+            quote_identifier(&column),
+            quote_identifier(&key)
+        ))?;
+        let mut rows = select.query([])?;
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let blob: Vec<u8> = row.get(1)?;
+            let mut decoded = gpb::decode(&blob).map_err(|e| Error::new(format!("{table} row {id}: {e}")))?;
+            transformer
+                .apply(&mut decoded.geometry, decoded.dims)
+                .map_err(|e| Error::new(format!("{table} row {id}: {e}")))?;
+            features::expand(&mut extent, decoded.geometry.envelope(decoded.dims));
+            update.execute((gpb::encode(&decoded.geometry, decoded.dims, srs_id), id))?;
+            count += 1;
+        }
+    }
+
+    tx.execute("UPDATE gpkg_geometry_columns SET srs_id = ?2 WHERE table_name = ?1", (table, srs_id))?;
+    tx.execute("UPDATE gpkg_contents SET srs_id = ?2 WHERE table_name = ?1", (table, srs_id))?;
+    features::set_extent(&tx, table, extent)?;
+    tx.commit()?;
+    Ok(count)
+}
@@ -0,0 +1,326 @@
+//! RTree spatial indexes (`gpkg_rtree_index` extension).
+//!
+//! The index is an `rtree_<table>_<column>` virtual table kept in sync by
+//! the triggers of GeoPackage 1.4, which call the functions in `st`. SQLite
+//! never consults an RTree on its own: queries use it by joining on `id`, e.g.
+//! `WHERE fid IN (SELECT id FROM rtree_t_geom WHERE minx <= ? AND maxx >= ? …)`.
+use super::extensions::{self, RTREE_INDEX};
+use super::features::{self, GeometryColumn};
+use super::table_exists;
+use crate::error::{Error, Result};
+use crate::geometry::{gpb, number};
+use crate::quote_identifier;
+use rusqlite::{Connection, OptionalExtension};
+
+/// The triggers of GeoPackage 1.4 as `(name suffix, definition)`; `{t}` is
+/// the table, `{c}` the geometry column, `{i}` the primary key and `{r}`
+/// the RTree.
+const TRIGGERS: [(&str, &str); 7] = [
+    (
+        "insert",
+        "AFTER INSERT ON {t}
+         WHEN (NEW.{c} NOT NULL AND NOT ST_IsEmpty(NEW.{c}))
+         BEGIN
+           INSERT OR REPLACE INTO {r} VALUES (
+             NEW.{i}, ST_MinX(NEW.{c}), ST_MaxX(NEW.{c}), ST_MinY(NEW.{c}), ST_MaxY(NEW.{c})
+           );
+         END;",
+    ),
+    (
+        "update6",
+        "AFTER UPDATE OF {c} ON {t}
+         WHEN OLD.{i} = NEW.{i} AND
+              (NEW.{c} NOTNULL AND NOT ST_IsEmpty(NEW.{c})) AND
+              (OLD.{c} NOTNULL AND NOT ST_IsEmpty(OLD.{c}))
+         BEGIN
+           UPDATE {r} SET
+             minx = ST_MinX(NEW.{c}), maxx = ST_MaxX(NEW.{c}),
+             miny = ST_MinY(NEW.{c}), maxy = ST_MaxY(NEW.{c})
+           WHERE id = NEW.{i};
+         END;",
+    ),
+    (
+        "update7",
+        "AFTER UPDATE OF {c} ON {t}
+         WHEN OLD.{i} = NEW.{i} AND
+              (NEW.{c} NOTNULL AND NOT ST_IsEmpty(NEW.{c})) AND
+              (OLD.{c} ISNULL OR ST_IsEmpty(OLD.{c}))
+         BEGIN
+           INSERT INTO {r} VALUES (
+             NEW.{i}, ST_MinX(NEW.{c}), ST_MaxX(NEW.{c}), ST_MinY(NEW.{c}), ST_MaxY(NEW.{c})
+           );
+         END;",
+    ),
+    (
+        "update5",
+        "AFTER UPDATE ON {t}
+         WHEN OLD.{i} != NEW.{i} AND
+              (NEW.{c} NOTNULL AND NOT ST_IsEmpty(NEW.{c}))
+         BEGIN
+           DELETE FROM {r} WHERE id = OLD.{i};
+           INSERT INTO {r} VALUES (
+             NEW.{i}, ST_MinX(NEW.{c}), ST_MaxX(NEW.{c}), ST_MinY(NEW.{c}), ST_MaxY(NEW.{c})
+           );
+         END;",
+    ),
+    (
+        "update4",
+        "AFTER UPDATE ON {t}
+         WHEN OLD.{i} != NEW.{i} AND
+              (NEW.{c} ISNULL OR ST_IsEmpty(NEW.{c}))
+         BEGIN
+           DELETE FROM {r} WHERE id IN (OLD.{i}, NEW.{i});
+         END;",
+    ),
+    (
+        "update2",
+        "AFTER UPDATE OF {c} ON {t}
+         WHEN OLD.{i} = NEW.{i} AND
+              (NEW.{c} ISNULL OR ST_IsEmpty(NEW.{c}))
+         BEGIN
+           DELETE FROM {r} WHERE id = OLD.{i};
+         END;",
+    ),
+    (
+        "delete",
+        "AFTER DELETE ON {t}
+         WHEN OLD.{c} NOT NULL
+         BEGIN
+           DELETE FROM {r} WHERE id = OLD.{i};
+         END;",
+    ),
+];
+
+/// Triggers replaced in 1.3.1; still dropped with the index.
+const LEGACY_TRIGGERS: [&str; 2] = ["update1", "update3"];
+
+/// `rtree_<table>_<column>`, the name the spec mandates for the index.
+fn index_name(table: &str, column: &str) -> String {
+    format!("rtree_{table}_{column}")
+}
+
+/// Whether the host SQLite provides the `rtree` module. It is built in only
+/// with `SQLITE_ENABLE_RTREE`, and without it every statement touching an
+/// index fails with a bare "no such module: rtree".
+fn has_module(conn: &Connection) -> Result<bool> {
+    let found = conn
+        .query_row("SELECT 1 FROM pragma_module_list WHERE name = 'rtree'", [], |_| Ok(()))
+        .optional()?;
+    Ok(found.is_some())
+}
+
+fn require_module(conn: &Connection) -> Result<()> {
+    if !has_module(conn)? {
+        return Err(Error::new("spatial indexes need the rtree module; SQLite must be built with SQLITE_ENABLE_RTREE"));
+    }
+    Ok(())
+}
+
+fn feature_column(conn: &Connection, table: &str) -> Result<GeometryColumn> {
+    features::geometry_column(conn, table)?
+        .ok_or_else(|| Error::new(format!("{table} is not a registered feature table")))
+}
+
+/// Creates, populates and registers the spatial index of `table` and returns
+/// its status (see [`status`]).
+pub fn create_index(conn: &mut Connection, table: &str) -> Result<String> {
+    require_module(conn)?;
+    let tx = conn.savepoint()?;
+    let column = feature_column(&tx, table)?;
+    let rtree = index_name(table, &column.column);
+    if table_exists(&tx, &rtree)? {
+        return Err(Error::new(format!("{rtree} already exists")));
+    }
+    let key = features::primary_key(&tx, table)?;
+
+    tx.execute_batch(&format!(
+        "CREATE VIRTUAL TABLE {} USING rtree(id, minx, maxx, miny, maxy)",
+        quote_identifier(&rtree)
+    ))?;
+    {
+        let mut select = tx.prepare(&format!(
+            "SELECT {}, {} FROM {} WHERE {1} NOT NULL",
+            quote_identifier(&key),
+            quote_identifier(&column.column),
+            quote_identifier(table)
+        ))?;
+        let mut insert = tx.prepare(&format!("INSERT INTO {} VALUES (?1, ?2, ?3, ?4, ?5)", quote_identifier(&rtree)))?;
+        let mut rows = select.query([])?;
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let blob: Vec<u8> = row.get(1)?;
+            let envelope = gpb::envelope(&blob).map_err(|e| Error::new(format!("{table} row {id}: {e}")))?;
+            if let Some(e) = envelope {
+                insert.execute((id, e.min_x, e.max_x, e.min_y, e.max_y))?;
+            }
+        }
+    }
+
+    for (suffix, definition) in TRIGGERS {
+        let definition = definition
+            .replace("{t}", &quote_identifier(table))
+            .replace("{c}", &quote_identifier(&column.column))
+            .replace("{i}", &quote_identifier(&key))
+            .replace("{r}", &quote_identifier(&rtree));
+        let name = quote_identifier(&format!("{rtree}_{suffix}"));
+        tx.execute_batch(&format!("CREATE TRIGGER {name} {definition}"))?;
+    }
+
+    extensions::register(&tx, Some(table), Some(&column.column), &RTREE_INDEX)?;
+    let status = status(&tx, table)?;
+    tx.commit()?;
+    Ok(status.unwrap_or_default())
+}
+
+/// Drops the spatial index of `table` with its triggers and extension
+/// registration. Returns false if there was no index.
+pub fn drop_index(conn: &mut Connection, table: &str) -> Result<bool> {
+    let tx = conn.savepoint()?;
+    let column = feature_column(&tx, table)?;
+    let rtree = index_name(table, &column.column);
+    let existed = table_exists(&tx, &rtree)?;
+    for suffix in TRIGGERS.iter().map(|(suffix, _)| suffix).chain(&LEGACY_TRIGGERS) {
+        tx.execute_batch(&format!(
+            "DROP TRIGGER IF EXISTS {}",
+            quote_identifier(&format!("{rtree}_{suffix}"))
+        ))?;
+    }
+    tx.execute_batch(&format!("DROP TABLE IF EXISTS {}", quote_identifier(&rtree)))?;
+    extensions::unregister(&tx, Some(table), Some(&column.column), RTREE_INDEX.name)?;
+    tx.commit()?;
+    Ok(existed)
+}
+
+/// What [`status`] reports about an index.
+struct IndexState {
+    rtree: String,
+    module: bool,
+    missing: Vec<&'static str>,
+}
+
+impl IndexState {
+    fn usable(&self) -> bool {
+        self.module && self.missing.is_empty()
+    }
+}
+
+/// Reads only the schema, so it stays cheap on large tables.
+fn state(conn: &Connection, table: &str) -> Result<Option<IndexState>> {
+    let Some(column) = features::geometry_column(conn, table)? else {
+        return Ok(None);
+    };
+    let rtree = index_name(table, &column.column);
+    if !table_exists(conn, &rtree)? {
+        return Ok(None);
+    }
+    let mut missing = Vec::new();
+    for (suffix, _) in TRIGGERS {
+        let found = conn
+            .query_row(
+                "SELECT 1 FROM sqlite_schema WHERE type = 'trigger' AND name = ?1",
+                [format!("{rtree}_{suffix}")],
+                |_| Ok(()),
+            )
+            .optional()?;
+        if found.is_none() {
+            missing.push(suffix);
+        }
+    }
+    Ok(Some(IndexState { rtree, module: has_module(conn)?, missing }))
+}
+
+/// Describes the spatial index of `table`, or `None` if it has none.
+///
+/// The index is reported usable if every trigger is in place and SQLite has
+/// the rtree module. Whether it holds every geometry is left to
+/// `GPKG_Validate`, which reads them all anyway (see [`entries`]).
+pub fn status(conn: &Connection, table: &str) -> Result<Option<String>> {
+    let Some(state) = state(conn, table)? else {
+        return Ok(None);
+    };
+    let mut status = state.rtree.clone();
+    if !state.module {
+        status.push_str("; no rtree module in this SQLite, not usable");
+    } else if !state.missing.is_empty() {
+        status.push_str(&format!("; missing triggers {}; not usable", state.missing.join(", ")));
+    } else {
+        status.push_str("; usable");
+    }
+    Ok(Some(status))
+}
+
+/// The name of the usable spatial index of `table` and its number of entries.
+pub fn entries(conn: &Connection, table: &str) -> Result<Option<(String, i64)>> {
+    let Some(state) = state(conn, table)?.filter(IndexState::usable) else {
+        return Ok(None);
+    };
+    let count = conn.query_row(&format!("SELECT count(*) FROM {}", quote_identifier(&state.rtree)), [], |row| {
+        row.get(0)
+    })?;
+    Ok(Some((state.rtree, count)))
+}
+
+/// Selects the features of a table whose envelope intersects a box.
+///
+/// Joins on the spatial index when it is usable and otherwise filters with
+/// `ST_MinX`/`ST_MaxX`/`ST_MinY`/`ST_MaxY`, which reads every geometry. The
+/// choice is made once, so callers querying many boxes should keep it.
+pub struct BBoxQuery {
+    /// The quoted table.
+    table: String,
+    /// The quoted geometry column.
+    column: String,
+    /// The quoted primary key and RTree, when the index is usable.
+    index: Option<(String, String)>,
+}
+
+impl BBoxQuery {
+    pub fn new(conn: &Connection, table: &str) -> Result<BBoxQuery> {
+        let column = feature_column(conn, table)?;
+        let index = match state(conn, table)?.filter(IndexState::usable) {
+            Some(index) => {
+                let key = features::primary_key(conn, table)?;
+                Some((quote_identifier(&key), quote_identifier(&index.rtree)))
+            }
+            None => None,
+        };
+        Ok(BBoxQuery { table: quote_identifier(table), column: quote_identifier(&column.column), index })
+    }
+
+    /// The query for the features intersecting `[min_x, min_y, max_x, max_y]`.
+    pub fn query(&self, [min_x, min_y, max_x, max_y]: [f64; 4]) -> Result<String> {
+        if [min_x, min_y, max_x, max_y].iter().any(|v| !v.is_finite()) || min_x > max_x || min_y > max_y {
+            return Err(Error::new("invalid bounding box: expected finite minx miny maxx maxy with min <= max"));
+        }
+        let (min_x, min_y, max_x, max_y) = (number(min_x), number(min_y), number(max_x), number(max_y));
+        let table = &self.table;
+        Ok(match &self.index {
+            Some((key, rtree)) => format!(
+                "SELECT * FROM {table} WHERE {key} IN (SELECT id FROM {rtree} \
+                 WHERE minx <= {max_x} AND maxx >= {min_x} AND miny <= {max_y} AND maxy >= {min_y})"
+            ),
+            None => {
+                let c = &self.column;
+                format!(
+                    "SELECT * FROM {table} WHERE {c} NOT NULL AND NOT ST_IsEmpty({c}) \
+                     AND ST_MinX({c}) <= {max_x} AND ST_MaxX({c}) >= {min_x} \
+                     AND ST_MinY({c}) <= {max_y} AND ST_MaxY({c}) >= {min_y}"
+                )
+            }
+        })
+    }
+}
+
+/// The features of `table` whose envelope intersects `bbox` (see [`BBoxQuery`]).
+pub fn bbox_query(conn: &Connection, table: &str, bbox: [f64; 4]) -> Result<String> {
+    BBoxQuery::new(conn, table)?.query(bbox)
+}
+
+/// Creates or replaces the view `temp.<table>_bbox` over [`bbox_query`] and
+/// returns the query, so the features can be selected in any output mode.
+pub fn bbox_view(conn: &Connection, table: &str, bbox: [f64; 4]) -> Result<String> {
+    let query = bbox_query(conn, table, bbox)?;
+    let view = quote_identifier(&format!("{table}_bbox"));
+    conn.execute_batch(&format!("DROP VIEW IF EXISTS temp.{view}; CREATE TEMP VIEW {view} AS {query}"))?;
+    Ok(query)
+}
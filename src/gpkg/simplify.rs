@@ -0,0 +1,53 @@
+//! `GPKG_Simplify(table, tolerance)`: generalizes every geometry of a
+//! feature table in place with `ST_Simplify`.
+use super::features;
+use crate::error::{Error, Result};
+use crate::geometry::gpb;
+use crate::quote_identifier;
+use crate::spatial_functions;
+use rusqlite::Connection;
+
+/// Simplifies the geometries of `table` with `tolerance` (in units of its
+/// SRS), recomputes the extent and reports the vertex counts.
+pub fn simplify(conn: &mut Connection, table: &str, tolerance: f64) -> Result<String> {
+    if tolerance < 0.0 {
+        return Err(Error::new("tolerance must not be negative"));
+    }
+    let tx = conn.savepoint()?;
+    let column = features::geometry_column(&tx, table)?
+        .ok_or_else(|| Error::new(format!("{table} is not a registered feature table")))?;
+    let key = features::primary_key(&tx, table)?;
+    let (mut geometries, mut before, mut after) = (0, 0, 0);
+    let mut extent = None;
+    {
+        let mut select = tx.prepare(&format!(
+            "SELECT {}, {} FROM {} WHERE {1} NOT NULL",
+            quote_identifier(&key),
+            quote_identifier(&column.column),
+            quote_identifier(table)
+        ))?;
+        let mut update = tx.prepare(&format!(
+            "UPDATE {} SET {} = ?1 WHERE {} = ?2",
+            quote_identifier(table),
+            quote_identifier(&column.column),
+            quote_identifier(&key)
+        ))?;
+        let mut rows = select.query([])?;
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let blob: Vec<u8> = row.get(1)?;
+            let decoded = gpb::decode(&blob).map_err(|e| Error::new(format!("{table} row {id}: {e}")))?;
+            let simplified = spatial_functions::simplify(&decoded.geometry, tolerance);
+            geometries += 1;
+            before += decoded.geometry.num_points();
+            after += simplified.num_points();
+            features::expand(&mut extent, simplified.envelope(decoded.dims));
+            if simplified != decoded.geometry {
+                update.execute((gpb::encode(&simplified, decoded.dims, decoded.srs_id), id))?;
+            }
+        }
+    }
+    features::set_extent(&tx, table, extent)?;
+    tx.commit()?;
+    Ok(format!("{table}: {geometries} geometries, {before} vertices reduced to {after}"))
+}
@@ -0,0 +1,323 @@
+//! Management of `gpkg_spatial_ref_sys`: listing, adding entries from the
+//! bundled EPSG table or from WKT files, and removing unused entries.
+use super::create::create_in;
+use super::{features, table_exists};
+use crate::error::{Error, Result};
+use rusqlite::Connection;
+use std::fmt::Write;
+use std::fs;
+
+const DEGREE: &str = "UNIT[\"degree\",0.0174532925199433,AUTHORITY[\"EPSG\",\"9122\"]]";
+const GREENWICH: &str = "PRIMEM[\"Greenwich\",0,AUTHORITY[\"EPSG\",\"8901\"]]";
+const METRE: &str = "UNIT[\"metre\",1,AUTHORITY[\"EPSG\",\"9001\"]]";
+const EASTING_NORTHING: &str = "AXIS[\"Easting\",EAST],AXIS[\"Northing\",NORTH]";
+
+/// A geographic CRS the bundled projected CRSs are based on:
+/// `(code, name, datum, datum code, spheroid, semi-major axis, inverse flattening, spheroid code, TOWGS84)`.
+type Datum = (i32, &'static str, &'static str, i32, &'static str, &'static str, &'static str, i32, Option<&'static str>);
+
+const WGS84: Datum = (4326, "WGS 84", "WGS_1984", 6326, "WGS 84", "6378137", "298.257223563", 7030, None);
+const ETRS89: Datum = (
+    4258,
+    "ETRS89",
+    "European_Terrestrial_Reference_System_1989",
+    6258,
+    "GRS 1980",
+    "6378137",
+    "298.257222101",
+    7019,
+    Some("0,0,0,0,0,0,0"),
+);
+const NAD83: Datum = (
+    4269,
+    "NAD83",
+    "North_American_Datum_1983",
+    6269,
+    "GRS 1980",
+    "6378137",
+    "298.257222101",
+    7019,
+    Some("0,0,0,0,0,0,0"),
+);
+const RGF93: Datum = (
+    4171,
+    "RGF93",
+    "Reseau_Geodesique_Francais_1993",
+    6171,
+    "GRS 1980",
+    "6378137",
+    "298.257222101",
+    7019,
+    Some("0,0,0,0,0,0,0"),
+);
+const OSGB36: Datum = (
+    4277,
+    "OSGB36",
+    "Ordnance_Survey_of_Great_Britain_1936",
+    6277,
+    "Airy 1830",
+    "6377563.396",
+    "299.3249646",
+    7001,
+    Some("446.448,-125.157,542.06,0.15,0.247,0.842,-20.489"),
+);
+
+fn geogcs((code, name, datum, datum_code, spheroid, a, rf, spheroid_code, towgs84): Datum) -> String {
+    let towgs84 = towgs84.map(|t| format!("TOWGS84[{t}],")).unwrap_or_default();
+    format!(
+        "GEOGCS[\"{name}\",DATUM[\"{datum}\",SPHEROID[\"{spheroid}\",{a},{rf},AUTHORITY[\"EPSG\",\"{spheroid_code}\"]],\
+         {towgs84}AUTHORITY[\"EPSG\",\"{datum_code}\"]],{GREENWICH},{DEGREE},AUTHORITY[\"EPSG\",\"{code}\"]]"
+    )
+}
+
+fn projcs(code: i32, name: &str, datum: Datum, projection: &str, parameters: &[(&str, f64)], axes: &str) -> String {
+    let mut wkt = format!("PROJCS[\"{name}\",{},PROJECTION[\"{projection}\"]", geogcs(datum));
+    for (parameter, value) in parameters {
+        write!(wkt, ",PARAMETER[\"{parameter}\",{value}]").unwrap();
+    }
+    write!(wkt, ",{METRE},{axes},AUTHORITY[\"EPSG\",\"{code}\"]]").unwrap();
+    wkt
+}
+
+fn utm(code: i32, datum: Datum, zone: i32, south: bool) -> (String, String) {
+    let name = format!("{} / UTM zone {zone}{}", datum.1, if south { "S" } else { "N" });
+    let parameters = [
+        ("latitude_of_origin", 0.0),
+        ("central_meridian", f64::from(zone * 6 - 183)),
+        ("scale_factor", 0.9996),
+        ("false_easting", 500000.0),
+        ("false_northing", if south { 10000000.0 } else { 0.0 }),
+    ];
+    let wkt = projcs(code, &name, datum, "Transverse_Mercator", &parameters, EASTING_NORTHING);
+    (name, wkt)
+}
+
+/// Codes of the bundled EPSG definitions besides the UTM zones.
+const BUNDLED: [i32; 10] = [4326, 4258, 4269, 4171, 4277, 3857, 3395, 3035, 27700, 2154];
+
+/// The bundled definition of `EPSG:code` as `(name, WKT)`: common
+/// geographic CRSs, web and world Mercator, LAEA Europe, British National
+/// Grid, Lambert-93 and the WGS 84 (326xx/327xx) and ETRS89 (258xx) UTM zones.
+pub fn epsg(code: i32) -> Option<(String, String)> {
+    let mercator = [("central_meridian", 0.0), ("scale_factor", 1.0), ("false_easting", 0.0), ("false_northing", 0.0)];
+    let definition = match code {
+        4326 | 4258 | 4269 | 4171 | 4277 => {
+            let datum = [WGS84, ETRS89, NAD83, RGF93, OSGB36].into_iter().find(|d| d.0 == code)?;
+            (datum.1.to_string(), geogcs(datum))
+        }
+        3857 => {
+            let name = "WGS 84 / Pseudo-Mercator";
+            let mut wkt = projcs(code, name, WGS84, "Mercator_1SP", &mercator, EASTING_NORTHING);
+            // GDAL and PROJ recognise web Mercator by this extension.
+            let proj4 = "EXTENSION[\"PROJ4\",\"+proj=merc +a=6378137 +b=6378137 +lat_ts=0 +lon_0=0 +x_0=0 +y_0=0 \
+                         +k=1 +units=m +nadgrids=@null +wktext +no_defs\"],";
+            wkt.insert_str(wkt.rfind("AUTHORITY").unwrap(), proj4);
+            (name.to_string(), wkt)
+        }
+        3395 => {
+            let name = "WGS 84 / World Mercator";
+            (name.to_string(), projcs(code, name, WGS84, "Mercator_1SP", &mercator, EASTING_NORTHING))
+        }
+        3035 => {
+            let name = "ETRS89-extended / LAEA Europe";
+            let parameters = [
+                ("latitude_of_center", 52.0),
+                ("longitude_of_center", 10.0),
+                ("false_easting", 4321000.0),
+                ("false_northing", 3210000.0),
+            ];
+            let axes = "AXIS[\"Northing\",NORTH],AXIS[\"Easting\",EAST]";
+            (name.to_string(), projcs(code, name, ETRS89, "Lambert_Azimuthal_Equal_Area", &parameters, axes))
+        }
+        27700 => {
+            let name = "OSGB36 / British National Grid";
+            let parameters = [
+                ("latitude_of_origin", 49.0),
+                ("central_meridian", -2.0),
+                ("scale_factor", 0.9996012717),
+                ("false_easting", 400000.0),
+                ("false_northing", -100000.0),
+            ];
+            (name.to_string(), projcs(code, name, OSGB36, "Transverse_Mercator", &parameters, EASTING_NORTHING))
+        }
+        2154 => {
+            let name = "RGF93 / Lambert-93";
+            let parameters = [
+                ("standard_parallel_1", 49.0),
+                ("standard_parallel_2", 44.0),
+                ("latitude_of_origin", 46.5),
+                ("central_meridian", 3.0),
+                ("false_easting", 700000.0),
+                ("false_northing", 6600000.0),
+            ];
+            let axes = "AXIS[\"X\",EAST],AXIS[\"Y\",NORTH]";
+            (name.to_string(), projcs(code, name, RGF93, "Lambert_Conformal_Conic_2SP", &parameters, axes))
+        }
+        32601..=32660 => utm(code, WGS84, code - 32600, false),
+        32701..=32760 => utm(code, WGS84, code - 32700, true),
+        25828..=25838 => utm(code, ETRS89, code - 25800, false),
+        _ => return None,
+    };
+    Some(definition)
+}
+
+/// One line per entry of `gpkg_spatial_ref_sys`, with the tables using it.
+pub fn list(conn: &Connection) -> Result<String> {
+    if !table_exists(conn, "gpkg_spatial_ref_sys")? {
+        return Err(Error::new("not a GeoPackage: gpkg_spatial_ref_sys does not exist"));
+    }
+    let mut stmt = conn.prepare(
+        "SELECT srs_id, srs_name, organization, organization_coordsys_id FROM gpkg_spatial_ref_sys ORDER BY srs_id",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i32>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, i64>(3)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let mut out = String::new();
+    for (srs_id, name, organization, code) in rows {
+        let users = users(conn, srs_id)?;
+        write!(out, "{srs_id:>6}  {name} ({organization}:{code})").unwrap();
+        if !users.is_empty() {
+            write!(out, "  used by {}", users.join(", ")).unwrap();
+        }
+        out.push('\n');
+    }
+    Ok(out.trim_end().to_string())
+}
+
+/// The bundled EPSG definitions that [`add_epsg`] can add.
+pub fn list_bundled() -> String {
+    let utm_zones = (32601..=32660).chain(32701..=32760).chain(25828..=25838);
+    let mut out = String::new();
+    for code in BUNDLED.into_iter().chain(utm_zones) {
+        let (name, _) = epsg(code).unwrap();
+        writeln!(out, "{code:>6}  {name}").unwrap();
+    }
+    out.trim_end().to_string()
+}
+
+/// Tables whose contents or geometry columns reference `srs_id`.
+fn users(conn: &Connection, srs_id: i32) -> Result<Vec<String>> {
+    let mut sql = "SELECT table_name FROM gpkg_contents WHERE srs_id = ?1".to_string();
+    for table in ["gpkg_geometry_columns", "gpkg_tile_matrix_set"] {
+        if table_exists(conn, table)? {
+            write!(sql, " UNION SELECT table_name FROM {table} WHERE srs_id = ?1").unwrap();
+        }
+    }
+    let mut stmt = conn.prepare(&sql)?;
+    let names = stmt.query_map([srs_id], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+    Ok(names)
+}
+
+fn insert(conn: &Connection, srs_id: i32, name: &str, organization: &str, code: i64, definition: &str) -> Result<()> {
+    create_in(conn)?;
+    if features::srs_exists(conn, srs_id)? {
+        return Err(Error::new(format!("srs_id {srs_id} already exists")));
+    }
+    conn.execute(
+        "INSERT INTO gpkg_spatial_ref_sys (srs_name, srs_id, organization, organization_coordsys_id, definition)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        (name, srs_id, organization, code, definition),
+    )?;
+    Ok(())
+}
+
+/// Adds `EPSG:code` from the bundled table with `srs_id = code`.
+pub fn add_epsg(conn: &mut Connection, code: i32) -> Result<()> {
+    let tx = conn.savepoint()?;
+    add_epsg_in(&tx, code)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// [`add_epsg`] for callers that already hold a savepoint.
+pub fn add_epsg_in(conn: &Connection, code: i32) -> Result<()> {
+    let (name, definition) =
+        epsg(code).ok_or_else(|| Error::new(format!("EPSG:{code} is not in the bundled table; add it from a WKT file")))?;
+    insert(conn, code, &name, "EPSG", code.into(), &definition)
+}
+
+/// Adds the WKT definition in `path` as `srs_id`. Name and authority code
+/// are taken from the WKT (`AUTHORITY[...]` or WKT2 `ID[...]`) if present.
+pub fn add_wkt_file(conn: &mut Connection, srs_id: i32, path: &str) -> Result<()> {
+    let wkt = fs::read_to_string(path).map_err(|e| Error::new(format!("cannot read {path}: {e}")))?;
+    let wkt = wkt.trim();
+    let name = wkt_name(wkt).ok_or_else(|| Error::new(format!("{path} does not contain a WKT definition")))?;
+    let (organization, code) = wkt_authority(wkt).unwrap_or(("NONE".to_string(), srs_id.into()));
+    let tx = conn.savepoint()?;
+    insert(&tx, srs_id, &name, &organization, code, wkt)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// The name of the outermost WKT object, e.g. `WGS 84` in `GEOGCS["WGS 84",...`.
+fn wkt_name(wkt: &str) -> Option<String> {
+    let open = wkt.find('[')?;
+    let rest = wkt[open + 1..].trim_start().strip_prefix('"')?;
+    Some(rest[..rest.find('"')?].to_string())
+}
+
+/// The authority of the outermost WKT object, which is its last
+/// `AUTHORITY`/`ID` element: `("EPSG", 4326)`.
+fn wkt_authority(wkt: &str) -> Option<(String, i64)> {
+    let start = ["AUTHORITY[", "ID["].iter().filter_map(|k| wkt.rfind(k).map(|i| i + k.len())).max()?;
+    let body = &wkt[start..start + wkt[start..].find(']')?];
+    let mut parts = body.split(',').map(|p| p.trim().trim_matches('"'));
+    let organization = parts.next()?.to_string();
+    let code = parts.next()?.parse().ok()?;
+    // Only the outermost object's authority sits right before the final `]`.
+    let tail = wkt[start..].trim_end();
+    (tail.matches(']').count() == 2).then_some((organization, code))
+}
+
+/// Removes `srs_id`, refusing the entries the spec requires and those still in use.
+pub fn remove(conn: &Connection, srs_id: i32) -> Result<()> {
+    if matches!(srs_id, 4326 | -1 | 0) {
+        return Err(Error::new(format!("srs_id {srs_id} is required by the GeoPackage specification")));
+    }
+    if !table_exists(conn, "gpkg_spatial_ref_sys")? || !features::srs_exists(conn, srs_id)? {
+        return Err(Error::new(format!("srs_id {srs_id} does not exist")));
+    }
+    let users = users(conn, srs_id)?;
+    if !users.is_empty() {
+        return Err(Error::new(format!("srs_id {srs_id} is used by {}", users.join(", "))));
+    }
+    conn.execute("DELETE FROM gpkg_spatial_ref_sys WHERE srs_id = ?1", [srs_id])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WKT1: &str = r#"PROJCS["WGS 84 / UTM zone 31N",
+        GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],
+            AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0],UNIT["degree",0.0174532925199433],AUTHORITY["EPSG","4326"]],
+        PROJECTION["Transverse_Mercator"],UNIT["metre",1,AUTHORITY["EPSG","9001"]],AUTHORITY["EPSG","32631"]]"#;
+    const WKT2: &str = r#"GEOGCRS["WGS 84",
+        DATUM["World Geodetic System 1984",ELLIPSOID["WGS 84",6378137,298.257223563]],CS[ellipsoidal,2],
+        AXIS["latitude",north],AXIS["longitude",east],ANGLEUNIT["degree",0.0174532925199433],ID["EPSG",4326]]"#;
+
+    #[test]
+    fn names() {
+        assert_eq!(wkt_name(WKT1).as_deref(), Some("WGS 84 / UTM zone 31N"));
+        assert_eq!(wkt_name(WKT2).as_deref(), Some("WGS 84"));
+        assert_eq!(wkt_name(r#"LOCAL_CS[ "site grid" ]"#).as_deref(), Some("site grid"));
+        assert_eq!(wkt_name("+proj=longlat +datum=WGS84"), None);
+        assert_eq!(wkt_name("GEOGCS[\"unterminated"), None);
+    }
+
+    #[test]
+    fn authorities() {
+        assert_eq!(wkt_authority(WKT1), Some(("EPSG".to_string(), 32631)));
+        assert_eq!(wkt_authority(WKT2), Some(("EPSG".to_string(), 4326)));
+        // Only nested objects carry an authority.
+        let nested = r#"PROJCS["custom",GEOGCS["WGS 84",AUTHORITY["EPSG","4326"]],UNIT["metre",1]]"#;
+        assert_eq!(wkt_authority(nested), None);
+        let unit_last = r#"PROJCS["custom",PROJECTION["Mercator"],UNIT["metre",1,AUTHORITY["EPSG","9001"]]]"#;
+        assert_eq!(wkt_authority(unit_last), None);
+        assert_eq!(wkt_authority(r#"GEOGCS["x",AUTHORITY["EPSG","abc"]]"#), None);
+        assert_eq!(wkt_authority(r#"GEOGCS["x"]"#), None);
+    }
+}
@@ -0,0 +1,48 @@
+//! The minimal runtime SQL functions of the GeoPackage specification
+//! (`ST_IsEmpty`, `ST_MinX`, …), which the RTree triggers and envelope
+//! queries rely on. NULL geometries yield NULL.
+use crate::error::Result;
+use crate::function::{Args, Context, Value};
+use crate::geometry::{Envelope, gpb};
+
+/// Applies `f` to the envelope of the geometry in argument 0.
+fn with_envelope(args: &Args, f: impl FnOnce(Option<Envelope>) -> Value) -> Result<Value> {
+    match args.opt_blob(0) {
+        Some(blob) => Ok(f(gpb::envelope(blob)?)),
+        None => Ok(Value::Null),
+    }
+}
+
+pub fn is_empty_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    with_envelope(args, |e| e.is_none().into())
+}
+
+pub fn min_x_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    with_envelope(args, |e| e.map(|e| e.min_x).into())
+}
+
+pub fn max_x_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    with_envelope(args, |e| e.map(|e| e.max_x).into())
+}
+
+pub fn min_y_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    with_envelope(args, |e| e.map(|e| e.min_y).into())
+}
+
+pub fn max_y_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    with_envelope(args, |e| e.map(|e| e.max_y).into())
+}
+
+pub fn geometry_type_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    match args.opt_blob(0) {
+        Some(blob) => Ok(gpb::decode(blob)?.geometry.type_name().into()),
+        None => Ok(Value::Null),
+    }
+}
+
+pub fn srid_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    match args.opt_blob(0) {
+        Some(blob) => Ok(i64::from(gpb::header(blob)?.srs_id).into()),
+        None => Ok(Value::Null),
+    }
+}
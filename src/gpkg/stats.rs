@@ -0,0 +1,126 @@
+//! `GPKG_Stats(table)`: a data-quality overview of a feature table.
+use super::features;
+use crate::error::{Error, Result};
+use crate::geometry::{gpb, number};
+use crate::quote_identifier;
+use rusqlite::Connection;
+use rusqlite::types::ValueRef;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+fn value(value: ValueRef<'_>) -> String {
+    match value {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Integer(i) => i.to_string(),
+        ValueRef::Real(r) => number(r),
+        ValueRef::Text(t) => {
+            let text = String::from_utf8_lossy(t);
+            match text.char_indices().nth(40) {
+                Some((end, _)) => format!("{:?}...", &text[..end]),
+                None => format!("{text:?}"),
+            }
+        }
+        ValueRef::Blob(b) => format!("BLOB({} bytes)", b.len()),
+    }
+}
+
+/// Feature count, geometry types, extent, vertex counts, NULL and empty
+/// geometries, and the value range of every attribute column of `table`.
+/// The extent is computed from the geometries, not taken from
+/// `gpkg_contents`.
+pub fn stats(conn: &Connection, table: &str) -> Result<String> {
+    let column = features::geometry_column(conn, table)?
+        .ok_or_else(|| Error::new(format!("{table} is not a registered feature table")))?;
+
+    let (mut count, mut nulls, mut empty) = (0, 0, 0);
+    let mut types: BTreeMap<String, usize> = BTreeMap::new();
+    let mut vertices = Vec::new();
+    let mut extent = None;
+    {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM {}",
+            quote_identifier(&column.column),
+            quote_identifier(table)
+        ))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            count += 1;
+            let Some(blob) = row.get::<_, Option<Vec<u8>>>(0)? else {
+                nulls += 1;
+                continue;
+            };
+            let decoded = gpb::decode(&blob).map_err(|e| Error::new(format!("{table} feature {count}: {e}")))?;
+            let type_name = format!("{}{}", decoded.geometry.type_name(), decoded.dims.suffix());
+            *types.entry(type_name).or_default() += 1;
+            if decoded.geometry.is_empty() {
+                empty += 1;
+                continue;
+            }
+            vertices.push(decoded.geometry.num_points());
+            features::expand(&mut extent, decoded.geometry.envelope(decoded.dims));
+        }
+    }
+
+    let mut out = String::new();
+    writeln!(out, "{table}: {count} features").unwrap();
+    writeln!(out, "  geometry column: {} ({})", column.column, column.type_name).unwrap();
+    writeln!(out, "  null geometries: {nulls}").unwrap();
+    writeln!(out, "  empty geometries: {empty}").unwrap();
+    let histogram: Vec<String> = types.iter().map(|(name, n)| format!("{name} {n}")).collect();
+    if !histogram.is_empty() {
+        writeln!(out, "  geometry types: {}", histogram.join(", ")).unwrap();
+    }
+    if let Some(e) = extent {
+        writeln!(
+            out,
+            "  extent: [{}, {}, {}, {}]",
+            number(e.min_x),
+            number(e.min_y),
+            number(e.max_x),
+            number(e.max_y)
+        )
+        .unwrap();
+    }
+    if !vertices.is_empty() {
+        vertices.sort_unstable();
+        let total: usize = vertices.iter().sum();
+        let percentile = |p: usize| vertices[(vertices.len() - 1) * p / 100];
+        writeln!(
+            out,
+            "  vertices: {total} total; per geometry min {}, median {}, p95 {}, max {}, mean {}",
+            vertices[0],
+            percentile(50),
+            percentile(95),
+            vertices[vertices.len() - 1],
+            number((total as f64 / vertices.len() as f64 * 10.0).round() / 10.0)
+        )
+        .unwrap();
+    }
+
+    let key = features::primary_key(conn, table).ok();
+    let attributes: Vec<String> = features::column_names(conn, table)?
+        .into_iter()
+        .filter(|c| !c.eq_ignore_ascii_case(&column.column) && Some(c) != key.as_ref())
+        .collect();
+    if !attributes.is_empty() {
+        writeln!(out, "  attributes:").unwrap();
+    }
+    for attribute in &attributes {
+        let quoted = quote_identifier(attribute);
+        let sql = format!(
+            "SELECT count({quoted}), count(DISTINCT {quoted}), min({quoted}), max({quoted}) FROM {}",
+            quote_identifier(table)
+        );
+        let line = conn.query_row(&sql, [], |row| {
+            let (values, distinct): (i64, i64) = (row.get(0)?, row.get(1)?);
+            Ok(format!(
+                "    {attribute}: {} null, {distinct} distinct, min {}, max {}",
+                count - values,
+                value(row.get_ref(2)?),
+                value(row.get_ref(3)?)
+            ))
+        })?;
+        writeln!(out, "{line}").unwrap();
+    }
+    Ok(out.trim_end().to_string())
+}
@@ -0,0 +1,428 @@
+//! Tile pyramids: `gpkg_tile_matrix_set`/`gpkg_tile_matrix` inspection,
+//! tile extraction and import from XYZ/TMS directories.
+use super::create::{TILES_SCHEMA, create_in};
+use super::extensions::{self, WEBP};
+use super::{features, srs, table_exists};
+use crate::error::{Error, Result};
+use crate::geometry::number;
+use crate::quote_identifier;
+use rusqlite::{Connection, OptionalExtension};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Tiles inserted per transaction by the importers.
+pub const BATCH: usize = 500;
+
+/// Half the width of the web Mercator square, in metres.
+pub const WEB_MERCATOR_EXTENT: f64 = 20037508.342789244;
+/// The latitude at which the web Mercator square ends.
+pub const MAX_LATITUDE: f64 = 85.0511287798066;
+
+/// The image format of tile data, recognised by its signature.
+pub fn image_format(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("jpeg")
+    } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("webp")
+    } else {
+        None
+    }
+}
+
+/// Width and height in pixels of PNG and JPEG tile data.
+pub fn image_size(data: &[u8]) -> Option<(i64, i64)> {
+    let be = |bytes: &[u8]| bytes.iter().fold(0i64, |n, b| n << 8 | i64::from(*b));
+    match image_format(data)? {
+        "png" if data.len() >= 24 => Some((be(&data[16..20]), be(&data[20..24]))),
+        "jpeg" => {
+            // Walk the marker segments up to the start-of-frame.
+            let mut i = 2;
+            while i + 9 < data.len() && data[i] == 0xFF {
+                let marker = data[i + 1];
+                if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+                    return Some((be(&data[i + 7..i + 9]), be(&data[i + 5..i + 7])));
+                }
+                i += 2 + be(&data[i + 2..i + 4]) as usize;
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// A global tiling scheme: web Mercator (one tile at zoom 0) or WGS 84
+/// geodetic (two tiles side by side at zoom 0).
+#[derive(Debug, Clone, Copy)]
+pub struct Grid {
+    pub srs_id: i32,
+    pub bounds: [f64; 4],
+}
+
+impl Grid {
+    pub fn for_srs(srs_id: i32) -> Result<Grid> {
+        let bounds = match srs_id {
+            3857 => [-WEB_MERCATOR_EXTENT, -WEB_MERCATOR_EXTENT, WEB_MERCATOR_EXTENT, WEB_MERCATOR_EXTENT],
+            4326 => [-180.0, -90.0, 180.0, 90.0],
+            _ => return Err(Error::new(format!("no tiling scheme for srs_id {srs_id}; use 3857 or 4326"))),
+        };
+        Ok(Grid { srs_id, bounds })
+    }
+
+    /// Matrix width and height at `zoom`.
+    pub fn matrix_size(&self, zoom: i64) -> (i64, i64) {
+        let n = 1i64 << zoom;
+        if self.srs_id == 4326 { (2 * n, n) } else { (n, n) }
+    }
+}
+
+/// Creates the tile table of `table` and registers it in `gpkg_contents` and
+/// `gpkg_tile_matrix_set`, or checks that an existing pyramid uses `grid`.
+/// The SRS is added from the bundled EPSG table if it is missing.
+pub fn create_pyramid(conn: &mut Connection, table: &str, grid: Grid) -> Result<()> {
+    let tx = conn.savepoint()?;
+    create_in(&tx)?;
+    if !features::srs_exists(&tx, grid.srs_id)? {
+        srs::add_epsg_in(&tx, grid.srs_id)?;
+    }
+    tx.execute_batch(TILES_SCHEMA)?;
+    let existing: Option<i32> = tx
+        .query_row("SELECT srs_id FROM gpkg_tile_matrix_set WHERE table_name = ?1", [table], |row| row.get(0))
+        .optional()?;
+    match existing {
+        Some(srs_id) if srs_id == grid.srs_id => return Ok(()),
+        Some(srs_id) => return Err(Error::new(format!("{table} is a tile pyramid in srs_id {srs_id}"))),
+        None if table_exists(&tx, table)? => return Err(Error::new(format!("table {table} already exists"))),
+        None => {}
+    }
+    tx.execute_batch(&format!(
+        "CREATE TABLE {} (
+           id INTEGER PRIMARY KEY AUTOINCREMENT,
+           zoom_level INTEGER NOT NULL,
+           tile_column INTEGER NOT NULL,
+           tile_row INTEGER NOT NULL,
+           tile_data BLOB NOT NULL,
+           UNIQUE (zoom_level, tile_column, tile_row)
+         )",
+        quote_identifier(table)
+    ))?;
+    let [min_x, min_y, max_x, max_y] = grid.bounds;
+    tx.execute(
+        "INSERT INTO gpkg_contents (table_name, data_type, identifier, srs_id, min_x, min_y, max_x, max_y)
+         VALUES (?1, 'tiles', ?1, ?2, ?3, ?4, ?5, ?6)",
+        (table, grid.srs_id, min_x, min_y, max_x, max_y),
+    )?;
+    tx.execute(
+        "INSERT INTO gpkg_tile_matrix_set (table_name, srs_id, min_x, min_y, max_x, max_y)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        (table, grid.srs_id, min_x, min_y, max_x, max_y),
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Registers zoom level `zoom` of `table` in `gpkg_tile_matrix` unless it is already.
+pub fn add_tile_matrix(conn: &Connection, table: &str, grid: Grid, zoom: i64, tile_size: (i64, i64)) -> Result<()> {
+    let (width, height) = grid.matrix_size(zoom);
+    let [min_x, min_y, max_x, max_y] = grid.bounds;
+    conn.execute(
+        "INSERT OR IGNORE INTO gpkg_tile_matrix
+           (table_name, zoom_level, matrix_width, matrix_height, tile_width, tile_height, pixel_x_size, pixel_y_size)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        (
+            table,
+            zoom,
+            width,
+            height,
+            tile_size.0,
+            tile_size.1,
+            (max_x - min_x) / (width * tile_size.0) as f64,
+            (max_y - min_y) / (height * tile_size.1) as f64,
+        ),
+    )?;
+    Ok(())
+}
+
+/// Numeric entries of `dir`, e.g. the zoom or column directories of a tile tree.
+fn numbered(dir: &Path) -> Result<Vec<(i64, PathBuf)>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|e| Error::new(format!("cannot read {}: {e}", dir.display())))? {
+        let path = entry?.path();
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+        if let Ok(n) = stem.parse::<i64>() {
+            entries.push((n, path));
+        }
+    }
+    Ok(entries)
+}
+
+/// Loads the `DIR/z/x/y.<format>` tiles of an XYZ (or, with scheme `tms`,
+/// bottom-up TMS) directory into the tile pyramid `table`, creating it if
+/// needed. Tiles are committed in batches. Returns the number of tiles.
+pub fn import(conn: &mut Connection, dir: &str, table: &str, format: &str, srs_id: i32, scheme: &str) -> Result<i64> {
+    let (signature, extensions): (&str, &[&str]) = match format {
+        "png" => ("png", &["png"]),
+        "jpeg" | "jpg" => ("jpeg", &["jpg", "jpeg"]),
+        "webp" => ("webp", &["webp"]),
+        _ => return Err(Error::new(format!("unknown tile format {format}; use png, jpeg or webp"))),
+    };
+    let flip = match scheme {
+        "xyz" => false,
+        "tms" => true,
+        _ => return Err(Error::new(format!("unknown tile scheme {scheme}; use xyz or tms"))),
+    };
+    let grid = Grid::for_srs(srs_id)?;
+
+    let mut tiles = Vec::new();
+    for (zoom, zoom_dir) in numbered(Path::new(dir))?.into_iter().filter(|(_, p)| p.is_dir()) {
+        if !(0..=30).contains(&zoom) {
+            continue;
+        }
+        for (x, column_dir) in numbered(&zoom_dir)?.into_iter().filter(|(_, p)| p.is_dir()) {
+            for (y, path) in numbered(&column_dir)? {
+                let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+                if extensions.iter().any(|e| extension.eq_ignore_ascii_case(e)) {
+                    tiles.push((zoom, x, y, path));
+                }
+            }
+        }
+    }
+    if tiles.is_empty() {
+        return Err(Error::new(format!("no {format} tiles found in {dir} (expected {dir}/z/x/y.{})", extensions[0])));
+    }
+    tiles.sort();
+
+    create_pyramid(conn, table, grid)?;
+    if signature == "webp" {
+        extensions::register(conn, Some(table), Some("tile_data"), &WEBP)?;
+    }
+    let mut zooms = HashSet::new();
+    for batch in tiles.chunks(BATCH) {
+        let tx = conn.savepoint()?;
+        {
+            let mut insert = tx.prepare(&format!(
+                "INSERT OR REPLACE INTO {} (zoom_level, tile_column, tile_row, tile_data) VALUES (?1, ?2, ?3, ?4)",
+                quote_identifier(table)
+            ))?;
+            for (zoom, x, y, path) in batch {
+                let (width, height) = grid.matrix_size(*zoom);
+                if !(0..width).contains(x) || !(0..height).contains(y) {
+                    return Err(Error::new(format!("{}: outside the zoom {zoom} tile matrix", path.display())));
+                }
+                let data = fs::read(path).map_err(|e| Error::new(format!("cannot read {}: {e}", path.display())))?;
+                if image_format(&data) != Some(signature) {
+                    return Err(Error::new(format!("{} is not a {signature} image", path.display())));
+                }
+                if zooms.insert(*zoom) {
+                    add_tile_matrix(&tx, table, grid, *zoom, image_size(&data).unwrap_or((256, 256)))?;
+                }
+                let row = if flip { height - 1 - y } else { *y };
+                insert.execute((zoom, x, row, &data))?;
+            }
+        }
+        tx.commit()?;
+    }
+    features::extend_contents(conn, table, None)?;
+    Ok(tiles.len() as i64)
+}
+
+/// A row of `gpkg_tile_matrix`.
+struct TileMatrix {
+    zoom: i64,
+    width: i64,
+    height: i64,
+    tile_width: i64,
+    tile_height: i64,
+    pixel_x: f64,
+    pixel_y: f64,
+}
+
+/// Tiles stored at one zoom level: count and column/row ranges.
+struct Stored {
+    count: i64,
+    columns: (i64, i64),
+    rows: (i64, i64),
+}
+
+fn tile_matrices(conn: &Connection, table: &str) -> Result<Vec<TileMatrix>> {
+    if !table_exists(conn, "gpkg_tile_matrix")? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare(
+        "SELECT zoom_level, matrix_width, matrix_height, tile_width, tile_height, pixel_x_size, pixel_y_size
+         FROM gpkg_tile_matrix WHERE table_name = ?1 ORDER BY zoom_level",
+    )?;
+    let matrices = stmt
+        .query_map([table], |row| {
+            Ok(TileMatrix {
+                zoom: row.get(0)?,
+                width: row.get(1)?,
+                height: row.get(2)?,
+                tile_width: row.get(3)?,
+                tile_height: row.get(4)?,
+                pixel_x: row.get(5)?,
+                pixel_y: row.get(6)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(matrices)
+}
+
+fn stored(conn: &Connection, table: &str) -> Result<BTreeMap<i64, Stored>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT zoom_level, count(*), min(tile_column), max(tile_column), min(tile_row), max(tile_row)
+         FROM {} GROUP BY zoom_level",
+        quote_identifier(table)
+    ))?;
+    let stored = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                Stored { count: row.get(1)?, columns: (row.get(2)?, row.get(3)?), rows: (row.get(4)?, row.get(5)?) },
+            ))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(stored)
+}
+
+/// The tile matrix set of `table` and, per zoom level, the matrix
+/// dimensions, tile size, pixel size and the tiles actually stored.
+pub fn describe(conn: &Connection, table: &str) -> Result<String> {
+    let set = if table_exists(conn, "gpkg_tile_matrix_set")? {
+        conn.query_row(
+            "SELECT srs_id, min_x, min_y, max_x, max_y FROM gpkg_tile_matrix_set WHERE table_name = ?1",
+            [table],
+            |row| Ok((row.get::<_, i64>(0)?, [row.get::<_, f64>(1)?, row.get(2)?, row.get(3)?, row.get(4)?])),
+        )
+        .optional()?
+    } else {
+        None
+    };
+    let (srs_id, bounds) = set.ok_or_else(|| Error::new(format!("{table} is not a registered tile pyramid")))?;
+    if !table_exists(conn, table)? {
+        return Err(Error::new(format!("tile table {table} does not exist")));
+    }
+    let matrices = tile_matrices(conn, table)?;
+    let mut stored = stored(conn, table)?;
+
+    let mut out = String::new();
+    writeln!(out, "{table} (tiles)").unwrap();
+    writeln!(out, "  srs:    {srs_id}").unwrap();
+    let bounds: Vec<_> = bounds.iter().map(|v| number(*v)).collect();
+    writeln!(out, "  bounds: [{}]", bounds.join(", ")).unwrap();
+    writeln!(out, "  zoom  matrix       tile size  tiles                                pixel size").unwrap();
+    for m in &matrices {
+        let tiles = match stored.remove(&m.zoom) {
+            Some(s) => format!(
+                "{} of {}, columns {}-{}, rows {}-{}",
+                s.count,
+                m.width * m.height,
+                s.columns.0,
+                s.columns.1,
+                s.rows.0,
+                s.rows.1
+            ),
+            None => format!("0 of {}", m.width * m.height),
+        };
+        writeln!(
+            out,
+            "  {:>4}  {:<11}  {:<9}  {tiles:<35}  {} x {}",
+            m.zoom,
+            format!("{} x {}", m.width, m.height),
+            format!("{} x {}", m.tile_width, m.tile_height),
+            number(m.pixel_x),
+            number(m.pixel_y),
+        )
+        .unwrap();
+    }
+    // Tiles at zoom levels gpkg_tile_matrix does not describe are invisible to readers.
+    for (zoom, s) in stored {
+        writeln!(out, "  {zoom:>4}  no tile matrix, {} tiles", s.count).unwrap();
+    }
+    Ok(out.trim_end().to_string())
+}
+
+/// The data of the tile at `zoom`/`column`/`row` of `table`.
+pub fn tile(conn: &Connection, table: &str, zoom: i64, column: i64, row: i64) -> Result<Vec<u8>> {
+    if !table_exists(conn, table)? {
+        return Err(Error::new(format!("tile table {table} does not exist")));
+    }
+    conn.query_row(
+        &format!(
+            "SELECT tile_data FROM {} WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+            quote_identifier(table)
+        ),
+        [zoom, column, row],
+        |row| row.get(0),
+    )
+    .optional()?
+    .ok_or_else(|| Error::new(format!("{table} has no tile at zoom {zoom}, column {column}, row {row}")))
+}
+
+/// Writes the tile at `zoom`/`column`/`row` of `table` to `path` and
+/// returns its format and size, e.g. `png, 1234 bytes`.
+pub fn extract(conn: &Connection, table: &str, zoom: i64, column: i64, row: i64, path: &str) -> Result<String> {
+    let data = tile(conn, table, zoom, column, row)?;
+    fs::write(path, &data).map_err(|e| Error::new(format!("cannot write {path}: {e}")))?;
+    Ok(format!("{}, {} bytes", image_format(&data).unwrap_or("unknown format"), data.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A JPEG start, the given segments and a baseline start-of-frame.
+    fn jpeg(segments: &[(u8, &[u8])], width: u16, height: u16) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8];
+        for (marker, body) in segments {
+            data.extend_from_slice(&[0xFF, *marker]);
+            data.extend_from_slice(&(body.len() as u16 + 2).to_be_bytes());
+            data.extend_from_slice(body);
+        }
+        data.extend_from_slice(&[0xFF, 0xC0, 0, 11, 8]);
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&[1, 1, 0x11, 0]);
+        data
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(image_format(b"\x89PNG\r\n\x1a\n"), Some("png"));
+        assert_eq!(image_format(&[0xFF, 0xD8, 0xFF, 0xE0]), Some("jpeg"));
+        assert_eq!(image_format(b"RIFF\0\0\0\0WEBPVP8 "), Some("webp"));
+        assert_eq!(image_format(b"\x1f\x8b\x08\0"), None);
+        assert_eq!(image_format(b"RIFF\0\0\0\0WAVE"), None);
+    }
+
+    #[test]
+    fn png_size() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&512u32.to_be_bytes());
+        png.extend_from_slice(&256u32.to_be_bytes());
+        assert_eq!(image_size(&png), Some((512, 256)));
+        assert_eq!(image_size(&png[..20]), None);
+    }
+
+    #[test]
+    fn jpeg_size() {
+        assert_eq!(image_size(&jpeg(&[], 256, 128)), Some((256, 128)));
+        // APP0 and a Huffman table (0xC4, in the SOF range but not a frame)
+        // come before the frame header.
+        let data = jpeg(&[(0xE0, b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0"), (0xC4, &[0; 20])], 640, 480);
+        assert_eq!(image_size(&data), Some((640, 480)));
+        // Truncated inside a segment, or with a segment that runs past the end.
+        assert_eq!(image_size(&data[..10]), None);
+        let mut runaway = vec![0xFF, 0xD8, 0xFF, 0xE0, 0xFF, 0xFF];
+        runaway.extend_from_slice(&[0; 16]);
+        assert_eq!(image_size(&runaway), None);
+    }
+
+    #[test]
+    fn webp_has_no_size() {
+        assert_eq!(image_size(b"RIFF\0\0\0\0WEBPVP8 \0\0\0\0\0\0\0\0"), None);
+    }
+}
@@ -0,0 +1,417 @@
+//! `GPKG_Validate(?mode?)`: checks the core requirements of the GeoPackage
+//! specification and reports each as PASS, WARN or FAIL.
+use super::features::{self, GeometryColumn};
+use super::{APPLICATION_ID, LEGACY_APPLICATION_IDS, application_id, extensions, rtree, table_exists, user_version};
+use crate::error::Result;
+use crate::geometry::{Envelope, gpb};
+use crate::quote_identifier;
+use rusqlite::Connection;
+use std::fmt;
+
+/// Columns the spec requires in each metadata table.
+const REQUIRED_COLUMNS: [(&str, &[&str]); 3] = [
+    (
+        "gpkg_spatial_ref_sys",
+        &["srs_name", "srs_id", "organization", "organization_coordsys_id", "definition", "description"],
+    ),
+    (
+        "gpkg_contents",
+        &[
+            "table_name",
+            "data_type",
+            "identifier",
+            "description",
+            "last_change",
+            "min_x",
+            "min_y",
+            "max_x",
+            "max_y",
+            "srs_id",
+        ],
+    ),
+    (
+        "gpkg_geometry_columns",
+        &["table_name", "column_name", "geometry_type_name", "srs_id", "z", "m"],
+    ),
+];
+
+const CORE_GEOMETRY_TYPES: [&str; 8] = [
+    "GEOMETRY",
+    "POINT",
+    "LINESTRING",
+    "POLYGON",
+    "MULTIPOINT",
+    "MULTILINESTRING",
+    "MULTIPOLYGON",
+    "GEOMETRYCOLLECTION",
+];
+
+/// Geometry types of the non-linear geometry types extension.
+const EXTENSION_GEOMETRY_TYPES: [&str; 7] = [
+    "CIRCULARSTRING",
+    "COMPOUNDCURVE",
+    "CURVEPOLYGON",
+    "MULTICURVE",
+    "MULTISURFACE",
+    "CURVE",
+    "SURFACE",
+];
+
+/// Stops reporting individual bad geometries of a table after this many.
+const MAX_GEOMETRY_ERRORS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Level::Pass => "PASS",
+            Level::Warn => "WARN",
+            Level::Fail => "FAIL",
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub checks: Vec<(Level, String)>,
+}
+
+impl Report {
+    fn pass(&mut self, msg: impl Into<String>) {
+        self.checks.push((Level::Pass, msg.into()));
+    }
+
+    fn warn(&mut self, msg: impl Into<String>) {
+        self.checks.push((Level::Warn, msg.into()));
+    }
+
+    fn fail(&mut self, msg: impl Into<String>) {
+        self.checks.push((Level::Fail, msg.into()));
+    }
+
+    fn check(&mut self, ok: bool, pass: impl Into<String>, fail: impl Into<String>) -> bool {
+        if ok { self.pass(pass) } else { self.fail(fail) }
+        ok
+    }
+
+    fn count(&self, level: Level) -> usize {
+        self.checks.iter().filter(|(l, _)| *l == level).count()
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.count(Level::Fail) > 0
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (level, msg) in &self.checks {
+            writeln!(f, "{level} {msg}")?;
+        }
+        write!(
+            f,
+            "{} passed, {} warnings, {} failed",
+            self.count(Level::Pass),
+            self.count(Level::Warn),
+            self.count(Level::Fail)
+        )
+    }
+}
+
+pub fn validate(conn: &Connection) -> Result<Report> {
+    let mut report = Report::default();
+    file_header(conn, &mut report)?;
+    integrity(conn, &mut report)?;
+
+    let mut have_tables = true;
+    for (table, columns) in REQUIRED_COLUMNS {
+        let required = table != "gpkg_geometry_columns";
+        if !table_exists(conn, table)? {
+            if required {
+                report.fail(format!("{table} does not exist"));
+                have_tables = false;
+            }
+            continue;
+        }
+        let existing = features::column_names(conn, table)?;
+        let missing: Vec<&str> = columns
+            .iter()
+            .copied()
+            .filter(|c| !existing.iter().any(|e| e.eq_ignore_ascii_case(c)))
+            .collect();
+        if !report.check(
+            missing.is_empty(),
+            format!("{table} has the required columns"),
+            format!("{table} lacks columns {}", missing.join(", ")),
+        ) {
+            have_tables = false;
+        }
+    }
+    if !have_tables {
+        return Ok(report);
+    }
+
+    spatial_ref_sys(conn, &mut report)?;
+    contents(conn, &mut report)?;
+    if table_exists(conn, "gpkg_extensions")? {
+        extensions(conn, &mut report)?;
+    }
+    Ok(report)
+}
+
+fn file_header(conn: &Connection, report: &mut Report) -> Result<()> {
+    let id = application_id(conn)?;
+    let version = user_version(conn)?;
+    if id == APPLICATION_ID {
+        report.pass(format!("application_id is 0x{id:08X} (GPKG)"));
+        report.check(
+            (10200..20000).contains(&version),
+            format!("user_version {version} is a GeoPackage 1.x version"),
+            format!("user_version {version} is not a GeoPackage 1.2+ version"),
+        );
+    } else if LEGACY_APPLICATION_IDS.contains(&id) {
+        report.warn(format!("application_id 0x{id:08X} is from GeoPackage 1.0/1.1"));
+    } else {
+        report.fail(format!("application_id 0x{id:08X} is not GPKG"));
+    }
+    Ok(())
+}
+
+fn integrity(conn: &Connection, report: &mut Report) -> Result<()> {
+    let integrity: String = conn.query_row("PRAGMA integrity_check(1)", [], |row| row.get(0))?;
+    report.check(integrity == "ok", "integrity_check ok", format!("integrity_check: {integrity}"));
+    let violations: i64 = conn.query_row("SELECT count(*) FROM pragma_foreign_key_check", [], |row| row.get(0))?;
+    report.check(
+        violations == 0,
+        "no foreign key violations",
+        format!("{violations} foreign key violations"),
+    );
+    Ok(())
+}
+
+fn spatial_ref_sys(conn: &Connection, report: &mut Report) -> Result<()> {
+    for (srs_id, name) in [(4326, "WGS 84"), (-1, "undefined cartesian"), (0, "undefined geographic")] {
+        report.check(
+            features::srs_exists(conn, srs_id)?,
+            format!("srs_id {srs_id} ({name}) is defined"),
+            format!("srs_id {srs_id} ({name}) is missing from gpkg_spatial_ref_sys"),
+        );
+    }
+    Ok(())
+}
+
+fn contents(conn: &Connection, report: &mut Report) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT table_name, data_type, srs_id, min_x, min_y, max_x, max_y FROM gpkg_contents ORDER BY table_name",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            let extent = match (row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?) {
+                (Some(min_x), Some(min_y), Some(max_x), Some(max_y)) => {
+                    Some(Envelope { min_x, max_x, min_y, max_y, z: None, m: None })
+                }
+                _ => None,
+            };
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<i32>>(2)?, extent))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for (table, data_type, srs_id, extent) in rows {
+        if !table_exists(conn, &table)? {
+            report.fail(format!("{table}: listed in gpkg_contents but does not exist"));
+            continue;
+        }
+        if !matches!(data_type.as_str(), "features" | "tiles" | "attributes") {
+            report.warn(format!("{table}: data_type {data_type} is defined by an extension"));
+        }
+        if let Some(srs_id) = srs_id
+            && !features::srs_exists(conn, srs_id)?
+        {
+            report.fail(format!("{table}: srs_id {srs_id} is not in gpkg_spatial_ref_sys"));
+        }
+        if data_type == "features" {
+            feature_table(conn, report, &table, srs_id, extent)?;
+        } else if data_type == "attributes" {
+            report.check(
+                features::primary_key(conn, &table).is_ok(),
+                format!("{table}: attributes table is consistent"),
+                format!("{table}: no INTEGER PRIMARY KEY column"),
+            );
+        }
+    }
+    Ok(())
+}
+
+fn extensions(conn: &Connection, report: &mut Report) -> Result<()> {
+    let mut stmt =
+        conn.prepare("SELECT extension_name, table_name, column_name, scope FROM gpkg_extensions ORDER BY rowid")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut ok = true;
+    for (name, table, column, scope) in rows {
+        let target = match (&table, &column) {
+            (Some(table), Some(column)) => format!(" on {table}.{column}"),
+            (Some(table), None) => format!(" on {table}"),
+            _ => String::new(),
+        };
+        if !matches!(scope.as_str(), "read-write" | "write-only") {
+            report.fail(format!("extension {name}{target}: invalid scope {scope}"));
+            ok = false;
+        } else if scope == "read-write" && !extensions::is_registered(&name) {
+            report.warn(format!(
+                "extension {name}{target} is read-write and not adopted by the spec; other readers may not support it"
+            ));
+            ok = false;
+        }
+        if let Some(table) = &table
+            && !table_exists(conn, table)?
+        {
+            report.fail(format!("extension {name}: table {table} does not exist"));
+            ok = false;
+        }
+    }
+    if ok {
+        report.pass("gpkg_extensions entries are valid");
+    }
+    Ok(())
+}
+
+fn feature_table(
+    conn: &Connection,
+    report: &mut Report,
+    table: &str,
+    contents_srs_id: Option<i32>,
+    extent: Option<Envelope>,
+) -> Result<()> {
+    let Some(column) = features::geometry_column(conn, table)? else {
+        report.fail(format!("{table}: feature table without a gpkg_geometry_columns entry"));
+        return Ok(());
+    };
+    let mut ok = true;
+    if features::primary_key(conn, table).is_err() {
+        report.fail(format!("{table}: no INTEGER PRIMARY KEY column"));
+        ok = false;
+    }
+    if !features::column_names(conn, table)?.iter().any(|c| c.eq_ignore_ascii_case(&column.column)) {
+        report.fail(format!("{table}: geometry column {} does not exist", column.column));
+        return Ok(());
+    }
+    let type_name = column.type_name.to_uppercase();
+    if EXTENSION_GEOMETRY_TYPES.contains(&type_name.as_str()) {
+        report.warn(format!("{table}: geometry type {type_name} requires the non-linear geometry extension"));
+    } else if !CORE_GEOMETRY_TYPES.contains(&type_name.as_str()) {
+        report.fail(format!("{table}: unknown geometry type {}", column.type_name));
+        ok = false;
+    }
+    if contents_srs_id != Some(column.srs_id) {
+        report.fail(format!(
+            "{table}: gpkg_geometry_columns srs_id {} differs from gpkg_contents",
+            column.srs_id
+        ));
+        ok = false;
+    }
+    if !(0..=2).contains(&column.z) || !(0..=2).contains(&column.m) {
+        report.fail(format!("{table}: z and m must be 0, 1 or 2"));
+        ok = false;
+    }
+    ok &= geometries(conn, report, table, &column, extent)?;
+    if ok {
+        report.pass(format!("{table}: feature table is consistent"));
+    }
+    Ok(())
+}
+
+/// Checks every geometry BLOB of a feature table, and that a usable spatial
+/// index holds one entry per non-empty geometry. Returns false if any failed.
+fn geometries(
+    conn: &Connection,
+    report: &mut Report,
+    table: &str,
+    column: &GeometryColumn,
+    extent: Option<Envelope>,
+) -> Result<bool> {
+    let type_name = column.type_name.to_uppercase();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT rowid, {} FROM {} WHERE {0} NOT NULL",
+        quote_identifier(&column.column),
+        quote_identifier(table)
+    ))?;
+    let mut rows = stmt.query([])?;
+    let mut errors = 0;
+    let mut outside = 0;
+    let mut indexed = 0;
+    let mut fail = |report: &mut Report, rowid: i64, msg: String| {
+        errors += 1;
+        if errors <= MAX_GEOMETRY_ERRORS {
+            report.fail(format!("{table} row {rowid}: {msg}"));
+        }
+    };
+    while let Some(row) = rows.next()? {
+        let rowid: i64 = row.get(0)?;
+        let Ok(blob) = row.get::<_, Vec<u8>>(1) else {
+            fail(report, rowid, "geometry is not a BLOB".to_string());
+            continue;
+        };
+        if gpb::header(&blob).is_ok_and(|h| !h.empty) {
+            indexed += 1;
+        }
+        let geometry = match gpb::decode(&blob) {
+            Ok(geometry) => geometry,
+            Err(e) => {
+                fail(report, rowid, e.to_string());
+                continue;
+            }
+        };
+        if geometry.srs_id != column.srs_id {
+            fail(report, rowid, format!("srs_id {} instead of {}", geometry.srs_id, column.srs_id));
+        }
+        if !column.accepts(&geometry.geometry) {
+            fail(report, rowid, format!("{} in a {type_name} column", geometry.geometry.type_name()));
+        }
+        if (column.z == 0 && geometry.dims.z) || (column.z == 1 && !geometry.dims.z) {
+            fail(report, rowid, format!("z values conflict with z = {}", column.z));
+        }
+        if (column.m == 0 && geometry.dims.m) || (column.m == 1 && !geometry.dims.m) {
+            fail(report, rowid, format!("m values conflict with m = {}", column.m));
+        }
+        if let (Some(e), Some(g)) = (extent, geometry.geometry.envelope(geometry.dims))
+            && (g.min_x < e.min_x || g.max_x > e.max_x || g.min_y < e.min_y || g.max_y > e.max_y)
+        {
+            outside += 1;
+        }
+    }
+    if errors > MAX_GEOMETRY_ERRORS {
+        report.fail(format!("{table}: {} more invalid geometries", errors - MAX_GEOMETRY_ERRORS));
+    }
+    match extent {
+        None => report.warn(format!("{table}: gpkg_contents has no extent")),
+        Some(_) if outside > 0 => {
+            report.warn(format!("{table}: gpkg_contents extent does not cover {outside} geometries"))
+        }
+        Some(_) => {}
+    }
+    let mut ok = errors == 0;
+    if let Some((index, entries)) = rtree::entries(conn, table)? {
+        ok &= report.check(
+            entries == indexed,
+            format!("{table}: {index} holds all {indexed} non-empty geometries"),
+            format!("{table}: {index} holds {entries} entries for {indexed} non-empty geometries"),
+        );
+    }
+    Ok(ok)
+}
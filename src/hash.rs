@@ -0,0 +1,116 @@
+//! Digests for deduplication and content addressing:
+//!
+//! - `md5(X)`, `sha1(X)`, `sha256(X)`, `sha3(X)`: the digest of `X` as
+//!   lowercase hex; `sha3` is SHA3-256
+//! - `hmac(algorithm, key, data)`: the HMAC of `data` with one of those
+//!   algorithms (`md5`, `sha1`, `sha256` or `sha3`), as lowercase hex
+//!
+//! Text is hashed as its UTF-8 bytes and BLOBs as they are; numbers are
+//! hashed as their text. NULL arguments give NULL.
+use crate::error::{Error, Result};
+use crate::function::{self, Args, Context, Value};
+use hmac::digest::KeyInit;
+use hmac::{Hmac, Mac};
+use libsqlite3_sys as ffi;
+use md5::Md5;
+use rusqlite::Connection;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use sha3::Sha3_256;
+use std::fmt::Write;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(2 * bytes.len()), |mut out, b| {
+        write!(out, "{b:02x}").unwrap();
+        out
+    })
+}
+
+fn digest_fn<D: Digest>(args: &Args) -> Result<Value> {
+    Ok(args.opt_blob(0).map(|data| hex(&D::digest(data))).into())
+}
+
+fn md5_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    digest_fn::<Md5>(args)
+}
+
+fn sha1_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    digest_fn::<Sha1>(args)
+}
+
+fn sha256_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    digest_fn::<Sha256>(args)
+}
+
+fn sha3_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    digest_fn::<Sha3_256>(args)
+}
+
+/// An HMAC keyed for one algorithm, cloned for every message.
+enum Keyed {
+    Md5(Hmac<Md5>),
+    Sha1(Hmac<Sha1>),
+    Sha256(Hmac<Sha256>),
+    Sha3(Box<Hmac<Sha3_256>>),
+}
+
+fn keyed<M: Mac + KeyInit>(key: &[u8]) -> Result<M> {
+    <M as KeyInit>::new_from_slice(key).map_err(|e| Error::new(format!("invalid HMAC key: {e}")))
+}
+
+fn finish<M: Mac>(mut mac: M, data: &[u8]) -> String {
+    mac.update(data);
+    hex(&mac.finalize().into_bytes())
+}
+
+impl Keyed {
+    fn new(algorithm: &str, key: &[u8]) -> Result<Keyed> {
+        Ok(match algorithm {
+            "md5" => Keyed::Md5(keyed(key)?),
+            "sha1" => Keyed::Sha1(keyed(key)?),
+            "sha256" => Keyed::Sha256(keyed(key)?),
+            "sha3" | "sha3-256" => Keyed::Sha3(Box::new(keyed(key)?)),
+            other => {
+                return Err(Error::new(format!("unknown HMAC algorithm {other}; use md5, sha1, sha256 or sha3")));
+            }
+        })
+    }
+
+    fn mac(&self, data: &[u8]) -> String {
+        match self {
+            Keyed::Md5(mac) => finish(mac.clone(), data),
+            Keyed::Sha1(mac) => finish(mac.clone(), data),
+            Keyed::Sha256(mac) => finish(mac.clone(), data),
+            Keyed::Sha3(mac) => finish((**mac).clone(), data),
+        }
+    }
+}
+
+/// The keyed state is kept on the key argument, so a constant key is
+/// prepared once per statement rather than once per row.
+fn hmac_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let (Some(algorithm), Some(key), Some(data)) = (args.opt_text(0), args.opt_blob(1), args.opt_blob(2)) else {
+        return Ok(Value::Null);
+    };
+    let algorithm = algorithm.to_lowercase();
+    let cached = ctx.aux(1, || Ok((algorithm.clone(), Keyed::new(&algorithm, key)?)))?;
+    let mac = if cached.0 == algorithm {
+        cached.1.mac(data)
+    } else {
+        Keyed::new(&algorithm, key)?.mac(data)
+    };
+    Ok(mac.into())
+}
+
+/// Registers the hash functions on `conn`.
+pub fn register(conn: &Connection) -> Result<()> {
+    let pure = ffi::SQLITE_DETERMINISTIC | ffi::SQLITE_INNOCUOUS;
+    let functions: [function::Definition; 5] = [
+        ("md5", 1, pure, md5_fn),
+        ("sha1", 1, pure, sha1_fn),
+        ("sha256", 1, pure, sha256_fn),
+        ("sha3", 1, pure, sha3_fn),
+        ("hmac", 3, pure, hmac_fn),
+    ];
+    function::register(conn, &functions)
+}
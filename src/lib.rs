@@ -1,45 +1,101 @@
+//! SQLite loadable extension with GeoPackage support.
+//!
+//! Build the `cdylib` and load it into any SQLite 3.51 or newer, e.g.
+//! `.load ./target/release/libgpkg_lib` in the `sqlite3` shell or
+//! `SELECT load_extension('./target/release/libgpkg_lib')`. SQLite calls
+//! [`sqlite3_extension_init`], which registers the SQL functions of `gpkg`,
+//! `spatial_functions`, `regexp` and `hash` and the `remotedb` module on
+//! that connection.
 use libsqlite3_sys as ffi;
-use std::ffi::CString;
-use std::os::raw::{c_char, c_int, c_void};
-
-// Callback-Funktion für eine benutzerdefinierte SQL-Funktion
-unsafe extern "C" fn my_function(
-    context: *mut ffi::sqlite3_context,
-    argc: c_int,
-    argv: *mut *mut ffi::sqlite3_value,
-) {
-    if argc != 2 {
-        let err = CString::new("Expected 2 arguments").unwrap();
-        ffi::sqlite3_result_error(context, err.as_ptr(), -1);
-        return;
-    }
+use rusqlite::Connection;
+use std::os::raw::{c_char, c_int};
+
+mod error;
+mod flatbuffers;
+mod function;
+mod geometry;
+mod gpkg;
+mod hash;
+mod json;
+mod protobuf;
+mod regexp;
+mod remotedb;
+mod spatial_functions;
+mod xml;
 
-    let arg1 = ffi::sqlite3_value_int(*argv.offset(0));
-    let arg2 = ffi::sqlite3_value_int(*argv.offset(1));
-    
-    let result = arg1 + arg2;
-    ffi::sqlite3_result_int(context, result);
+/// Quotes `name` as an SQL identifier.
+pub(crate) fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
 }
 
+/// The entry point SQLite looks up when it loads the library.
+///
+/// All SQLite calls of the extension go through the routines in `p_api`
+/// (`SQLITE_EXTENSION_INIT2`), so they reach the SQLite that loaded it.
+///
+/// # Safety
+///
+/// Must only be called by SQLite, with the arguments of an extension load.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn sqlite3_extension_init(
     db: *mut ffi::sqlite3,
     pz_err_msg: *mut *mut c_char,
     p_api: *mut ffi::sqlite3_api_routines,
 ) -> c_int {
-    let fn_name = CString::new("add_numbers").unwrap();
-    
-    let result = ffi::sqlite3_create_function_v2(
-        db,
-        fn_name.as_ptr(),
-        2,  // Anzahl der Argumente
-        ffi::SQLITE_UTF8 | ffi::SQLITE_DETERMINISTIC,
-        std::ptr::null_mut(),
-        Some(my_function),
-        None,
-        None,
-        None,
-    );
-    
-    result
-}
\ No newline at end of file
+    unsafe { Connection::extension_init2(db, pz_err_msg, p_api, init) }
+}
+
+fn init(conn: Connection) -> rusqlite::Result<bool> {
+    remotedb::register(&conn)
+        .and_then(|()| gpkg::register(&conn))
+        .and_then(|()| spatial_functions::register(&conn))
+        .and_then(|()| regexp::register(&conn))
+        .and_then(|()| hash::register(&conn))
+        .map_err(|e| rusqlite::Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_ERROR), Some(e.to_string())))?;
+    // Registered on this connection only; other connections load it again.
+    Ok(false)
+}
+
+/// Connections with the extension loaded, for the tests of the SQL functions.
+///
+/// The extension reaches SQLite only through the routines it is loaded with,
+/// so the tests link the system SQLite and register the entry point as an
+/// auto extension, which SQLite calls like a `.load` on every new connection.
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::ffi::CString;
+    use std::path::PathBuf;
+    use std::sync::Once;
+
+    type EntryPoint =
+        unsafe extern "C" fn(*mut ffi::sqlite3, *mut *mut c_char, *mut ffi::sqlite3_api_routines) -> c_int;
+
+    #[link(name = "sqlite3")]
+    unsafe extern "C" {
+        fn sqlite3_auto_extension(entry: unsafe extern "C" fn()) -> c_int;
+        fn sqlite3_open(filename: *const c_char, db: *mut *mut ffi::sqlite3) -> c_int;
+    }
+
+    /// Opens `path` (or `:memory:`) with the extension loaded.
+    pub(crate) fn open(path: &str) -> Connection {
+        static REGISTER: Once = Once::new();
+        REGISTER.call_once(|| {
+            let entry: EntryPoint = sqlite3_extension_init;
+            let entry = unsafe { std::mem::transmute::<EntryPoint, unsafe extern "C" fn()>(entry) };
+            let rc = unsafe { sqlite3_auto_extension(entry) };
+            assert_eq!(rc, ffi::SQLITE_OK);
+        });
+        let path = CString::new(path).unwrap();
+        let mut db = std::ptr::null_mut();
+        assert_eq!(unsafe { sqlite3_open(path.as_ptr(), &mut db) }, ffi::SQLITE_OK);
+        unsafe { Connection::from_handle_owned(db) }.unwrap()
+    }
+
+    /// A fresh path in the temporary directory for the test file `name`.
+    pub(crate) fn temp_path(name: &str) -> String {
+        let path: PathBuf = std::env::temp_dir().join(format!("gpkg-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+}
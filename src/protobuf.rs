@@ -0,0 +1,117 @@
+//! Minimal Protocol Buffers writer for the export functions.
+//!
+//! Fields are appended in the order they are written; nested messages are
+//! encoded separately and added as length-delimited fields.
+
+const VARINT: u64 = 0;
+const FIXED64: u64 = 1;
+const LENGTH_DELIMITED: u64 = 2;
+
+#[derive(Default)]
+pub struct Message {
+    buf: Vec<u8>,
+}
+
+impl Message {
+    pub fn new() -> Message {
+        Message::default()
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u64) {
+        self.varint(u64::from(field) << 3 | wire_type);
+    }
+
+    pub fn uint(&mut self, field: u32, value: u64) {
+        self.key(field, VARINT);
+        self.varint(value);
+    }
+
+    /// A `sint64`, zigzag encoded.
+    pub fn sint(&mut self, field: u32, value: i64) {
+        self.uint(field, zigzag(value));
+    }
+
+    pub fn double(&mut self, field: u32, value: f64) {
+        self.key(field, FIXED64);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, field: u32, value: &[u8]) {
+        self.key(field, LENGTH_DELIMITED);
+        self.varint(value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    pub fn string(&mut self, field: u32, value: &str) {
+        self.bytes(field, value.as_bytes());
+    }
+
+    pub fn message(&mut self, field: u32, value: &Message) {
+        self.bytes(field, &value.buf);
+    }
+
+    /// A packed repeated `uint32`.
+    pub fn packed(&mut self, field: u32, values: &[u32]) {
+        let mut packed = Message::new();
+        values.iter().for_each(|v| packed.varint(u64::from(*v)));
+        self.bytes(field, &packed.buf);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+pub fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(write: impl FnOnce(&mut Message)) -> Vec<u8> {
+        let mut message = Message::new();
+        write(&mut message);
+        message.finish()
+    }
+
+    // Expected bytes from the Protocol Buffers encoding guide.
+    #[test]
+    fn scalars() {
+        assert_eq!(encoded(|m| m.uint(1, 150)), [0x08, 0x96, 0x01]);
+        assert_eq!(encoded(|m| m.uint(1, 0)), [0x08, 0x00]);
+        assert_eq!(encoded(|m| m.uint(16, u64::MAX))[..2], [0x80, 0x01]);
+        assert_eq!(encoded(|m| m.uint(16, u64::MAX)).len(), 2 + 10);
+        assert_eq!(encoded(|m| m.sint(1, -1)), [0x08, 0x01]);
+        assert_eq!(encoded(|m| m.sint(1, -2)), [0x08, 0x03]);
+        assert_eq!(encoded(|m| m.double(1, 1.0)), [0x09, 0, 0, 0, 0, 0, 0, 0xf0, 0x3f]);
+    }
+
+    #[test]
+    fn length_delimited() {
+        assert_eq!(encoded(|m| m.string(2, "testing")), b"\x12\x07testing");
+        assert_eq!(encoded(|m| m.bytes(2, &[])), [0x12, 0x00]);
+        assert_eq!(encoded(|m| m.packed(4, &[3, 270, 86942])), [0x22, 0x06, 0x03, 0x8e, 0x02, 0x9e, 0xa7, 0x05]);
+        let mut inner = Message::new();
+        inner.uint(1, 150);
+        assert_eq!(encoded(|m| m.message(3, &inner)), [0x1a, 0x03, 0x08, 0x96, 0x01]);
+        let long = "x".repeat(300);
+        assert_eq!(encoded(|m| m.string(1, &long))[..3], [0x0a, 0xac, 0x02]);
+    }
+
+    #[test]
+    fn zigzag_values() {
+        assert_eq!([0, -1, 1, -2, 2].map(zigzag), [0, 1, 2, 3, 4]);
+        assert_eq!(zigzag(i64::MAX), u64::MAX - 1);
+        assert_eq!(zigzag(i64::MIN), u64::MAX);
+    }
+}
@@ -0,0 +1,53 @@
+//! The `regexp(pattern, text)` function behind SQLite's `REGEXP` operator,
+//! so `WHERE name REGEXP '^A[0-9]+$'` works on any connection that loads
+//! the extension.
+//!
+//! Patterns use the syntax of the `regex` crate and match anywhere in the
+//! text unless anchored. A constant pattern is compiled once per statement
+//! and kept as auxiliary data of the call, since a query calls the function
+//! with the same pattern for every row. Patterns taken from a column, as in
+//! `name REGEXP other_column`, go through a small LRU cache of compiled
+//! patterns instead.
+use crate::error::{Error, Result};
+use crate::function::{self, Args, Context, Value};
+use libsqlite3_sys as ffi;
+use regex::Regex;
+use rusqlite::Connection;
+use std::sync::{Mutex, PoisonError};
+
+const CACHE_SIZE: usize = 32;
+
+/// Compiled patterns, least recently used first.
+static CACHE: Mutex<Vec<(String, Regex)>> = Mutex::new(Vec::new());
+
+fn compile(pattern: &str) -> Result<Regex> {
+    let mut cache = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(i) = cache.iter().position(|(p, _)| p == pattern) {
+        let entry = cache.remove(i);
+        let regex = entry.1.clone();
+        cache.push(entry);
+        return Ok(regex);
+    }
+    let regex = Regex::new(pattern).map_err(|e| Error::new(format!("invalid regular expression: {e}")))?;
+    if cache.len() == CACHE_SIZE {
+        cache.remove(0);
+    }
+    cache.push((pattern.to_string(), regex.clone()));
+    Ok(regex)
+}
+
+/// `text REGEXP pattern` is `regexp(pattern, text)`; NULL if either is NULL.
+fn regexp_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let (Some(pattern), Some(text)) = (args.opt_text(0), args.opt_text(1)) else {
+        return Ok(Value::Null);
+    };
+    let regex = ctx.aux(0, || compile(&pattern))?;
+    Ok(regex.is_match(&text).into())
+}
+
+/// Registers `regexp` on `conn`.
+pub fn register(conn: &Connection) -> Result<()> {
+    let pure = ffi::SQLITE_DETERMINISTIC | ffi::SQLITE_INNOCUOUS;
+    let functions: [function::Definition; 1] = [("regexp", 2, pure, regexp_fn)];
+    function::register(conn, &functions)
+}
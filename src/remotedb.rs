@@ -0,0 +1,430 @@
+//! `remotedb` virtual table module.
+//!
+//! Exposes a table of another SQLite file without ATTACH:
+//!
+//! ```sql
+//! CREATE VIRTUAL TABLE roads USING remotedb('other.gpkg', 'roads');
+//! SELECT * FROM roads WHERE rowid = 42;
+//! ```
+//!
+//! The remote file is opened read-only (URI filenames such as
+//! `file:other.gpkg?immutable=1` are accepted), the virtual table has no
+//! `xUpdate` so writes are rejected, and `rowid = ?` constraints are pushed
+//! down to the remote connection.
+use crate::error::{self, Error};
+use crate::quote_identifier;
+use libsqlite3_sys as ffi;
+use rusqlite::Connection;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::slice;
+
+const MODULE_NAME: &CStr = c"remotedb";
+
+/// Scan strategies chosen by `xBestIndex` and handed to `xFilter`.
+const SCAN_FULL: c_int = 0;
+const SCAN_ROWID: c_int = 1;
+
+#[repr(C)]
+struct RemoteTable {
+    base: ffi::sqlite3_vtab,
+    remote: *mut ffi::sqlite3,
+    layer: String,
+    /// The name that reaches the remote rowid: `rowid`, `_rowid_` or `oid`,
+    /// whichever the layer does not use for a column.
+    rowid: &'static str,
+    /// The quoted declared columns. Scans list them instead of using `*`,
+    /// which would also return the generated columns the declaration omits.
+    columns: String,
+}
+
+#[repr(C)]
+struct RemoteCursor {
+    base: ffi::sqlite3_vtab_cursor,
+    stmt: *mut ffi::sqlite3_stmt,
+    eof: bool,
+}
+
+static REMOTEDB_MODULE: ffi::sqlite3_module = ffi::sqlite3_module {
+    iVersion: 1,
+    xCreate: Some(remote_connect),
+    xConnect: Some(remote_connect),
+    xBestIndex: Some(remote_best_index),
+    xDisconnect: Some(remote_disconnect),
+    xDestroy: Some(remote_disconnect),
+    xOpen: Some(remote_open),
+    xClose: Some(remote_close),
+    xFilter: Some(remote_filter),
+    xNext: Some(remote_next),
+    xEof: Some(remote_eof),
+    xColumn: Some(remote_column),
+    xRowid: Some(remote_rowid),
+    ..unsafe { std::mem::zeroed() }
+};
+
+/// Registers the `remotedb` module on `conn`.
+pub fn register(conn: &Connection) -> error::Result<()> {
+    let rc = unsafe {
+        ffi::sqlite3_create_module_v2(
+            conn.handle(),
+            MODULE_NAME.as_ptr(),
+            &REMOTEDB_MODULE,
+            ptr::null_mut(),
+            None,
+        )
+    };
+    if rc != ffi::SQLITE_OK {
+        return Err(Error::new(format!("cannot register the remotedb module: {}", ffi::Error::new(rc))));
+    }
+    Ok(())
+}
+
+/// Strips the quotes SQLite leaves around module arguments.
+fn dequote(arg: &str) -> String {
+    let arg = arg.trim();
+    for quote in ['\'', '"', '`'] {
+        if arg.len() >= 2 && arg.starts_with(quote) && arg.ends_with(quote) {
+            let doubled: String = [quote, quote].iter().collect();
+            return arg[1..arg.len() - 1].replace(&doubled, &quote.to_string());
+        }
+    }
+    if arg.len() >= 2 && arg.starts_with('[') && arg.ends_with(']') {
+        return arg[1..arg.len() - 1].to_string();
+    }
+    arg.to_string()
+}
+
+/// Copies `msg` into memory owned by SQLite, as required for error messages.
+unsafe fn sqlite_string(msg: &str) -> *mut c_char {
+    let msg = CString::new(msg.replace('\0', "")).unwrap();
+    let bytes = msg.as_bytes_with_nul();
+    unsafe {
+        let copy = ffi::sqlite3_malloc64(bytes.len() as u64) as *mut c_char;
+        if !copy.is_null() {
+            ptr::copy_nonoverlapping(bytes.as_ptr() as *const c_char, copy, bytes.len());
+        }
+        copy
+    }
+}
+
+unsafe fn set_vtab_error(vtab: *mut ffi::sqlite3_vtab, msg: &str) {
+    unsafe {
+        ffi::sqlite3_free((*vtab).zErrMsg as *mut c_void);
+        (*vtab).zErrMsg = sqlite_string(msg);
+    }
+}
+
+unsafe fn errmsg(db: *mut ffi::sqlite3) -> String {
+    unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(db)).to_string_lossy().into_owned() }
+}
+
+unsafe fn prepare(db: *mut ffi::sqlite3, sql: &str) -> Result<*mut ffi::sqlite3_stmt, String> {
+    let sql = CString::new(sql).map_err(|e| e.to_string())?;
+    let mut stmt = ptr::null_mut();
+    let rc = unsafe { ffi::sqlite3_prepare_v2(db, sql.as_ptr(), -1, &mut stmt, ptr::null_mut()) };
+    if rc != ffi::SQLITE_OK {
+        return Err(unsafe { errmsg(db) });
+    }
+    Ok(stmt)
+}
+
+unsafe fn open_remote(file: &str) -> Result<*mut ffi::sqlite3, String> {
+    let path = CString::new(file).map_err(|e| e.to_string())?;
+    let mut remote = ptr::null_mut();
+    let rc = unsafe {
+        ffi::sqlite3_open_v2(
+            path.as_ptr(),
+            &mut remote,
+            ffi::SQLITE_OPEN_READONLY | ffi::SQLITE_OPEN_URI,
+            ptr::null(),
+        )
+    };
+    if rc != ffi::SQLITE_OK {
+        let msg = if remote.is_null() {
+            format!("cannot open {file}")
+        } else {
+            format!("cannot open {file}: {}", unsafe { errmsg(remote) })
+        };
+        unsafe { ffi::sqlite3_close(remote) };
+        return Err(msg);
+    }
+    Ok(remote)
+}
+
+/// Builds the `CREATE TABLE` statement declaring the remote layer's columns,
+/// and returns it with the name that selects the remote rowid and the
+/// quoted column list.
+unsafe fn declare_columns(remote: *mut ffi::sqlite3, layer: &str) -> Result<(String, &'static str, String), String> {
+    let stmt = unsafe { prepare(remote, "SELECT name, type FROM pragma_table_info(?1)")? };
+    let layer_c = CString::new(layer).map_err(|e| e.to_string())?;
+    let mut names = Vec::new();
+    let mut columns = Vec::new();
+    unsafe {
+        ffi::sqlite3_bind_text(stmt, 1, layer_c.as_ptr(), -1, ffi::SQLITE_TRANSIENT());
+        while ffi::sqlite3_step(stmt) == ffi::SQLITE_ROW {
+            let name = CStr::from_ptr(ffi::sqlite3_column_text(stmt, 0) as *const c_char).to_string_lossy();
+            let decl = ffi::sqlite3_column_text(stmt, 1);
+            let decl = if decl.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(decl as *const c_char).to_string_lossy().into_owned()
+            };
+            columns.push(format!("{} {}", quote_identifier(&name), decl).trim_end().to_string());
+            names.push(name.into_owned());
+        }
+        ffi::sqlite3_finalize(stmt);
+    }
+    if columns.is_empty() {
+        return Err(format!("no such table: {layer}"));
+    }
+
+    // A column called rowid hides the real rowid behind that name.
+    let rowid = ["rowid", "_rowid_", "oid"]
+        .into_iter()
+        .find(|alias| !names.iter().any(|n| n.eq_ignore_ascii_case(alias)))
+        .ok_or_else(|| format!("{layer} has columns named rowid, _rowid_ and oid, so its rowid cannot be read"))?;
+    // The scan relies on rowid, so WITHOUT ROWID tables are refused up front.
+    let probe = unsafe { prepare(remote, &format!("SELECT {rowid} FROM {} LIMIT 0", quote_identifier(layer)))? };
+    unsafe { ffi::sqlite3_finalize(probe) };
+
+    let quoted: Vec<String> = names.iter().map(|name| quote_identifier(name)).collect();
+    Ok((format!("CREATE TABLE x({})", columns.join(", ")), rowid, quoted.join(", ")))
+}
+
+unsafe extern "C" fn remote_connect(
+    db: *mut ffi::sqlite3,
+    _aux: *mut c_void,
+    argc: c_int,
+    argv: *const *const c_char,
+    pp_vtab: *mut *mut ffi::sqlite3_vtab,
+    pz_err: *mut *mut c_char,
+) -> c_int {
+    // argv[0..3] are the module, database and table names; the rest are ours.
+    let args: Vec<String> = (0..argc as usize)
+        .map(|i| unsafe { CStr::from_ptr(*argv.add(i)).to_string_lossy().into_owned() })
+        .collect();
+    if args.len() != 5 {
+        unsafe { *pz_err = sqlite_string("remotedb: expected arguments (file, table)") };
+        return ffi::SQLITE_ERROR;
+    }
+    let file = dequote(&args[3]);
+    let layer = dequote(&args[4]);
+
+    let remote = match unsafe { open_remote(&file) } {
+        Ok(remote) => remote,
+        Err(msg) => {
+            unsafe { *pz_err = sqlite_string(&format!("remotedb: {msg}")) };
+            return ffi::SQLITE_ERROR;
+        }
+    };
+    let (schema, rowid, columns) = match unsafe { declare_columns(remote, &layer) } {
+        Ok(declared) => declared,
+        Err(msg) => {
+            unsafe {
+                ffi::sqlite3_close(remote);
+                *pz_err = sqlite_string(&format!("remotedb: {msg}"));
+            }
+            return ffi::SQLITE_ERROR;
+        }
+    };
+
+    let schema = CString::new(schema).unwrap();
+    let rc = unsafe { ffi::sqlite3_declare_vtab(db, schema.as_ptr()) };
+    if rc != ffi::SQLITE_OK {
+        unsafe { ffi::sqlite3_close(remote) };
+        return rc;
+    }
+    // The module opens arbitrary files, so keep it out of triggers and views.
+    unsafe { ffi::sqlite3_vtab_config(db, ffi::SQLITE_VTAB_DIRECTONLY) };
+
+    let table = Box::new(RemoteTable {
+        base: unsafe { std::mem::zeroed() },
+        remote,
+        layer,
+        rowid,
+        columns,
+    });
+    unsafe { *pp_vtab = Box::into_raw(table) as *mut ffi::sqlite3_vtab };
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn remote_best_index(
+    _vtab: *mut ffi::sqlite3_vtab,
+    info: *mut ffi::sqlite3_index_info,
+) -> c_int {
+    let info = unsafe { &mut *info };
+    let count = info.nConstraint as usize;
+    let (constraints, usage) = if count == 0 {
+        (&[][..], &mut [][..])
+    } else {
+        unsafe {
+            (
+                slice::from_raw_parts(info.aConstraint, count),
+                slice::from_raw_parts_mut(info.aConstraintUsage, count),
+            )
+        }
+    };
+
+    for (constraint, usage) in constraints.iter().zip(usage.iter_mut()) {
+        if constraint.usable != 0
+            && constraint.iColumn == -1
+            && constraint.op as c_int == ffi::SQLITE_INDEX_CONSTRAINT_EQ
+        {
+            usage.argvIndex = 1;
+            usage.omit = 1;
+            info.idxNum = SCAN_ROWID;
+            info.idxFlags = ffi::SQLITE_INDEX_SCAN_UNIQUE;
+            info.estimatedCost = 1.0;
+            info.estimatedRows = 1;
+            return ffi::SQLITE_OK;
+        }
+    }
+
+    info.idxNum = SCAN_FULL;
+    info.estimatedCost = 1_000_000.0;
+    info.estimatedRows = 1_000_000;
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn remote_disconnect(vtab: *mut ffi::sqlite3_vtab) -> c_int {
+    let table = unsafe { Box::from_raw(vtab as *mut RemoteTable) };
+    unsafe {
+        ffi::sqlite3_free(table.base.zErrMsg as *mut c_void);
+        ffi::sqlite3_close(table.remote);
+    }
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn remote_open(
+    _vtab: *mut ffi::sqlite3_vtab,
+    pp_cursor: *mut *mut ffi::sqlite3_vtab_cursor,
+) -> c_int {
+    let cursor = Box::new(RemoteCursor {
+        base: unsafe { std::mem::zeroed() },
+        stmt: ptr::null_mut(),
+        eof: true,
+    });
+    unsafe { *pp_cursor = Box::into_raw(cursor) as *mut ffi::sqlite3_vtab_cursor };
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn remote_close(cursor: *mut ffi::sqlite3_vtab_cursor) -> c_int {
+    let cursor = unsafe { Box::from_raw(cursor as *mut RemoteCursor) };
+    unsafe { ffi::sqlite3_finalize(cursor.stmt) };
+    ffi::SQLITE_OK
+}
+
+/// Steps the remote statement, reporting failures on the virtual table.
+unsafe fn step(cursor: &mut RemoteCursor) -> c_int {
+    match unsafe { ffi::sqlite3_step(cursor.stmt) } {
+        ffi::SQLITE_ROW => {
+            cursor.eof = false;
+            ffi::SQLITE_OK
+        }
+        ffi::SQLITE_DONE => {
+            cursor.eof = true;
+            ffi::SQLITE_OK
+        }
+        rc => {
+            cursor.eof = true;
+            unsafe {
+                let table = cursor.base.pVtab as *mut RemoteTable;
+                set_vtab_error(cursor.base.pVtab, &format!("remotedb: {}", errmsg((*table).remote)));
+            }
+            rc
+        }
+    }
+}
+
+unsafe extern "C" fn remote_filter(
+    cursor: *mut ffi::sqlite3_vtab_cursor,
+    idx_num: c_int,
+    _idx_str: *const c_char,
+    argc: c_int,
+    argv: *mut *mut ffi::sqlite3_value,
+) -> c_int {
+    let cursor = unsafe { &mut *(cursor as *mut RemoteCursor) };
+    let table = unsafe { &*(cursor.base.pVtab as *mut RemoteTable) };
+
+    unsafe { ffi::sqlite3_finalize(cursor.stmt) };
+    cursor.stmt = ptr::null_mut();
+    cursor.eof = true;
+
+    let rowid = table.rowid;
+    let mut sql = format!("SELECT {rowid}, {} FROM {}", table.columns, quote_identifier(&table.layer));
+    if idx_num == SCAN_ROWID {
+        sql.push_str(&format!(" WHERE {rowid} = ?1"));
+    }
+    cursor.stmt = match unsafe { prepare(table.remote, &sql) } {
+        Ok(stmt) => stmt,
+        Err(msg) => {
+            unsafe { set_vtab_error(cursor.base.pVtab, &format!("remotedb: {msg}")) };
+            return ffi::SQLITE_ERROR;
+        }
+    };
+    if idx_num == SCAN_ROWID && argc > 0 {
+        unsafe { ffi::sqlite3_bind_value(cursor.stmt, 1, *argv) };
+    }
+    unsafe { step(cursor) }
+}
+
+unsafe extern "C" fn remote_next(cursor: *mut ffi::sqlite3_vtab_cursor) -> c_int {
+    unsafe { step(&mut *(cursor as *mut RemoteCursor)) }
+}
+
+unsafe extern "C" fn remote_eof(cursor: *mut ffi::sqlite3_vtab_cursor) -> c_int {
+    unsafe { (*(cursor as *mut RemoteCursor)).eof as c_int }
+}
+
+unsafe extern "C" fn remote_column(
+    cursor: *mut ffi::sqlite3_vtab_cursor,
+    context: *mut ffi::sqlite3_context,
+    column: c_int,
+) -> c_int {
+    unsafe {
+        let cursor = &*(cursor as *mut RemoteCursor);
+        // Column 0 of the remote statement is the rowid.
+        ffi::sqlite3_result_value(context, ffi::sqlite3_column_value(cursor.stmt, column + 1));
+    }
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn remote_rowid(
+    cursor: *mut ffi::sqlite3_vtab_cursor,
+    p_rowid: *mut ffi::sqlite3_int64,
+) -> c_int {
+    unsafe {
+        let cursor = &*(cursor as *mut RemoteCursor);
+        *p_rowid = ffi::sqlite3_column_int64(cursor.stmt, 0);
+    }
+    ffi::SQLITE_OK
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{open, temp_path};
+
+    #[test]
+    fn columns_and_rowid() {
+        let path = temp_path("remote.db");
+        open(&path)
+            .execute_batch(
+                "CREATE TABLE t (a, b AS (a * 2), c, rowid TEXT);
+                 INSERT INTO t (a, c, rowid) VALUES (1, 'x', 'first'), (2, 'y', 'second');
+                 DELETE FROM t WHERE a = 1;",
+            )
+            .unwrap();
+        let conn = open(":memory:");
+        conn.execute_batch(&format!("CREATE VIRTUAL TABLE r USING remotedb('{path}', 't')")).unwrap();
+
+        // The generated column is not declared and must not shift the others;
+        // the user's rowid column stays apart from the real rowid.
+        let row: (i64, String, String) =
+            conn.query_row("SELECT * FROM r", [], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?))).unwrap();
+        assert_eq!(row, (2, "y".to_string(), "second".to_string()));
+        let found: Option<String> = conn.query_row("SELECT c FROM r WHERE _rowid_ = 2", [], |r| r.get(0)).ok();
+        assert_eq!(found.as_deref(), Some("y"));
+        assert!(conn.query_row("SELECT c FROM r WHERE _rowid_ = 1", [], |r| r.get::<_, String>(0)).is_err());
+    }
+}
@@ -0,0 +1,431 @@
+//! Geometry constructors, output formats and planar measurements:
+//!
+//! - `ST_GeomFromText(wkt, ?srs_id?)`: a GeoPackage geometry BLOB (srs_id 0
+//!   unless given)
+//! - `ST_GeomFromWKB(wkb, ?srs_id?)`: a GeoPackage geometry BLOB from ISO
+//!   WKB or PostGIS EWKB, as a BLOB or hex text; an EWKB SRID is used unless
+//!   `srs_id` is given, otherwise 0
+//! - `ST_AsText(geom)` / `ST_AsGeoJSON(geom)`
+//! - `ST_AsBinary(geom)` / `ST_AsEWKB(geom)`: the geometry as ISO WKB without
+//!   the GeoPackage header, or as EWKB carrying the srs_id
+//! - `ST_Area(geom)` / `ST_Length(geom)`: in units of the geometry's SRS
+//! - `ST_Centroid(geom)`: a point, NULL for empty geometries
+//! - `ST_Buffer(geom, distance)`: a (multi)polygon
+//! - `ST_Transform(geom, srs_id)`: the geometry reprojected to another SRS
+//!   of `gpkg_spatial_ref_sys`
+//! - `ST_Simplify(geom, tolerance)`: Douglas-Peucker simplification of lines
+//!   and rings, keeping the z and m of the vertices that remain
+//! - `ST_SnapToGrid(geom, size)`: x and y rounded to multiples of `size`,
+//!   with repeated vertices removed
+//! - `ST_GeoHash(geom, ?precision?)`: the geohash of a longitude/latitude
+//!   geometry; without a precision, the longest one whose cell holds the
+//!   whole envelope, or NULL if no cell does. Coordinates outside ±180/±90
+//!   are an error
+//! - `ST_PointFromGeoHash(hash)` / `ST_GeomFromGeoHash(hash)`: the centre
+//!   point or the polygon of a geohash cell, in srs 4326
+//! - `ST_TileX(lon, zoom)` / `ST_TileY(lat, zoom)`: the column and row of
+//!   the XYZ (web Mercator) tile holding a longitude or latitude
+//! - `ST_TileEnvelope(zoom, x, y, ?srs_id?)`: the polygon of an XYZ tile in
+//!   srs 3857 (the default) or 4326
+//!
+//! Measurements run on the `geo` crate and ignore z and m. Lines that
+//! simplification or snapping collapses below two points, and rings below
+//! four, are dropped.
+use crate::error::{Error, Result};
+use crate::function::{self, Args, Context, Value};
+use crate::geometry::{Coord, Dims, Geometry, geohash, geojson, gpb, wkb, wkt};
+use crate::gpkg::{MAX_LATITUDE, Transformer, WEB_MERCATOR_EXTENT};
+use geo::{Area, Buffer, Centroid, Euclidean, Length, Simplify};
+use libsqlite3_sys as ffi;
+use rusqlite::Connection;
+use std::f64::consts::PI;
+
+fn to_geo_coords(coords: &[Coord]) -> geo::LineString<f64> {
+    geo::LineString::new(coords.iter().map(|c| geo::Coord { x: c.x, y: c.y }).collect())
+}
+
+fn to_geo_polygon(rings: &[Vec<Coord>]) -> geo::Polygon<f64> {
+    let mut rings = rings.iter().map(|ring| to_geo_coords(ring));
+    let exterior = rings.next().unwrap_or_else(|| geo::LineString::new(Vec::new()));
+    geo::Polygon::new(exterior, rings.collect())
+}
+
+fn to_geo(geometry: &Geometry) -> geo::Geometry<f64> {
+    match geometry {
+        Geometry::Point(Some(c)) => geo::Point::new(c.x, c.y).into(),
+        Geometry::Point(None) => geo::MultiPoint::new(Vec::new()).into(),
+        Geometry::LineString(line) => to_geo_coords(line).into(),
+        Geometry::Polygon(rings) => to_geo_polygon(rings).into(),
+        Geometry::MultiPoint(points) => {
+            geo::MultiPoint::new(points.iter().map(|c| geo::Point::new(c.x, c.y)).collect()).into()
+        }
+        Geometry::MultiLineString(lines) => {
+            geo::MultiLineString::new(lines.iter().map(|line| to_geo_coords(line)).collect()).into()
+        }
+        Geometry::MultiPolygon(polygons) => {
+            geo::MultiPolygon::new(polygons.iter().map(|rings| to_geo_polygon(rings)).collect()).into()
+        }
+        Geometry::GeometryCollection(geometries) => {
+            geo::Geometry::GeometryCollection(geo::GeometryCollection(geometries.iter().map(to_geo).collect()))
+        }
+    }
+}
+
+fn from_geo_coords(line: &geo::LineString<f64>) -> Vec<Coord> {
+    line.coords().map(|c| Coord { x: c.x, y: c.y, ..Coord::default() }).collect()
+}
+
+fn from_geo_polygon(polygon: &geo::Polygon<f64>) -> Vec<Vec<Coord>> {
+    let mut rings = vec![from_geo_coords(polygon.exterior())];
+    rings.extend(polygon.interiors().iter().map(from_geo_coords));
+    rings
+}
+
+/// Rebuilds `geometry` with every line and ring passed through `f`,
+/// dropping the parts that collapse.
+fn map_lines(geometry: &Geometry, f: &impl Fn(&[Coord]) -> Vec<Coord>) -> Geometry {
+    let line = |line: &Vec<Coord>| Some(f(line)).filter(|line| line.len() >= 2);
+    let polygon = |rings: &Vec<Vec<Coord>>| {
+        let mut out = Vec::with_capacity(rings.len());
+        for (i, ring) in rings.iter().enumerate() {
+            match Some(f(ring)).filter(|ring| ring.len() >= 4) {
+                Some(ring) => out.push(ring),
+                // Without its exterior ring the polygon is gone.
+                None if i == 0 => return Vec::new(),
+                None => {}
+            }
+        }
+        out
+    };
+    match geometry {
+        Geometry::Point(_) | Geometry::MultiPoint(_) => geometry.clone(),
+        Geometry::LineString(coords) => Geometry::LineString(line(coords).unwrap_or_default()),
+        Geometry::Polygon(rings) => Geometry::Polygon(polygon(rings)),
+        Geometry::MultiLineString(lines) => Geometry::MultiLineString(lines.iter().filter_map(line).collect()),
+        Geometry::MultiPolygon(polygons) => {
+            Geometry::MultiPolygon(polygons.iter().map(polygon).filter(|p| !p.is_empty()).collect())
+        }
+        Geometry::GeometryCollection(geometries) => {
+            Geometry::GeometryCollection(geometries.iter().map(|g| map_lines(g, f)).collect())
+        }
+    }
+}
+
+/// Douglas-Peucker simplification with `tolerance` in units of the SRS.
+/// The simplified lines are subsets of the original vertices, so those keep
+/// their z and m.
+pub fn simplify(geometry: &Geometry, tolerance: f64) -> Geometry {
+    map_lines(geometry, &|coords| {
+        let simplified = to_geo_coords(coords).simplify(tolerance);
+        let mut original = coords.iter();
+        simplified.coords().filter_map(|c| original.find(|o| o.x == c.x && o.y == c.y).copied()).collect()
+    })
+}
+
+/// Rounds x and y to multiples of `size` and removes consecutive repeated
+/// vertices. A `size` of 0 leaves the geometry unchanged.
+pub fn snap_to_grid(geometry: &Geometry, size: f64) -> Geometry {
+    if size == 0.0 {
+        return geometry.clone();
+    }
+    let mut snapped = geometry.clone();
+    snapped.for_each_coord_mut(&mut |c| {
+        c.x = (c.x / size).round() * size;
+        c.y = (c.y / size).round() * size;
+    });
+    map_lines(&snapped, &|coords| {
+        let mut out: Vec<Coord> = Vec::with_capacity(coords.len());
+        for c in coords {
+            if out.last().is_none_or(|last| last.x != c.x || last.y != c.y) {
+                out.push(*c);
+            }
+        }
+        out
+    })
+}
+
+/// The geometry in argument `i` with its srs_id, or `None` for NULL.
+fn geometry_arg(args: &Args, i: usize) -> Result<Option<(Geometry, Dims, i32)>> {
+    match args.opt_blob(i) {
+        Some(blob) => {
+            let decoded = gpb::decode(blob)?;
+            Ok(Some((decoded.geometry, decoded.dims, decoded.srs_id)))
+        }
+        None => Ok(None),
+    }
+}
+
+fn geom_from_text_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    let Some(text) = args.opt_text(0) else {
+        return Ok(Value::Null);
+    };
+    let srs_id = args.opt_int(1).unwrap_or(0);
+    let srs_id = i32::try_from(srs_id).map_err(|_| Error::new(format!("invalid srs_id {srs_id}")))?;
+    let (geometry, dims) = wkt::read(&text)?;
+    Ok(gpb::encode(&geometry, dims, srs_id).into())
+}
+
+/// `bytes` decoded if they are hex text, as in PostGIS dumps (optionally
+/// with the `\\x` prefix of bytea output). WKB itself starts with a 0 or 1
+/// byte, never an ASCII character.
+fn unhex(bytes: &[u8]) -> Result<Vec<u8>> {
+    let text = bytes.strip_prefix(b"\\x").unwrap_or(bytes).trim_ascii();
+    if text.first().is_none_or(|b| !b.is_ascii_hexdigit()) {
+        return Ok(bytes.to_vec());
+    }
+    if !text.len().is_multiple_of(2) {
+        return Err(Error::new("invalid hex WKB: odd number of digits"));
+    }
+    text.chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| Error::new("invalid hex WKB"))
+        })
+        .collect()
+}
+
+fn geom_from_wkb_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    let Some(bytes) = args.opt_blob(0) else {
+        return Ok(Value::Null);
+    };
+    let (geometry, dims, srid) = wkb::read_ewkb(&unhex(bytes)?)?;
+    let srs_id = args.opt_int(1).unwrap_or(srid.map_or(0, i64::from));
+    let srs_id = i32::try_from(srs_id).map_err(|_| Error::new(format!("invalid srs_id {srs_id}")))?;
+    Ok(gpb::encode(&geometry, dims, srs_id).into())
+}
+
+fn as_binary_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    Ok(geometry_arg(args, 0)?.map(|(geometry, dims, _)| wkb::write(&geometry, dims)).into())
+}
+
+fn as_ewkb_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    Ok(geometry_arg(args, 0)?.map(|(geometry, dims, srs_id)| wkb::write_ewkb(&geometry, dims, srs_id)).into())
+}
+
+fn as_text_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    Ok(geometry_arg(args, 0)?.map(|(geometry, dims, _)| wkt::write(&geometry, dims)).into())
+}
+
+fn as_geojson_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    Ok(geometry_arg(args, 0)?.map(|(geometry, dims, _)| geojson::write(&geometry, dims)).into())
+}
+
+fn area_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    Ok(geometry_arg(args, 0)?.map(|(geometry, _, _)| to_geo(&geometry).unsigned_area()).into())
+}
+
+/// Length of the linear parts of `geometry`; areas and points count as 0.
+fn length(geometry: &Geometry) -> f64 {
+    match geometry {
+        Geometry::LineString(line) => Euclidean.length(&to_geo_coords(line)),
+        Geometry::MultiLineString(lines) => lines.iter().map(|line| Euclidean.length(&to_geo_coords(line))).sum(),
+        Geometry::GeometryCollection(geometries) => geometries.iter().map(length).sum(),
+        _ => 0.0,
+    }
+}
+
+fn length_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    Ok(geometry_arg(args, 0)?.map(|(geometry, _, _)| length(&geometry)).into())
+}
+
+fn centroid_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    let Some((geometry, _, srs_id)) = geometry_arg(args, 0)? else {
+        return Ok(Value::Null);
+    };
+    Ok(to_geo(&geometry)
+        .centroid()
+        .map(|p| gpb::encode(&Geometry::Point(Some(Coord { x: p.x(), y: p.y(), ..Coord::default() })), Dims::XY, srs_id))
+        .into())
+}
+
+fn buffer_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    let Some((geometry, _, srs_id)) = geometry_arg(args, 0)? else {
+        return Ok(Value::Null);
+    };
+    let distance = args.opt_double(1).ok_or_else(|| Error::new("ST_Buffer: distance must not be NULL"))?;
+    let buffered = to_geo(&geometry).buffer(distance);
+    let mut polygons: Vec<_> = buffered.0.iter().map(from_geo_polygon).collect();
+    let result = if polygons.len() == 1 {
+        Geometry::Polygon(polygons.remove(0))
+    } else {
+        Geometry::MultiPolygon(polygons)
+    };
+    Ok(gpb::encode(&result, Dims::XY, srs_id).into())
+}
+
+fn simplify_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    let Some((geometry, dims, srs_id)) = geometry_arg(args, 0)? else {
+        return Ok(Value::Null);
+    };
+    let tolerance = args.opt_double(1).ok_or_else(|| Error::new("ST_Simplify: tolerance must not be NULL"))?;
+    if tolerance < 0.0 {
+        return Err(Error::new("ST_Simplify: tolerance must not be negative"));
+    }
+    Ok(gpb::encode(&simplify(&geometry, tolerance), dims, srs_id).into())
+}
+
+fn snap_to_grid_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    let Some((geometry, dims, srs_id)) = geometry_arg(args, 0)? else {
+        return Ok(Value::Null);
+    };
+    let size = args.opt_double(1).ok_or_else(|| Error::new("ST_SnapToGrid: size must not be NULL"))?;
+    if size < 0.0 {
+        return Err(Error::new("ST_SnapToGrid: size must not be negative"));
+    }
+    Ok(gpb::encode(&snap_to_grid(&geometry, size), dims, srs_id).into())
+}
+
+/// A polygon of the box `[min_x, min_y, max_x, max_y]`.
+fn box_polygon([min_x, min_y, max_x, max_y]: [f64; 4]) -> Geometry {
+    let corner = |x, y| Coord { x, y, ..Coord::default() };
+    Geometry::Polygon(vec![vec![
+        corner(min_x, min_y),
+        corner(max_x, min_y),
+        corner(max_x, max_y),
+        corner(min_x, max_y),
+        corner(min_x, min_y),
+    ]])
+}
+
+fn geohash_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    let Some((geometry, dims, _)) = geometry_arg(args, 0)? else {
+        return Ok(Value::Null);
+    };
+    let Some(envelope) = geometry.envelope(dims) else {
+        return Ok(Value::Null);
+    };
+    let precision = match args.opt_int(1) {
+        Some(p) if (1..=geohash::MAX_PRECISION as i64).contains(&p) => Some(p as usize),
+        Some(p) => {
+            return Err(Error::new(format!("ST_GeoHash: precision must be 1 to {}, not {p}", geohash::MAX_PRECISION)));
+        }
+        None => None,
+    };
+    if envelope.min_x < -180.0 || envelope.max_x > 180.0 || envelope.min_y < -90.0 || envelope.max_y > 90.0 {
+        return Err(Error::new("ST_GeoHash: coordinates must be longitudes and latitudes within ±180/±90"));
+    }
+    let (lon, lat) = ((envelope.min_x + envelope.max_x) / 2.0, (envelope.min_y + envelope.max_y) / 2.0);
+    let hash = match precision {
+        Some(precision) => geohash::encode(lon, lat, precision),
+        None => {
+            // The common prefix of the corners is the smallest cell holding both.
+            let min = geohash::encode(envelope.min_x, envelope.min_y, geohash::MAX_PRECISION);
+            let max = geohash::encode(envelope.max_x, envelope.max_y, geohash::MAX_PRECISION);
+            min.chars().zip(max.chars()).take_while(|(a, b)| a == b).map(|(a, _)| a).collect()
+        }
+    };
+    if hash.is_empty() {
+        return Ok(Value::Null);
+    }
+    Ok(hash.into())
+}
+
+fn point_from_geohash_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    let Some(hash) = args.opt_text(0) else {
+        return Ok(Value::Null);
+    };
+    let [min_x, min_y, max_x, max_y] = geohash::decode(&hash)?;
+    let centre = Coord { x: (min_x + max_x) / 2.0, y: (min_y + max_y) / 2.0, ..Coord::default() };
+    Ok(gpb::encode(&Geometry::Point(Some(centre)), Dims::XY, 4326).into())
+}
+
+fn geom_from_geohash_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    let Some(hash) = args.opt_text(0) else {
+        return Ok(Value::Null);
+    };
+    Ok(gpb::encode(&box_polygon(geohash::decode(&hash)?), Dims::XY, 4326).into())
+}
+
+/// The number of tiles per side at `zoom`.
+fn tiles_per_side(args: &Args, i: usize) -> Result<f64> {
+    let zoom = args.int(i)?;
+    if !(0..=30).contains(&zoom) {
+        return Err(Error::new(format!("zoom level must be 0 to 30, not {zoom}")));
+    }
+    Ok((1u64 << zoom) as f64)
+}
+
+fn tile_x_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    let n = tiles_per_side(args, 1)?;
+    Ok(args.opt_double(0).map(|lon| (((lon + 180.0) / 360.0 * n).floor()).clamp(0.0, n - 1.0) as i64).into())
+}
+
+fn tile_y_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    let n = tiles_per_side(args, 1)?;
+    Ok(args
+        .opt_double(0)
+        .map(|lat| {
+            let lat = lat.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+            let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0 * n;
+            y.floor().clamp(0.0, n - 1.0) as i64
+        })
+        .into())
+}
+
+fn tile_envelope_fn(_ctx: &Context, args: &Args) -> Result<Value> {
+    let n = tiles_per_side(args, 0)?;
+    let (x, y) = (args.int(1)?, args.int(2)?);
+    if !(0.0..n).contains(&(x as f64)) || !(0.0..n).contains(&(y as f64)) {
+        return Err(Error::new(format!("tile {x}/{y} is outside zoom level {}", n.log2())));
+    }
+    let size = 2.0 * WEB_MERCATOR_EXTENT / n;
+    let (min_x, max_y) = (-WEB_MERCATOR_EXTENT + x as f64 * size, WEB_MERCATOR_EXTENT - y as f64 * size);
+    let mut bounds = [min_x, max_y - size, min_x + size, max_y];
+    let srs_id = args.opt_int(3).unwrap_or(3857);
+    match srs_id {
+        3857 => {}
+        4326 => {
+            let lat = |y: f64| (2.0 * (y / WEB_MERCATOR_EXTENT * PI).exp().atan() - PI / 2.0).to_degrees();
+            let lon = |x: f64| x / WEB_MERCATOR_EXTENT * 180.0;
+            bounds = [lon(bounds[0]), lat(bounds[1]), lon(bounds[2]), lat(bounds[3])];
+        }
+        _ => return Err(Error::new(format!("ST_TileEnvelope: srs_id must be 3857 or 4326, not {srs_id}"))),
+    }
+    Ok(gpb::encode(&box_polygon(bounds), Dims::XY, srs_id as i32).into())
+}
+
+fn transform_fn(ctx: &Context, args: &Args) -> Result<Value> {
+    let Some((mut geometry, dims, from)) = geometry_arg(args, 0)? else {
+        return Ok(Value::Null);
+    };
+    let to = args.int(1)?;
+    let to = i32::try_from(to).map_err(|_| Error::new(format!("invalid srs_id {to}")))?;
+    if from != to {
+        Transformer::new(&ctx.connection()?, from, to)?.apply(&mut geometry, dims)?;
+    }
+    Ok(gpb::encode(&geometry, dims, to).into())
+}
+
+/// Registers the spatial SQL functions on `conn`.
+pub fn register(conn: &Connection) -> Result<()> {
+    let pure = ffi::SQLITE_DETERMINISTIC | ffi::SQLITE_INNOCUOUS;
+    let functions: [function::Definition; 23] = [
+        ("ST_GeomFromText", 1, pure, geom_from_text_fn),
+        ("ST_GeomFromText", 2, pure, geom_from_text_fn),
+        ("ST_GeomFromWKB", 1, pure, geom_from_wkb_fn),
+        ("ST_GeomFromWKB", 2, pure, geom_from_wkb_fn),
+        ("ST_AsText", 1, pure, as_text_fn),
+        ("ST_AsBinary", 1, pure, as_binary_fn),
+        ("ST_AsEWKB", 1, pure, as_ewkb_fn),
+        ("ST_AsGeoJSON", 1, pure, as_geojson_fn),
+        ("ST_Area", 1, pure, area_fn),
+        ("ST_Length", 1, pure, length_fn),
+        ("ST_Centroid", 1, pure, centroid_fn),
+        ("ST_Buffer", 2, pure, buffer_fn),
+        ("ST_Simplify", 2, pure, simplify_fn),
+        ("ST_SnapToGrid", 2, pure, snap_to_grid_fn),
+        ("ST_GeoHash", 1, pure, geohash_fn),
+        ("ST_GeoHash", 2, pure, geohash_fn),
+        ("ST_PointFromGeoHash", 1, pure, point_from_geohash_fn),
+        ("ST_GeomFromGeoHash", 1, pure, geom_from_geohash_fn),
+        ("ST_TileX", 2, pure, tile_x_fn),
+        ("ST_TileY", 2, pure, tile_y_fn),
+        ("ST_TileEnvelope", 3, pure, tile_envelope_fn),
+        ("ST_TileEnvelope", 4, pure, tile_envelope_fn),
+        // Reads gpkg_spatial_ref_sys, so the result depends on the database.
+        ("ST_Transform", 2, ffi::SQLITE_INNOCUOUS, transform_fn),
+    ];
+    function::register(conn, &functions)
+}
@@ -0,0 +1,310 @@
+//! Minimal XML reader for the import functions, and escaping for writers.
+//!
+//! Namespace prefixes are dropped from element and attribute names; the
+//! formats read here (GPX) never mix vocabularies that share local names.
+use crate::error::{Error, Result};
+
+/// Deepest element nesting accepted; the parser recurses once per level.
+const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    /// Local name, without namespace prefix.
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    /// Concatenated character data directly inside the element.
+    pub text: String,
+}
+
+impl Element {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// The first child element called `name`.
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    /// The child elements called `name`.
+    pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    /// The trimmed text of the child `name`, if present and not blank.
+    pub fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|c| c.text.trim()).filter(|t| !t.is_empty())
+    }
+}
+
+/// Parses a document and returns its root element.
+pub fn parse(text: &str) -> Result<Element> {
+    let mut parser = Parser { text, pos: 0 };
+    parser.skip_misc()?;
+    let root = parser.element(0)?;
+    parser.skip_misc()?;
+    if parser.pos < text.len() {
+        return Err(parser.error("content after the root element"));
+    }
+    Ok(root)
+}
+
+/// Escapes `&`, `<`, `>` and quotes for text and attribute values.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn local_name(name: &str) -> String {
+    name.rsplit(':').next().unwrap_or(name).to_string()
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> Error {
+        let line = self.text[..self.pos].matches('\n').count() + 1;
+        Error::new(format!("invalid XML on line {line}: {message}"))
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skips `end`-terminated markup such as comments.
+    fn skip_past(&mut self, end: &str) -> Result<()> {
+        match self.rest().find(end) {
+            Some(i) => {
+                self.pos += i + end.len();
+                Ok(())
+            }
+            None => Err(self.error(&format!("missing {end}"))),
+        }
+    }
+
+    /// Skips whitespace, the XML declaration, processing instructions,
+    /// comments and the document type declaration.
+    fn skip_misc(&mut self) -> Result<()> {
+        if self.pos == 0 && self.rest().starts_with('\u{feff}') {
+            self.pos += '\u{feff}'.len_utf8();
+        }
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<!DOCTYPE") {
+                self.skip_past(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<&'a str> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '/' | '>' | '=' | '<'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn element(&mut self, depth: usize) -> Result<Element> {
+        if !self.rest().starts_with('<') {
+            return Err(self.error("expected an element"));
+        }
+        if depth > MAX_DEPTH {
+            return Err(self.error("elements nested too deep"));
+        }
+        self.pos += 1;
+        let qualified = self.name()?.to_string();
+        let mut element =
+            Element { name: local_name(&qualified), attributes: Vec::new(), children: Vec::new(), text: String::new() };
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("/>") {
+                self.pos += 2;
+                return Ok(element);
+            }
+            if rest.starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            let name = local_name(self.name()?);
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(self.error("expected = after attribute name"));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let Some(quote) = self.rest().chars().next().filter(|c| matches!(c, '"' | '\'')) else {
+                return Err(self.error("expected a quoted attribute value"));
+            };
+            self.pos += 1;
+            let Some(len) = self.rest().find(quote) else {
+                return Err(self.error("unterminated attribute value"));
+            };
+            let value = self.unescape(&self.text[self.pos..self.pos + len])?;
+            self.pos += len + 1;
+            element.attributes.push((name, value));
+        }
+
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("</") {
+                self.pos += 2;
+                if self.name()? != qualified {
+                    return Err(self.error(&format!("expected </{qualified}>")));
+                }
+                self.skip_whitespace();
+                if !self.rest().starts_with('>') {
+                    return Err(self.error("expected >"));
+                }
+                self.pos += 1;
+                return Ok(element);
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if let Some(data) = rest.strip_prefix("<![CDATA[") {
+                let Some(len) = data.find("]]>") else {
+                    return Err(self.error("unterminated CDATA section"));
+                };
+                element.text.push_str(&data[..len]);
+                self.pos += "<![CDATA[".len() + len + 3;
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with('<') {
+                element.children.push(self.element(depth + 1)?);
+            } else if rest.is_empty() {
+                return Err(self.error(&format!("missing </{qualified}>")));
+            } else {
+                let len = rest.find('<').unwrap_or(rest.len());
+                let text = self.unescape(&rest[..len])?;
+                element.text.push_str(&text);
+                self.pos += len;
+            }
+        }
+    }
+
+    fn unescape(&self, raw: &str) -> Result<String> {
+        let mut out = String::with_capacity(raw.len());
+        let mut rest = raw;
+        while let Some(i) = rest.find('&') {
+            out.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+            let Some(end) = rest.find(';') else {
+                return Err(self.error("unterminated entity reference"));
+            };
+            let c = match &rest[..end] {
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "amp" => Some('&'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                entity => match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                    None => entity.strip_prefix('#').and_then(|d| d.parse().ok()).and_then(char::from_u32),
+                },
+            };
+            let Some(c) = c else {
+                return Err(self.error(&format!("unknown entity &{};", &rest[..end])));
+            };
+            out.push(c);
+            rest = &rest[end + 1..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn document() {
+        let root = parse(
+            "\u{feff}<?xml version=\"1.0\"?>\n<!-- a GPX file -->\n<!DOCTYPE gpx>\n\
+             <gpx:gpx version='1.1' xmlns:gpx=\"http://www.topografix.com/GPX/1/1\">\n\
+             <wpt lat=\"1.5\" lon=\"-2\"><name> Café &amp; <![CDATA[<b>bar</b>]]></name><!-- x --></wpt>\n\
+             <wpt lat=\"3\" lon=\"4\"/>\n\
+             </gpx:gpx>\n<!-- trailer -->",
+        )
+        .unwrap();
+        assert_eq!(root.name, "gpx");
+        assert_eq!(root.attribute("version"), Some("1.1"));
+        assert_eq!(root.attribute("gpx"), Some("http://www.topografix.com/GPX/1/1"));
+        assert_eq!(root.children("wpt").count(), 2);
+        let wpt = root.child("wpt").unwrap();
+        assert_eq!((wpt.attribute("lat"), wpt.attribute("lon")), (Some("1.5"), Some("-2")));
+        assert_eq!(wpt.child_text("name"), Some("Café & <b>bar</b>"));
+        assert_eq!(root.children("wpt").nth(1).unwrap().children, Vec::new());
+        assert_eq!(root.child_text("wpt"), None);
+    }
+
+    #[test]
+    fn entities() {
+        let root = parse("<a v=\"&lt;&gt;&quot;&apos;\">&#233;&#xE9;&#X4e2d;</a>").unwrap();
+        assert_eq!(root.attribute("v"), Some("<>\"'"));
+        assert_eq!(root.text, "éé中");
+        assert_eq!(escape("<a & 'b'> \"c\""), "&lt;a &amp; &apos;b&apos;&gt; &quot;c&quot;");
+        assert_eq!(parse(&format!("<a>{}</a>", escape("x<&>\"'y"))).unwrap().text, "x<&>\"'y");
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        for text in [
+            "",
+            "text",
+            "<a>",
+            "<a></b>",
+            "<a></a><b/>",
+            "<a b></a>",
+            "<a b=c></a>",
+            "<a b=\"c></a>",
+            "<a>&nbsp;</a>",
+            "<a>&amp</a>",
+            "<a>&#xD800;</a>",
+            "<a><![CDATA[x</a>",
+            "<!-- open <a/>",
+            "<a",
+        ] {
+            assert!(parse(text).is_err(), "{text}");
+        }
+        let error = parse("<a>\n<b>\n</a>").unwrap_err().to_string();
+        assert!(error.contains("line 3"), "{error}");
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |levels: usize| format!("{}{}", "<a>".repeat(levels), "</a>".repeat(levels));
+        assert!(parse(&nested(MAX_DEPTH + 1)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 2)).is_err());
+        assert!(parse(&"<a>".repeat(1_000_000)).is_err());
+    }
+}